@@ -0,0 +1,126 @@
+use std::fmt;
+use redis::{ErrorKind, RedisError};
+
+/// # BtcmData Error
+///
+/// The `BtcmDataError` enum is the single error type returned by every public
+/// function of this crate, whatever storage backend is behind it.
+///
+/// * `Connection` - the backend could not be reached, or the connection was dropped.
+/// * `Protocol` - the backend answered with an error or an unexpected reply.
+/// * `TypeMismatch` - the stored value has another type than expected (e.g. `WRONGTYPE`).
+/// * `NotFound` - the requested key or record does not exist.
+/// * `Conflict` - the operation conflicts with the current state of the data.
+/// * `Blocked` - one of the users involved has blocked the other.
+/// * `Duplicate` - a value that must be unique is already used (e.g. a taken username).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BtcmDataError {
+    Connection(String),
+    Protocol(String),
+    TypeMismatch(String),
+    NotFound(String),
+    Conflict(String),
+    Blocked(String),
+    Duplicate(String),
+}
+
+impl fmt::Display for BtcmDataError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BtcmDataError::Connection(msg) => write!(f, "connection error: {}", msg),
+            BtcmDataError::Protocol(msg) => write!(f, "protocol error: {}", msg),
+            BtcmDataError::TypeMismatch(msg) => write!(f, "type mismatch: {}", msg),
+            BtcmDataError::NotFound(msg) => write!(f, "not found: {}", msg),
+            BtcmDataError::Conflict(msg) => write!(f, "conflict: {}", msg),
+            BtcmDataError::Blocked(msg) => write!(f, "blocked: {}", msg),
+            BtcmDataError::Duplicate(msg) => write!(f, "duplicate: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for BtcmDataError {}
+
+impl From<RedisError> for BtcmDataError {
+    fn from(err: RedisError) -> Self {
+        // 连接类错误(IO错误、连接被拒绝、连接断开、超时)统一归为Connection
+        if err.is_io_error() || err.is_connection_refusal() || err.is_connection_dropped() || err.is_timeout() {
+            return BtcmDataError::Connection(err.to_string());
+        }
+        match err.kind() {
+            ErrorKind::TypeError => BtcmDataError::TypeMismatch(err.to_string()),
+            ErrorKind::ExtensionError if err.code() == Some("WRONGTYPE") => {
+                BtcmDataError::TypeMismatch(err.to_string())
+            }
+            _ => BtcmDataError::Protocol(err.to_string()),
+        }
+    }
+}
+
+impl From<rusqlite::Error> for BtcmDataError {
+    fn from(err: rusqlite::Error) -> Self {
+        match err {
+            rusqlite::Error::QueryReturnedNoRows => BtcmDataError::NotFound(err.to_string()),
+            rusqlite::Error::InvalidColumnType(..)
+            | rusqlite::Error::FromSqlConversionFailure(..)
+            | rusqlite::Error::IntegralValueOutOfRange(..) => BtcmDataError::TypeMismatch(err.to_string()),
+            rusqlite::Error::SqliteFailure(ref e, _) => match e.code {
+                rusqlite::ErrorCode::ConstraintViolation => BtcmDataError::Conflict(err.to_string()),
+                rusqlite::ErrorCode::CannotOpen
+                | rusqlite::ErrorCode::DatabaseBusy
+                | rusqlite::ErrorCode::DatabaseLocked => BtcmDataError::Connection(err.to_string()),
+                _ => BtcmDataError::Protocol(err.to_string()),
+            },
+            _ => BtcmDataError::Protocol(err.to_string()),
+        }
+    }
+}
+
+impl From<tokio_postgres::Error> for BtcmDataError {
+    fn from(err: tokio_postgres::Error) -> Self {
+        use tokio_postgres::error::SqlState;
+
+        if err.is_closed() {
+            return BtcmDataError::Connection(err.to_string());
+        }
+        match err.code() {
+            // 外键约束失败表示引用的用户、群组或设备不存在
+            Some(code) if *code == SqlState::FOREIGN_KEY_VIOLATION => BtcmDataError::NotFound(err.to_string()),
+            // 用户唯一字段索引冲突表示用户名、手机号或邮箱已被其他用户使用
+            Some(code) if *code == SqlState::UNIQUE_VIOLATION && is_user_unique_violation(&err) => {
+                BtcmDataError::Duplicate(err.to_string())
+            }
+            Some(code) if *code == SqlState::UNIQUE_VIOLATION || *code == SqlState::T_R_SERIALIZATION_FAILURE => {
+                BtcmDataError::Conflict(err.to_string())
+            }
+            Some(code) if *code == SqlState::DATATYPE_MISMATCH => BtcmDataError::TypeMismatch(err.to_string()),
+            Some(_) => BtcmDataError::Protocol(err.to_string()),
+            // 没有SQLSTATE的错误来自客户端:IO错误归为Connection，其余为类型转换等错误
+            None if std::error::Error::source(&err).is_some_and(|e| e.is::<std::io::Error>()) => {
+                BtcmDataError::Connection(err.to_string())
+            }
+            None => BtcmDataError::Protocol(err.to_string()),
+        }
+    }
+}
+
+/// 判断唯一约束冲突是否来自PostgreSQL中用户唯一字段的索引
+fn is_user_unique_violation(err: &tokio_postgres::Error) -> bool {
+    err.as_db_error().and_then(|e| e.constraint()) == Some(crate::postgresql::USER_UNIQUE_INDEX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redis_error_mapping() {
+        let err = RedisError::from((ErrorKind::TypeError, "Response was of incompatible type"));
+        assert!(matches!(BtcmDataError::from(err), BtcmDataError::TypeMismatch(_)));
+
+        let err = RedisError::from(std::io::Error::new(std::io::ErrorKind::BrokenPipe, "broken pipe"));
+        assert!(matches!(BtcmDataError::from(err), BtcmDataError::Connection(_)));
+
+        let err = RedisError::from((ErrorKind::ResponseError, "An error was signalled by the server"));
+        assert!(matches!(BtcmDataError::from(err), BtcmDataError::Protocol(_)));
+    }
+}
@@ -1,6 +1,19 @@
 pub mod sqlite;
 pub mod postgresql;
 pub mod redis;
+pub mod error;
+pub mod store;
+pub mod model;
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// 当前时间(Unix毫秒)，用于创建时间、发送时间、过期时间等以毫秒表示的字段
+pub(crate) fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
 
 
 
@@ -0,0 +1,575 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::str::FromStr;
+use btcmbase::client::ClientID;
+use getset::{Getters, MutGetters, Setters};
+use serde::{Deserialize, Serialize};
+use crate::error::BtcmDataError;
+
+/// # Conversation
+///
+/// A conversation messages are ordered in: either the one-to-one conversation between
+/// two users or a group conversation.
+///
+/// The two ids of a user conversation are stored in ascending order, so
+/// `Conversation::user(a, b) == Conversation::user(b, a)`.
+///
+/// The string form (`user:<min>:<max>` or `group:<id>`) is used as key suffix and hash
+/// field, and parses back with `FromStr`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Conversation {
+    User(u64, u64),
+    Group(u64),
+}
+
+impl Conversation {
+    /// Returns the one-to-one conversation between two users.
+    pub fn user(a: ClientID, b: ClientID) -> Self {
+        let (a, b): (u64, u64) = (a.into(), b.into());
+        Conversation::User(a.min(b), a.max(b))
+    }
+
+    /// Returns the conversation of a group.
+    pub fn group(group: ClientID) -> Self {
+        Conversation::Group(group.into())
+    }
+}
+
+impl fmt::Display for Conversation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Conversation::User(a, b) => write!(f, "user:{}:{}", a, b),
+            Conversation::Group(g) => write!(f, "group:{}", g),
+        }
+    }
+}
+
+impl FromStr for Conversation {
+    type Err = BtcmDataError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || BtcmDataError::TypeMismatch(format!("invalid conversation: {}", s));
+        let parts: Vec<&str> = s.split(':').collect();
+        match parts.as_slice() {
+            ["user", a, b] => {
+                let (a, b): (u64, u64) = (a.parse().map_err(|_| invalid())?, b.parse().map_err(|_| invalid())?);
+                Ok(Conversation::User(a.min(b), a.max(b)))
+            }
+            ["group", g] => Ok(Conversation::Group(g.parse().map_err(|_| invalid())?)),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// # Group Info
+///
+/// The profile of a group, stored next to its membership set. `owner` is the
+/// `ClientID` of the group owner and `created_at` is in Unix milliseconds.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Getters, Setters)]
+#[getset(get = "pub", set = "pub")]
+pub struct GroupInfo {
+    name: String,
+    owner: u64,
+    avatar: Option<String>,
+    description: Option<String>,
+    created_at: u64,
+}
+
+impl GroupInfo {
+    /// Creates the profile of a new group, created now.
+    pub fn new(name: &str, owner: ClientID) -> Self {
+        GroupInfo {
+            name: name.to_string(),
+            owner: owner.into(),
+            avatar: None,
+            description: None,
+            created_at: crate::now_millis(),
+        }
+    }
+
+    /// Converts the profile into hash fields, unset optional fields are left out.
+    pub(crate) fn to_fields(&self) -> Vec<(&'static str, String)> {
+        let mut fields = vec![
+            ("name", self.name.clone()),
+            ("owner", self.owner.to_string()),
+            ("created_at", self.created_at.to_string()),
+        ];
+        if let Some(avatar) = &self.avatar {
+            fields.push(("avatar", avatar.clone()));
+        }
+        if let Some(description) = &self.description {
+            fields.push(("description", description.clone()));
+        }
+        fields
+    }
+
+    /// Parses the profile from hash fields.
+    pub(crate) fn from_fields(mut hm: HashMap<String, String>) -> Result<Self, BtcmDataError> {
+        Ok(GroupInfo {
+            name: hm.remove("name").unwrap_or_default(),
+            owner: parse_field(&hm, "owner")?,
+            avatar: hm.remove("avatar"),
+            description: hm.remove("description"),
+            created_at: parse_field(&hm, "created_at")?,
+        })
+    }
+}
+
+/// # Group Role
+///
+/// The role of a member in a group. A group has exactly one owner, any number of
+/// admins and ordinary members.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GroupRole {
+    Owner,
+    Admin,
+    Member,
+}
+
+/// # Unique Field
+///
+/// A field of `User` whose value identifies at most one user. The stores keep an index
+/// from each value to its user, used for login and to find users by phone or email.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum UniqueField {
+    Username,
+    Phone,
+    Email,
+}
+
+impl UniqueField {
+    /// All unique fields.
+    pub const ALL: [UniqueField; 3] = [UniqueField::Username, UniqueField::Phone, UniqueField::Email];
+
+    /// The name of the field in the stored profile.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UniqueField::Username => "username",
+            UniqueField::Phone => "phone",
+            UniqueField::Email => "email",
+        }
+    }
+
+    /// The unique field with the given name, `None` for any other field.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|field| field.as_str() == name)
+    }
+}
+
+impl fmt::Display for UniqueField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// # User
+///
+/// The profile of a user, stored as the `users:<id>` hash in Redis and as field/value
+/// rows in the SQL backends. Every known field maps to one hash field of the same name;
+/// any other field found in storage is kept in `extra`, so profiles written by newer or
+/// older code round-trip without losing data. Entries of `extra` named like a known
+/// field are ignored by `to_fields`. `created_at` is in Unix milliseconds.
+///
+/// `username`, `phone` and `email` are unique across users (see `UniqueField`): a value
+/// already used by another user is refused with `BtcmDataError::Duplicate`. Empty values
+/// are not indexed.
+///
+/// `version` is maintained by the store: every write of the profile increments it, and
+/// `UserStore::update_user` only succeeds if it still matches the stored one. Callers
+/// can only read it: it is not written by `to_fields`, not even from `extra`, and cannot
+/// be set through the partial field updates.
+///
+/// With serde the extension fields are flattened next to the known ones.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Getters, Setters, MutGetters, Serialize, Deserialize)]
+#[getset(get = "pub", set = "pub")]
+#[serde(default)]
+pub struct User {
+    nickname: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    username: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    phone: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    email: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    avatar: Option<String>,
+    created_at: u64,
+    #[getset(set = "pub(crate)")]
+    version: u64,
+    #[getset(get_mut = "pub")]
+    #[serde(flatten)]
+    extra: HashMap<String, String>,
+}
+
+impl User {
+    /// The hash field holding the version.
+    pub(crate) const VERSION_FIELD: &'static str = "version";
+
+    /// The hash fields of the typed members, never taken from `extra`.
+    const FIELDS: &'static [&'static str] = &["nickname", "username", "phone", "email", "avatar", "created_at", Self::VERSION_FIELD];
+
+    /// Creates the profile of a new user, created now.
+    pub fn new(nickname: &str) -> Self {
+        User { nickname: nickname.to_string(), created_at: crate::now_millis(), ..Default::default() }
+    }
+
+    /// Converts the profile into hash fields, unset optional fields are left out.
+    pub fn to_fields(&self) -> Vec<(String, String)> {
+        let mut fields = vec![
+            ("nickname".to_string(), self.nickname.clone()),
+            ("created_at".to_string(), self.created_at.to_string()),
+        ];
+        push_optional(&mut fields, "username", &self.username);
+        push_optional(&mut fields, "phone", &self.phone);
+        push_optional(&mut fields, "email", &self.email);
+        push_optional(&mut fields, "avatar", &self.avatar);
+        push_extra(&mut fields, &self.extra, Self::FIELDS);
+        fields
+    }
+
+    /// Checks that a partial update keeps the profile parseable by `from_fields` and
+    /// leaves the version to the store.
+    pub(crate) fn check_fields(fields: &HashMap<String, String>) -> Result<(), BtcmDataError> {
+        Self::check_field_names(fields.keys().map(String::as_str))?;
+        check_numeric_fields(fields, &["created_at"])
+    }
+
+    /// Checks that the fields can be written or deleted by the caller.
+    pub(crate) fn check_field_names<'a>(mut fields: impl Iterator<Item = &'a str>) -> Result<(), BtcmDataError> {
+        if fields.any(|field| field == Self::VERSION_FIELD) {
+            return Err(BtcmDataError::Conflict("field version is maintained by the store".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Parses the profile from hash fields, unknown fields go to `extra`.
+    pub fn from_fields(mut hm: HashMap<String, String>) -> Result<Self, BtcmDataError> {
+        let created_at = parse_field(&hm, "created_at")?;
+        let version = parse_field(&hm, Self::VERSION_FIELD)?;
+        hm.remove("created_at");
+        hm.remove(Self::VERSION_FIELD);
+        Ok(User {
+            nickname: hm.remove("nickname").unwrap_or_default(),
+            username: hm.remove("username"),
+            phone: hm.remove("phone"),
+            email: hm.remove("email"),
+            avatar: hm.remove("avatar"),
+            created_at,
+            version,
+            extra: hm,
+        })
+    }
+}
+
+/// # Deleted User
+///
+/// A soft-deleted user, see `UserStore::remove_user`: the archived profile, when it was
+/// deleted (Unix milliseconds) and the reason given for the deletion, if any.
+#[derive(Debug, Clone, PartialEq, Eq, Getters)]
+#[getset(get = "pub")]
+pub struct DeletedUser {
+    client_id: u64,
+    user: User,
+    deleted_at: u64,
+    reason: Option<String>,
+}
+
+impl DeletedUser {
+    pub(crate) fn new(client_id: u64, user: User, deleted_at: u64, reason: Option<String>) -> Self {
+        DeletedUser { client_id, user, deleted_at, reason }
+    }
+}
+
+/// # Account Deletion Report
+///
+/// What `AccountStore::delete_account` touched besides soft deleting the profile.
+/// Backends that do not keep a piece of data (e.g. group owners outside Redis) leave the
+/// corresponding field empty.
+///
+/// * `user_removed` - the profile was soft deleted by this call, false if it already was.
+/// * `contacts` - users whose contact set the account was removed from.
+/// * `groups` - groups the account left, including the transferred ones.
+/// * `transferred_groups` - groups the account owned, with the member that became owner.
+/// * `removed_groups` - groups the account owned that were removed, having no other member.
+/// * `devices` - devices removed together with their inbox, outbox and sync cursors.
+/// * `friend_requests` - the number of friend requests from or to the account that were deleted.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Getters)]
+#[getset(get = "pub")]
+pub struct AccountDeletionReport {
+    pub(crate) user_removed: bool,
+    pub(crate) contacts: HashSet<u64>,
+    pub(crate) groups: HashSet<u64>,
+    pub(crate) transferred_groups: HashMap<u64, u64>,
+    pub(crate) removed_groups: HashSet<u64>,
+    pub(crate) devices: HashSet<u32>,
+    pub(crate) friend_requests: usize,
+}
+
+/// # Friend Request State
+///
+/// The state of a friend request. Only a `Pending` request changes state: the recipient
+/// accepts or rejects it, the sender cancels it, or it expires.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FriendRequestState {
+    Pending,
+    Accepted,
+    Rejected,
+    Cancelled,
+    Expired,
+}
+
+impl FriendRequestState {
+    /// The state as stored in the request hash.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FriendRequestState::Pending => "pending",
+            FriendRequestState::Accepted => "accepted",
+            FriendRequestState::Rejected => "rejected",
+            FriendRequestState::Cancelled => "cancelled",
+            FriendRequestState::Expired => "expired",
+        }
+    }
+}
+
+impl fmt::Display for FriendRequestState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for FriendRequestState {
+    type Err = BtcmDataError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pending" => Ok(FriendRequestState::Pending),
+            "accepted" => Ok(FriendRequestState::Accepted),
+            "rejected" => Ok(FriendRequestState::Rejected),
+            "cancelled" => Ok(FriendRequestState::Cancelled),
+            "expired" => Ok(FriendRequestState::Expired),
+            _ => Err(BtcmDataError::TypeMismatch(format!("invalid friend request state: {}", s))),
+        }
+    }
+}
+
+/// # Friend Request
+///
+/// A friend request from `from` to `to`, stored as the `friend_req:<from>:<to>` hash in
+/// Redis. `created_at`, `updated_at` (the last state change) and `expires_at` are in Unix
+/// milliseconds; a pending request past `expires_at` reads as `Expired`.
+#[derive(Debug, Clone, PartialEq, Eq, Getters)]
+#[getset(get = "pub")]
+pub struct FriendRequest {
+    pub(crate) from: u64,
+    pub(crate) to: u64,
+    pub(crate) state: FriendRequestState,
+    pub(crate) message: Option<String>,
+    pub(crate) created_at: u64,
+    pub(crate) updated_at: u64,
+    pub(crate) expires_at: u64,
+}
+
+impl FriendRequest {
+    /// Parses the request from hash fields, a pending request expired at `now` reads as `Expired`.
+    pub(crate) fn from_fields(mut hm: HashMap<String, String>, now: u64) -> Result<Self, BtcmDataError> {
+        let mut request = FriendRequest {
+            from: parse_field(&hm, "from")?,
+            to: parse_field(&hm, "to")?,
+            state: hm.get("state").map(|s| s.parse()).transpose()?.unwrap_or(FriendRequestState::Pending),
+            message: hm.remove("message"),
+            created_at: parse_field(&hm, "created_at")?,
+            updated_at: parse_field(&hm, "updated_at")?,
+            expires_at: parse_field(&hm, "expires_at")?,
+        };
+        if request.state == FriendRequestState::Pending && request.expires_at <= now {
+            request.state = FriendRequestState::Expired;
+        }
+        Ok(request)
+    }
+}
+
+/// # Device
+///
+/// The information of one of a client's devices, stored as the
+/// `client_device:<id>:<dev>` hash. `kind` is stored as the `type` field;
+/// `last_seen` is in Unix milliseconds. Unknown fields are kept in `extra` as for `User`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Getters, Setters, MutGetters, Serialize, Deserialize)]
+#[getset(get = "pub", set = "pub")]
+#[serde(default)]
+pub struct Device {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    kind: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    os: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    app_version: Option<String>,
+    last_seen: u64,
+    #[getset(get_mut = "pub")]
+    #[serde(flatten)]
+    extra: HashMap<String, String>,
+}
+
+impl Device {
+    /// The hash fields of the typed members, never taken from `extra`.
+    const FIELDS: &'static [&'static str] = &["name", "type", "os", "app_version", "last_seen"];
+
+    /// Converts the device into hash fields, unset optional fields are left out.
+    pub fn to_fields(&self) -> Vec<(String, String)> {
+        let mut fields = vec![("last_seen".to_string(), self.last_seen.to_string())];
+        push_optional(&mut fields, "name", &self.name);
+        push_optional(&mut fields, "type", &self.kind);
+        push_optional(&mut fields, "os", &self.os);
+        push_optional(&mut fields, "app_version", &self.app_version);
+        push_extra(&mut fields, &self.extra, Self::FIELDS);
+        fields
+    }
+
+    /// Checks that a partial update keeps the device parseable by `from_fields`.
+    pub(crate) fn check_fields(fields: &HashMap<String, String>) -> Result<(), BtcmDataError> {
+        check_numeric_fields(fields, &["last_seen"])
+    }
+
+    /// Parses the device from hash fields, unknown fields go to `extra`.
+    pub fn from_fields(mut hm: HashMap<String, String>) -> Result<Self, BtcmDataError> {
+        let last_seen = parse_field(&hm, "last_seen")?;
+        hm.remove("last_seen");
+        Ok(Device {
+            name: hm.remove("name"),
+            kind: hm.remove("type"),
+            os: hm.remove("os"),
+            app_version: hm.remove("app_version"),
+            last_seen,
+            extra: hm,
+        })
+    }
+}
+
+fn check_numeric_fields(fields: &HashMap<String, String>, numeric: &[&str]) -> Result<(), BtcmDataError> {
+    for field in numeric {
+        parse_field::<u64>(fields, field)?;
+    }
+    Ok(())
+}
+
+fn push_optional(fields: &mut Vec<(String, String)>, field: &str, value: &Option<String>) {
+    if let Some(value) = value {
+        fields.push((field.to_string(), value.clone()));
+    }
+}
+
+/// Appends the extension fields, except those named like a typed field, which would
+/// otherwise overwrite it in storage.
+fn push_extra(fields: &mut Vec<(String, String)>, extra: &HashMap<String, String>, reserved: &[&str]) {
+    let extra = extra.iter().filter(|(field, _)| !reserved.contains(&field.as_str()));
+    fields.extend(extra.map(|(field, value)| (field.clone(), value.clone())));
+}
+
+/// # Contact Remark
+///
+/// Private metadata a user keeps about one of their contacts: a nickname shown
+/// instead of the contact's own name, free-form notes and labels (e.g. "family",
+/// "work"). Only the owner sees it.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Getters, Setters)]
+#[getset(get = "pub", set = "pub")]
+pub struct ContactRemark {
+    nickname: Option<String>,
+    notes: Option<String>,
+    labels: BTreeSet<String>,
+}
+
+impl ContactRemark {
+    /// Adds a label, returns whether it was not set before.
+    pub fn add_label(&mut self, label: &str) -> bool {
+        self.labels.insert(label.to_string())
+    }
+
+    /// Removes a label, returns whether it was set.
+    pub fn remove_label(&mut self, label: &str) -> bool {
+        self.labels.remove(label)
+    }
+
+    /// Returns whether nothing is set.
+    pub fn is_empty(&self) -> bool {
+        self.nickname.is_none() && self.notes.is_none() && self.labels.is_empty()
+    }
+
+    /// Converts the nickname and notes into hash fields, unset fields are left out.
+    /// Labels are stored in a set of their own.
+    pub(crate) fn to_fields(&self) -> Vec<(&'static str, String)> {
+        let mut fields = Vec::new();
+        if let Some(nickname) = &self.nickname {
+            fields.push(("nickname", nickname.clone()));
+        }
+        if let Some(notes) = &self.notes {
+            fields.push(("notes", notes.clone()));
+        }
+        fields
+    }
+
+    /// Builds the remark from the hash fields and the label set.
+    pub(crate) fn from_fields(mut hm: HashMap<String, String>, labels: BTreeSet<String>) -> Self {
+        ContactRemark { nickname: hm.remove("nickname"), notes: hm.remove("notes"), labels }
+    }
+}
+
+/// Parses a numeric hash field, a missing field is 0.
+pub(crate) fn parse_field<T: FromStr + Default>(hm: &HashMap<String, String>, field: &str) -> Result<T, BtcmDataError> {
+    match hm.get(field) {
+        Some(value) => value
+            .parse()
+            .map_err(|_| BtcmDataError::TypeMismatch(format!("field {} has invalid value {}", field, value))),
+        None => Ok(T::default()),
+    }
+}
+
+/// Converts a stored device id to the `u32` used by the device APIs.
+pub(crate) fn device_id(id: u64) -> Result<u32, BtcmDataError> {
+    u32::try_from(id).map_err(|_| BtcmDataError::TypeMismatch(format!("device id {} does not fit in u32", id)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conversation_round_trip() {
+        let conv = Conversation::user(ClientID::from(2002), ClientID::from(1001));
+        assert_eq!(conv, Conversation::User(1001, 2002));
+        assert_eq!(conv.to_string(), "user:1001:2002");
+        assert_eq!("user:1001:2002".parse::<Conversation>().unwrap(), conv);
+        assert_eq!("group:7".parse::<Conversation>().unwrap(), Conversation::group(ClientID::from(7)));
+        assert!("room:7".parse::<Conversation>().is_err());
+    }
+
+    #[test]
+    fn user_fields_round_trip() {
+        let hm: HashMap<String, String> = [("nickname", "John"), ("email", "john@example.com"), ("created_at", "42"), ("age", "30")]
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        let user = User::from_fields(hm.clone()).unwrap();
+        assert_eq!(user.email().as_deref(), Some("john@example.com"));
+        assert_eq!(user.extra().get("age").map(String::as_str), Some("30"));
+        assert_eq!(user.to_fields().into_iter().collect::<HashMap<_, _>>(), hm);
+        assert!(User::from_fields([("created_at".to_string(), "x".to_string())].into_iter().collect()).is_err());
+
+        // extra cannot shadow a typed field
+        let mut user = user;
+        user.extra_mut().insert("nickname".to_string(), "Mallory".to_string());
+        user.extra_mut().insert(User::VERSION_FIELD.to_string(), "7".to_string());
+        assert_eq!(user.to_fields().into_iter().collect::<HashMap<_, _>>(), hm);
+    }
+
+    #[test]
+    fn pending_request_past_expiry_reads_as_expired() {
+        let hm: HashMap<String, String> = [("from", "1001"), ("to", "1002"), ("state", "pending"), ("expires_at", "100")]
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        assert_eq!(*FriendRequest::from_fields(hm.clone(), 99).unwrap().state(), FriendRequestState::Pending);
+        assert_eq!(*FriendRequest::from_fields(hm, 100).unwrap().state(), FriendRequestState::Expired);
+        assert_eq!("cancelled".parse::<FriendRequestState>().unwrap(), FriendRequestState::Cancelled);
+    }
+}
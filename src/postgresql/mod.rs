@@ -0,0 +1,1193 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+use async_trait::async_trait;
+use btcmbase::client::ClientID;
+use once_cell::sync::OnceCell;
+use tokio::sync::{Mutex, MutexGuard};
+use tokio_postgres::{Client, NoTls, Transaction};
+use crate::error::BtcmDataError;
+use crate::model::{device_id, AccountDeletionReport, DeletedUser, Device, GroupInfo, GroupRole, UniqueField, User};
+use crate::store::{AccountStore, BlockStore, ContactStore, DeviceStore, GroupStore, UserStore};
+
+/// 建表语句。PostgreSQL是账号和群组数据的最终存储，因此用外键约束用户、群组和设备之间的关系:
+/// - `users`: 账号，`deleted_at`非空表示已软删除，`deleted_reason`为删除原因
+/// - `user_fields` / `del_user_fields`: 用户信息哈希和已删除用户的信息，
+///   `user_fields_unique`保证用户名、手机号和邮箱(`UniqueField`)不重复，`user_fields_nickname_ascii`用于昵称前缀搜索，
+///   与Redis的`user_nickname_index`一致:只转换ASCII字母的大小写，按字节序("C")排序
+/// - `user_contacts` / `group_contacts`: 用户联系人和群组联系人
+/// - `blocklist`: 用户的黑名单
+/// - `groups` / `group_members` / `group_fields`: 群组、群组成员和群组信息哈希
+/// - `group_admins`: 群组管理员(不包括群主)，引用群组成员，成员移出群组时级联删除
+/// - `client_devices` / `device_fields`: 客户端设备和设备信息哈希
+static SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS users (
+    client_id  BIGINT PRIMARY KEY,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    deleted_at TIMESTAMPTZ,
+    deleted_reason TEXT
+);
+-- 升级在增加deleted_reason之前创建的数据库
+ALTER TABLE users ADD COLUMN IF NOT EXISTS deleted_reason TEXT;
+CREATE INDEX IF NOT EXISTS users_deleted_at ON users (deleted_at) WHERE deleted_at IS NOT NULL;
+CREATE TABLE IF NOT EXISTS user_fields (
+    client_id BIGINT NOT NULL REFERENCES users (client_id) ON DELETE CASCADE,
+    field     TEXT   NOT NULL,
+    value     TEXT   NOT NULL,
+    PRIMARY KEY (client_id, field)
+);
+CREATE UNIQUE INDEX IF NOT EXISTS user_fields_unique ON user_fields (field, value)
+    WHERE field IN ('username', 'phone', 'email') AND value <> '';
+CREATE INDEX IF NOT EXISTS user_fields_nickname_ascii
+    ON user_fields ((translate(value, 'ABCDEFGHIJKLMNOPQRSTUVWXYZ', 'abcdefghijklmnopqrstuvwxyz') COLLATE \"C\"))
+    WHERE field = 'nickname';
+CREATE TABLE IF NOT EXISTS del_user_fields (
+    client_id BIGINT NOT NULL REFERENCES users (client_id) ON DELETE CASCADE,
+    field     TEXT   NOT NULL,
+    value     TEXT   NOT NULL,
+    PRIMARY KEY (client_id, field)
+);
+CREATE TABLE IF NOT EXISTS user_contacts (
+    client_id  BIGINT NOT NULL REFERENCES users (client_id) ON DELETE CASCADE,
+    contact_id BIGINT NOT NULL REFERENCES users (client_id) ON DELETE CASCADE,
+    PRIMARY KEY (client_id, contact_id)
+);
+CREATE TABLE IF NOT EXISTS blocklist (
+    client_id  BIGINT NOT NULL REFERENCES users (client_id) ON DELETE CASCADE,
+    blocked_id BIGINT NOT NULL REFERENCES users (client_id) ON DELETE CASCADE,
+    PRIMARY KEY (client_id, blocked_id)
+);
+CREATE TABLE IF NOT EXISTS groups (
+    group_id   BIGINT PRIMARY KEY,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+CREATE TABLE IF NOT EXISTS group_members (
+    group_id  BIGINT NOT NULL REFERENCES groups (group_id) ON DELETE CASCADE,
+    member_id BIGINT NOT NULL REFERENCES users (client_id) ON DELETE CASCADE,
+    PRIMARY KEY (group_id, member_id)
+);
+CREATE INDEX IF NOT EXISTS group_members_member ON group_members (member_id);
+CREATE TABLE IF NOT EXISTS group_admins (
+    group_id  BIGINT NOT NULL,
+    member_id BIGINT NOT NULL,
+    PRIMARY KEY (group_id, member_id),
+    FOREIGN KEY (group_id, member_id) REFERENCES group_members (group_id, member_id) ON DELETE CASCADE
+);
+CREATE TABLE IF NOT EXISTS group_fields (
+    group_id BIGINT NOT NULL REFERENCES groups (group_id) ON DELETE CASCADE,
+    field    TEXT   NOT NULL,
+    value    TEXT   NOT NULL,
+    PRIMARY KEY (group_id, field)
+);
+CREATE TABLE IF NOT EXISTS group_contacts (
+    client_id BIGINT NOT NULL REFERENCES users (client_id) ON DELETE CASCADE,
+    group_id  BIGINT NOT NULL REFERENCES groups (group_id) ON DELETE CASCADE,
+    PRIMARY KEY (client_id, group_id)
+);
+CREATE TABLE IF NOT EXISTS client_devices (
+    client_id BIGINT NOT NULL REFERENCES users (client_id) ON DELETE CASCADE,
+    device_id BIGINT NOT NULL,
+    PRIMARY KEY (client_id, device_id)
+);
+CREATE TABLE IF NOT EXISTS device_fields (
+    client_id BIGINT NOT NULL,
+    device_id BIGINT NOT NULL,
+    field     TEXT   NOT NULL,
+    value     TEXT   NOT NULL,
+    PRIMARY KEY (client_id, device_id, field),
+    FOREIGN KEY (client_id, device_id) REFERENCES client_devices (client_id, device_id) ON DELETE CASCADE
+);
+";
+
+/// 用户唯一字段索引的名称，与SCHEMA中的一致，用于将唯一约束冲突转换为`BtcmDataError::Duplicate`
+pub(crate) static USER_UNIQUE_INDEX: &str = "user_fields_unique";
+
+/// # PostgreSQL Store
+///
+/// The `PostgresStore` struct implements the storage traits of `crate::store` on top of
+/// PostgreSQL, the durable source of truth for account and group data. Contacts, group
+/// members and devices must reference existing users (and groups), otherwise the
+/// operation fails with `BtcmDataError::NotFound`. Every multi-row change runs in a
+/// single transaction. A dropped connection is re-established on the next call.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use btcmdata::postgresql::init_postgres_database;
+/// use btcmdata::store::UserStore;
+/// use btcmbase::client::ClientID;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let url = "host=localhost user=postgres dbname=btcm";
+///     let store = init_postgres_database(url).await.expect("Failed to initialize PostgreSQL database.");
+///     let user = store.get_user(ClientID::from(123)).await.unwrap();
+///     println!("User info: {:?}", user);
+/// }
+/// ```
+pub struct PostgresStore {
+    url: String,
+    client: Mutex<Client>,
+}
+
+impl PostgresStore {
+    /// Connects to PostgreSQL, spawns the connection task on the tokio runtime and
+    /// creates the tables.
+    pub async fn connect(postgres_url: &str) -> Result<Self, BtcmDataError> {
+        let client = connect_client(postgres_url).await?;
+        client.batch_execute(SCHEMA).await?;
+        Ok(PostgresStore { url: postgres_url.to_string(), client: Mutex::new(client) })
+    }
+
+    /// Locks the client, reconnecting first when the connection has been closed.
+    /// A query that was running when the connection dropped still fails with
+    /// `BtcmDataError::Connection`; the next call gets a fresh connection.
+    async fn client(&self) -> Result<MutexGuard<'_, Client>, BtcmDataError> {
+        let mut client = self.client.lock().await;
+        if client.is_closed() {
+            *client = connect_client(&self.url).await?;
+        }
+        Ok(client)
+    }
+}
+
+/// 建立连接并在tokio运行时中运行连接任务，连接断开后任务结束，`Client::is_closed`返回true
+async fn connect_client(postgres_url: &str) -> Result<Client, BtcmDataError> {
+    let (client, connection) = tokio_postgres::connect(postgres_url, NoTls).await?;
+    tokio::spawn(async move {
+        let _ = connection.await;
+    });
+    Ok(client)
+}
+
+// 使用 OnceCell 包装 Singleton，确保只初始化一次
+static SINGLETON_POSTGRES_STORE: OnceCell<Arc<PostgresStore>> = OnceCell::new();
+
+/// Initializes the PostgreSQL database and returns a Result containing an Arc-wrapped `PostgresStore`.
+///
+/// # Arguments
+///
+/// * `postgres_url` - A string representing the PostgreSQL connection parameters.
+///
+/// # Returns
+///
+/// Returns a `Result` containing an `Arc<PostgresStore>` on success, or a `BtcmDataError` on failure.
+pub async fn init_postgres_database(postgres_url: &str) -> Result<Arc<PostgresStore>, BtcmDataError> {
+    if let Some(store) = SINGLETON_POSTGRES_STORE.get() {
+        return Ok(store.clone());
+    }
+    let store = PostgresStore::connect(postgres_url).await?;
+
+    // Initialize the singleton instance
+    Ok(SINGLETON_POSTGRES_STORE.get_or_init(|| Arc::new(store)).clone())
+}
+
+/// Gets the PostgreSQL store from the singleton instance.
+///
+/// # Returns
+///
+/// Returns an `Option<Arc<PostgresStore>>`. If the singleton instance exists, it returns the store;
+/// otherwise, it returns `None`.
+pub fn get_postgres_store() -> Option<Arc<PostgresStore>> {
+    SINGLETON_POSTGRES_STORE.get().cloned()
+}
+
+fn id_of(clt: ClientID) -> i64 {
+    let id: u64 = clt.into();
+    id as i64
+}
+
+/// 读取单列id的查询结果为HashSet
+async fn query_ids(client: &Client, sql: &str, id: i64) -> Result<HashSet<u64>, BtcmDataError> {
+    let rows = client.query(sql, &[&id]).await?;
+    Ok(rows.iter().map(|row| row.get::<_, i64>(0) as u64).collect())
+}
+
+/// 任意一方拉黑了另一方时返回`BtcmDataError::Blocked`
+async fn check_not_blocked(tx: &Transaction<'_>, a: ClientID, b: ClientID) -> Result<(), BtcmDataError> {
+    let row = tx
+        .query_opt(
+            "SELECT 1 FROM blocklist WHERE (client_id = $1 AND blocked_id = $2) OR (client_id = $2 AND blocked_id = $1)",
+            &[&id_of(a), &id_of(b)],
+        )
+        .await?;
+    if row.is_some() {
+        let (a, b): (u64, u64) = (a.into(), b.into());
+        return Err(BtcmDataError::Blocked(format!("contact between {} and {} is blocked", a, b)));
+    }
+    Ok(())
+}
+
+/// 与Redis一致:任意一个成员拉黑了群主时拒绝将其加入群组
+async fn check_not_blocking_owner(tx: &Transaction<'_>, clt: ClientID, owner: u64, members: &HashSet<u64>) -> Result<(), BtcmDataError> {
+    let stmt = tx.prepare("SELECT 1 FROM blocklist WHERE client_id = $1 AND blocked_id = $2").await?;
+    for member in members {
+        if tx.query_opt(&stmt, &[&(*member as i64), &(owner as i64)]).await?.is_some() {
+            let group_id: u64 = clt.into();
+            return Err(BtcmDataError::Blocked(format!("user {} has blocked the owner of group {}", member, group_id)));
+        }
+    }
+    Ok(())
+}
+
+/// 锁定并读取群组信息中的群主，群组没有群组信息时返回None
+async fn lock_group_owner(tx: &Transaction<'_>, clt: ClientID) -> Result<Option<u64>, BtcmDataError> {
+    let row = tx
+        .query_opt("SELECT value FROM group_fields WHERE group_id = $1 AND field = 'owner' FOR UPDATE", &[&id_of(clt)])
+        .await?;
+    row.map(|row| {
+        let owner: String = row.get(0);
+        owner.parse().map_err(|_| BtcmDataError::TypeMismatch(format!("field owner has invalid value {}", owner)))
+    })
+    .transpose()
+}
+
+/// 与Redis的TRANSFER_OWNER_SCRIPT一致:修改群主，新群主不再是管理员
+async fn set_group_owner(tx: &Transaction<'_>, clt: ClientID, owner: u64) -> Result<(), BtcmDataError> {
+    tx.execute("UPDATE group_fields SET value = $2 WHERE group_id = $1 AND field = 'owner'", &[&id_of(clt), &owner.to_string()])
+        .await?;
+    tx.execute("DELETE FROM group_admins WHERE group_id = $1 AND member_id = $2", &[&id_of(clt), &(owner as i64)]).await?;
+    Ok(())
+}
+
+/// 锁定未删除的用户行，用户不存在时返回`BtcmDataError::NotFound`
+async fn lock_user(tx: &Transaction<'_>, clt: ClientID) -> Result<(), BtcmDataError> {
+    let row = tx
+        .query_opt("SELECT 1 FROM users WHERE client_id = $1 AND deleted_at IS NULL FOR UPDATE", &[&id_of(clt)])
+        .await?;
+    if row.is_none() {
+        let user_id: u64 = clt.into();
+        return Err(BtcmDataError::NotFound(format!("user {}", user_id)));
+    }
+    Ok(())
+}
+
+/// 与Redis的WRITE_USER_SCRIPT一致:锁定用户行，检查期望的版本号，并将版本号加1
+async fn bump_user_version(tx: &Transaction<'_>, clt: ClientID, expected: Option<u64>) -> Result<u64, BtcmDataError> {
+    lock_user(tx, clt).await?;
+    let row = tx
+        .query_opt(
+            "SELECT value FROM user_fields WHERE client_id = $1 AND field = $2",
+            &[&id_of(clt), &User::VERSION_FIELD],
+        )
+        .await?;
+    let current: u64 = match row {
+        Some(row) => {
+            let value: String = row.get(0);
+            value
+                .parse()
+                .map_err(|_| BtcmDataError::TypeMismatch(format!("field version has invalid value {}", value)))?
+        }
+        None => 0,
+    };
+    if expected.is_some_and(|expected| expected != current) {
+        let user_id: u64 = clt.into();
+        return Err(BtcmDataError::Conflict(format!("user {} was modified concurrently", user_id)));
+    }
+    tx.execute(
+        "INSERT INTO user_fields (client_id, field, value) VALUES ($1, $2, $3)
+         ON CONFLICT (client_id, field) DO UPDATE SET value = excluded.value",
+        &[&id_of(clt), &User::VERSION_FIELD, &(current + 1).to_string()],
+    )
+    .await?;
+    Ok(current + 1)
+}
+
+/// 标记用户已删除并将用户信息移入`del_user_fields`，用户已被删除时返回false，
+/// 用户不存在时返回`BtcmDataError::NotFound`
+async fn soft_delete_user(tx: &Transaction<'_>, clt: ClientID, reason: Option<&str>) -> Result<bool, BtcmDataError> {
+    let id = id_of(clt);
+    let updated = tx
+        .execute(
+            "UPDATE users SET deleted_at = now(), deleted_reason = $2 WHERE client_id = $1 AND deleted_at IS NULL",
+            &[&id, &reason],
+        )
+        .await?;
+    if updated == 0 {
+        // 已删除的用户仍保留在users中
+        let row = tx.query_opt("SELECT 1 FROM users WHERE client_id = $1", &[&id]).await?;
+        if row.is_some() {
+            return Ok(false);
+        }
+        return Err(BtcmDataError::NotFound(format!("user {}", id as u64)));
+    }
+    tx.execute(
+        "INSERT INTO del_user_fields (client_id, field, value) SELECT client_id, field, value FROM user_fields WHERE client_id = $1",
+        &[&id],
+    )
+    .await?;
+    tx.execute("DELETE FROM user_fields WHERE client_id = $1", &[&id]).await?;
+    Ok(true)
+}
+
+/// 检查唯一字段的值没有被其他用户使用，否则返回`BtcmDataError::Duplicate`。
+/// 并发写入同一个值时由`user_fields_unique`索引兜底
+async fn check_unique_fields<'a>(
+    tx: &Transaction<'_>,
+    clt: ClientID,
+    fields: impl Iterator<Item = (&'a String, &'a String)>,
+) -> Result<(), BtcmDataError> {
+    for (field, value) in fields {
+        if UniqueField::from_name(field).is_none() || value.is_empty() {
+            continue;
+        }
+        let row = tx
+            .query_opt(
+                "SELECT 1 FROM user_fields WHERE field = $1 AND value = $2 AND client_id <> $3",
+                &[field, value, &id_of(clt)],
+            )
+            .await?;
+        if row.is_some() {
+            return Err(BtcmDataError::Duplicate(format!("{} is already used by another user", field)));
+        }
+    }
+    Ok(())
+}
+
+/// 整体替换用户信息的字段，保留版本号
+async fn replace_user_fields(tx: &Transaction<'_>, clt: ClientID, user: &User) -> Result<(), BtcmDataError> {
+    tx.execute("DELETE FROM user_fields WHERE client_id = $1 AND field <> $2", &[&id_of(clt), &User::VERSION_FIELD])
+        .await?;
+    let stmt = tx.prepare("INSERT INTO user_fields (client_id, field, value) VALUES ($1, $2, $3)").await?;
+    for (field, value) in user.to_fields() {
+        tx.execute(&stmt, &[&id_of(clt), &field, &value]).await?;
+    }
+    Ok(())
+}
+
+/// 在事务中对 (a, b) 和 (b, a) 两个方向执行同一条语句
+async fn update_pair(tx: &Transaction<'_>, sql: &str, a: i64, b: i64) -> Result<(), BtcmDataError> {
+    let stmt = tx.prepare(sql).await?;
+    tx.execute(&stmt, &[&a, &b]).await?;
+    tx.execute(&stmt, &[&b, &a]).await?;
+    Ok(())
+}
+
+/// 在事务中向 (owner, id) 形式的关系表插入或删除一批id
+async fn update_ids(tx: &Transaction<'_>, sql: &str, owner: i64, ids: &HashSet<u64>) -> Result<(), BtcmDataError> {
+    let stmt = tx.prepare(sql).await?;
+    for id in ids {
+        tx.execute(&stmt, &[&owner, &(*id as i64)]).await?;
+    }
+    Ok(())
+}
+
+#[async_trait]
+impl UserStore for PostgresStore {
+    async fn add_user(&self, clt: ClientID, user: &User) -> Result<(), BtcmDataError> {
+        let mut client = self.client().await?;
+        let tx = client.transaction().await?;
+        // 与Redis一致:重新注册已软删除的用户时清除删除标记，并丢弃其已删除的用户信息
+        tx.execute(
+            "INSERT INTO users (client_id) VALUES ($1)
+             ON CONFLICT (client_id) DO UPDATE SET deleted_at = NULL, deleted_reason = NULL",
+            &[&id_of(clt)],
+        )
+        .await?;
+        tx.execute("DELETE FROM del_user_fields WHERE client_id = $1", &[&id_of(clt)]).await?;
+        bump_user_version(&tx, clt, None).await?;
+        check_unique_fields(&tx, clt, user.to_fields().iter().map(|(field, value)| (field, value))).await?;
+        replace_user_fields(&tx, clt, user).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn update_user(&self, clt: ClientID, user: &User) -> Result<u64, BtcmDataError> {
+        let mut client = self.client().await?;
+        let tx = client.transaction().await?;
+        let version = bump_user_version(&tx, clt, Some(*user.version())).await?;
+        check_unique_fields(&tx, clt, user.to_fields().iter().map(|(field, value)| (field, value))).await?;
+        replace_user_fields(&tx, clt, user).await?;
+        tx.commit().await?;
+        Ok(version)
+    }
+
+    async fn get_user(&self, clt: ClientID) -> Result<Option<User>, BtcmDataError> {
+        let client = self.client().await?;
+        let rows = client
+            .query("SELECT field, value FROM user_fields WHERE client_id = $1", &[&id_of(clt)])
+            .await?;
+        if rows.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(User::from_fields(rows.iter().map(|row| (row.get(0), row.get(1))).collect())?))
+    }
+
+    async fn get_user_fields(&self, clt: ClientID, fields: &[&str]) -> Result<HashMap<String, String>, BtcmDataError> {
+        let client = self.client().await?;
+        let rows = client
+            .query("SELECT field, value FROM user_fields WHERE client_id = $1 AND field = ANY($2)", &[&id_of(clt), &fields])
+            .await?;
+        Ok(rows.iter().map(|row| (row.get(0), row.get(1))).collect())
+    }
+
+    async fn set_user_fields(&self, clt: ClientID, fields: &HashMap<String, String>) -> Result<(), BtcmDataError> {
+        User::check_fields(fields)?;
+        let mut client = self.client().await?;
+        let tx = client.transaction().await?;
+        bump_user_version(&tx, clt, None).await?;
+        check_unique_fields(&tx, clt, fields.iter()).await?;
+        let stmt = tx
+            .prepare(
+                "INSERT INTO user_fields (client_id, field, value) VALUES ($1, $2, $3)
+                 ON CONFLICT (client_id, field) DO UPDATE SET value = excluded.value",
+            )
+            .await?;
+        for (field, value) in fields {
+            tx.execute(&stmt, &[&id_of(clt), field, value]).await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn delete_user_fields(&self, clt: ClientID, fields: &[&str]) -> Result<usize, BtcmDataError> {
+        User::check_field_names(fields.iter().copied())?;
+        let mut client = self.client().await?;
+        let tx = client.transaction().await?;
+        lock_user(&tx, clt).await?;
+        let removed = tx
+            .execute("DELETE FROM user_fields WHERE client_id = $1 AND field = ANY($2)", &[&id_of(clt), &fields])
+            .await?;
+        if removed > 0 {
+            bump_user_version(&tx, clt, None).await?;
+        }
+        tx.commit().await?;
+        Ok(removed as usize)
+    }
+
+    async fn find_user_by(&self, field: UniqueField, value: &str) -> Result<Option<u64>, BtcmDataError> {
+        let client = self.client().await?;
+        let row = client
+            .query_opt(
+                "SELECT client_id FROM user_fields WHERE field = $1 AND value = $2
+                 AND field IN ('username', 'phone', 'email') AND value <> ''",
+                &[&field.as_str(), &value],
+            )
+            .await?;
+        Ok(row.map(|row| row.get::<_, i64>(0) as u64))
+    }
+
+    async fn search_users_by_nickname(&self, prefix: &str, offset: usize, count: usize) -> Result<Vec<u64>, BtcmDataError> {
+        let client = self.client().await?;
+        // 与Redis一致:只转换ASCII字母，按字节序排序；转义LIKE的通配符，前缀匹配可以使用user_fields_nickname_ascii索引
+        let prefix = prefix.to_ascii_lowercase();
+        let pattern = format!("{}%", prefix.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_"));
+        let rows = client
+            .query(
+                "SELECT client_id FROM user_fields WHERE field = 'nickname' AND value <> ''
+                 AND translate(value, 'ABCDEFGHIJKLMNOPQRSTUVWXYZ', 'abcdefghijklmnopqrstuvwxyz') COLLATE \"C\" LIKE $1 ESCAPE '\\'
+                 ORDER BY translate(value, 'ABCDEFGHIJKLMNOPQRSTUVWXYZ', 'abcdefghijklmnopqrstuvwxyz') COLLATE \"C\", client_id
+                 LIMIT $2 OFFSET $3",
+                &[&pattern, &(count as i64), &(offset as i64)],
+            )
+            .await?;
+        Ok(rows.iter().map(|row| row.get::<_, i64>(0) as u64).collect())
+    }
+
+    async fn exists_user(&self, clt: ClientID) -> Result<bool, BtcmDataError> {
+        let client = self.client().await?;
+        let row = client
+            .query_opt("SELECT 1 FROM users WHERE client_id = $1 AND deleted_at IS NULL", &[&id_of(clt)])
+            .await?;
+        Ok(row.is_some())
+    }
+
+    async fn remove_user(&self, clt: ClientID, reason: Option<&str>) -> Result<bool, BtcmDataError> {
+        let mut client = self.client().await?;
+        let tx = client.transaction().await?;
+        let removed = soft_delete_user(&tx, clt, reason).await?;
+        tx.commit().await?;
+        Ok(removed)
+    }
+
+    async fn restore_user(&self, clt: ClientID) -> Result<(), BtcmDataError> {
+        let mut client = self.client().await?;
+        let id = id_of(clt);
+        let tx = client.transaction().await?;
+        let row = tx
+            .query_opt("SELECT deleted_at IS NOT NULL FROM users WHERE client_id = $1 FOR UPDATE", &[&id])
+            .await?;
+        match row.map(|row| row.get::<_, bool>(0)) {
+            None => return Err(BtcmDataError::NotFound(format!("deleted user {}", id as u64))),
+            Some(false) => return Err(BtcmDataError::Conflict(format!("user {} exists", id as u64))),
+            Some(true) => {}
+        }
+        let rows = tx.query("SELECT field, value FROM del_user_fields WHERE client_id = $1", &[&id]).await?;
+        let archived: HashMap<String, String> = rows.iter().map(|row| (row.get(0), row.get(1))).collect();
+        check_unique_fields(&tx, clt, archived.iter()).await?;
+        tx.execute(
+            "INSERT INTO user_fields (client_id, field, value) SELECT client_id, field, value FROM del_user_fields WHERE client_id = $1",
+            &[&id],
+        )
+        .await?;
+        tx.execute("DELETE FROM del_user_fields WHERE client_id = $1", &[&id]).await?;
+        tx.execute("UPDATE users SET deleted_at = NULL, deleted_reason = NULL WHERE client_id = $1", &[&id]).await?;
+        bump_user_version(&tx, clt, None).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn list_deleted_users(&self, offset: usize, count: usize) -> Result<Vec<DeletedUser>, BtcmDataError> {
+        let client = self.client().await?;
+        let rows = client
+            .query(
+                "SELECT client_id, (extract(epoch FROM deleted_at) * 1000)::BIGINT, deleted_reason FROM users
+                 WHERE deleted_at IS NOT NULL ORDER BY deleted_at, client_id LIMIT $1 OFFSET $2",
+                &[&(count as i64), &(offset as i64)],
+            )
+            .await?;
+        let ids: Vec<i64> = rows.iter().map(|row| row.get(0)).collect();
+        let mut archived: HashMap<i64, HashMap<String, String>> = HashMap::new();
+        for row in client
+            .query("SELECT client_id, field, value FROM del_user_fields WHERE client_id = ANY($1)", &[&ids])
+            .await?
+        {
+            archived.entry(row.get(0)).or_default().insert(row.get(1), row.get(2));
+        }
+        rows.iter()
+            .map(|row| {
+                let id: i64 = row.get(0);
+                let deleted_at: i64 = row.get(1);
+                let user = User::from_fields(archived.remove(&id).unwrap_or_default())?;
+                Ok(DeletedUser::new(id as u64, user, deleted_at as u64, row.get(2)))
+            })
+            .collect()
+    }
+
+    async fn purge_deleted_users(&self, retention: Duration) -> Result<usize, BtcmDataError> {
+        let client = self.client().await?;
+        // 外键的ON DELETE CASCADE同时删除该用户的联系人、群组成员和设备等数据
+        let purged = client
+            .execute(
+                "DELETE FROM users WHERE deleted_at <= now() - make_interval(secs => $1)",
+                &[&retention.as_secs_f64()],
+            )
+            .await?;
+        Ok(purged as usize)
+    }
+}
+
+#[async_trait]
+impl ContactStore for PostgresStore {
+    async fn add_user_contacts(&self, clt: ClientID, hs: &HashSet<u64>) -> Result<(), BtcmDataError> {
+        let mut client = self.client().await?;
+        let tx = client.transaction().await?;
+        for contact in hs {
+            check_not_blocked(&tx, clt, ClientID::from(*contact)).await?;
+        }
+        update_ids(&tx, "INSERT INTO user_contacts (client_id, contact_id) VALUES ($1, $2) ON CONFLICT DO NOTHING", id_of(clt), hs).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn del_user_contacts(&self, clt: ClientID, hs: &HashSet<u64>) -> Result<(), BtcmDataError> {
+        let mut client = self.client().await?;
+        let tx = client.transaction().await?;
+        update_ids(&tx, "DELETE FROM user_contacts WHERE client_id = $1 AND contact_id = $2", id_of(clt), hs).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn get_user_contacts(&self, clt: ClientID) -> Result<HashSet<u64>, BtcmDataError> {
+        let client = self.client().await?;
+        query_ids(&client, "SELECT contact_id FROM user_contacts WHERE client_id = $1", id_of(clt)).await
+    }
+
+    async fn add_contact(&self, a: ClientID, b: ClientID) -> Result<(), BtcmDataError> {
+        let mut client = self.client().await?;
+        let tx = client.transaction().await?;
+        check_not_blocked(&tx, a, b).await?;
+        update_pair(&tx, "INSERT INTO user_contacts (client_id, contact_id) VALUES ($1, $2) ON CONFLICT DO NOTHING", id_of(a), id_of(b)).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn remove_contact(&self, a: ClientID, b: ClientID) -> Result<(), BtcmDataError> {
+        let mut client = self.client().await?;
+        let tx = client.transaction().await?;
+        update_pair(&tx, "DELETE FROM user_contacts WHERE client_id = $1 AND contact_id = $2", id_of(a), id_of(b)).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn follow(&self, follower: ClientID, followee: ClientID) -> Result<(), BtcmDataError> {
+        let mut client = self.client().await?;
+        let tx = client.transaction().await?;
+        check_not_blocked(&tx, follower, followee).await?;
+        tx.execute(
+            "INSERT INTO user_contacts (client_id, contact_id) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+            &[&id_of(follower), &id_of(followee)],
+        )
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn unfollow(&self, follower: ClientID, followee: ClientID) -> Result<(), BtcmDataError> {
+        let client = self.client().await?;
+        client
+            .execute("DELETE FROM user_contacts WHERE client_id = $1 AND contact_id = $2", &[&id_of(follower), &id_of(followee)])
+            .await?;
+        Ok(())
+    }
+
+    async fn add_group_contacts(&self, clt: ClientID, hs: &HashSet<u64>) -> Result<(), BtcmDataError> {
+        let mut client = self.client().await?;
+        let tx = client.transaction().await?;
+        update_ids(&tx, "INSERT INTO group_contacts (client_id, group_id) VALUES ($1, $2) ON CONFLICT DO NOTHING", id_of(clt), hs).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn del_group_contacts(&self, clt: ClientID, hs: &HashSet<u64>) -> Result<(), BtcmDataError> {
+        let mut client = self.client().await?;
+        let tx = client.transaction().await?;
+        update_ids(&tx, "DELETE FROM group_contacts WHERE client_id = $1 AND group_id = $2", id_of(clt), hs).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn get_group_contacts(&self, clt: ClientID) -> Result<HashSet<u64>, BtcmDataError> {
+        let client = self.client().await?;
+        query_ids(&client, "SELECT group_id FROM group_contacts WHERE client_id = $1", id_of(clt)).await
+    }
+}
+
+#[async_trait]
+impl BlockStore for PostgresStore {
+    async fn block_user(&self, clt: ClientID, blocked: ClientID) -> Result<(), BtcmDataError> {
+        let client = self.client().await?;
+        client
+            .execute(
+                "INSERT INTO blocklist (client_id, blocked_id) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+                &[&id_of(clt), &id_of(blocked)],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn unblock_user(&self, clt: ClientID, blocked: ClientID) -> Result<bool, BtcmDataError> {
+        let client = self.client().await?;
+        let removed = client
+            .execute("DELETE FROM blocklist WHERE client_id = $1 AND blocked_id = $2", &[&id_of(clt), &id_of(blocked)])
+            .await?;
+        Ok(removed > 0)
+    }
+
+    async fn get_blocklist(&self, clt: ClientID) -> Result<HashSet<u64>, BtcmDataError> {
+        let client = self.client().await?;
+        query_ids(&client, "SELECT blocked_id FROM blocklist WHERE client_id = $1", id_of(clt)).await
+    }
+
+    async fn is_blocked(&self, clt: ClientID, other: ClientID) -> Result<bool, BtcmDataError> {
+        let client = self.client().await?;
+        let row = client
+            .query_opt("SELECT 1 FROM blocklist WHERE client_id = $1 AND blocked_id = $2", &[&id_of(clt), &id_of(other)])
+            .await?;
+        Ok(row.is_some())
+    }
+}
+
+#[async_trait]
+impl GroupStore for PostgresStore {
+    async fn add_group(&self, clt: ClientID, hs: &HashSet<u64>) -> Result<(), BtcmDataError> {
+        let mut client = self.client().await?;
+        let tx = client.transaction().await?;
+        tx.execute("INSERT INTO groups (group_id) VALUES ($1) ON CONFLICT DO NOTHING", &[&id_of(clt)]).await?;
+        if let Some(owner) = lock_group_owner(&tx, clt).await? {
+            check_not_blocking_owner(&tx, clt, owner, hs).await?;
+        }
+        update_ids(&tx, "INSERT INTO group_members (group_id, member_id) VALUES ($1, $2) ON CONFLICT DO NOTHING", id_of(clt), hs).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn del_group(&self, clt: ClientID, hs: &HashSet<u64>) -> Result<(), BtcmDataError> {
+        let mut client = self.client().await?;
+        let tx = client.transaction().await?;
+        // 与Redis一致:成员中包含群主时拒绝删除，锁定群主行以免与转让群主并发
+        if lock_group_owner(&tx, clt).await?.is_some_and(|owner| hs.contains(&owner)) {
+            let group_id: u64 = clt.into();
+            return Err(BtcmDataError::Conflict(format!("cannot remove the owner of group {}", group_id)));
+        }
+        // 被移出的成员的管理员身份通过外键级联删除
+        update_ids(&tx, "DELETE FROM group_members WHERE group_id = $1 AND member_id = $2", id_of(clt), hs).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn create_group(&self, clt: ClientID, info: &GroupInfo, hs: &HashSet<u64>) -> Result<(), BtcmDataError> {
+        let mut client = self.client().await?;
+        let tx = client.transaction().await?;
+        tx.execute("INSERT INTO groups (group_id) VALUES ($1) ON CONFLICT DO NOTHING", &[&id_of(clt)]).await?;
+        let created = tx
+            .execute(
+                "INSERT INTO group_fields (group_id, field, value) VALUES ($1, 'owner', $2) ON CONFLICT DO NOTHING",
+                &[&id_of(clt), &info.owner().to_string()],
+            )
+            .await?;
+        if created == 0 {
+            let group_id: u64 = clt.into();
+            return Err(BtcmDataError::Conflict(format!("group {} already exists", group_id)));
+        }
+        check_not_blocking_owner(&tx, clt, *info.owner(), hs).await?;
+        let stmt = tx.prepare("INSERT INTO group_fields (group_id, field, value) VALUES ($1, $2, $3) ON CONFLICT DO NOTHING").await?;
+        for (field, value) in info.to_fields() {
+            tx.execute(&stmt, &[&id_of(clt), &field, &value]).await?;
+        }
+        let mut members = hs.clone();
+        members.insert(*info.owner());
+        update_ids(&tx, "INSERT INTO group_members (group_id, member_id) VALUES ($1, $2) ON CONFLICT DO NOTHING", id_of(clt), &members).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn get_group_info(&self, clt: ClientID) -> Result<Option<GroupInfo>, BtcmDataError> {
+        let client = self.client().await?;
+        let rows = client.query("SELECT field, value FROM group_fields WHERE group_id = $1", &[&id_of(clt)]).await?;
+        if rows.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(GroupInfo::from_fields(rows.iter().map(|row| (row.get(0), row.get(1))).collect())?))
+    }
+
+    async fn transfer_owner(&self, clt: ClientID, new_owner: ClientID) -> Result<(), BtcmDataError> {
+        let mut client = self.client().await?;
+        let tx = client.transaction().await?;
+        let group_id: u64 = clt.into();
+        let owner_id: u64 = new_owner.into();
+        if lock_group_owner(&tx, clt).await?.is_none() {
+            return Err(BtcmDataError::NotFound(format!("group {}", group_id)));
+        }
+        let member = tx
+            .query_opt("SELECT 1 FROM group_members WHERE group_id = $1 AND member_id = $2", &[&id_of(clt), &id_of(new_owner)])
+            .await?;
+        if member.is_none() {
+            return Err(BtcmDataError::NotFound(format!("member {} of group {}", owner_id, group_id)));
+        }
+        set_group_owner(&tx, clt, owner_id).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn promote_admin(&self, clt: ClientID, member: ClientID) -> Result<(), BtcmDataError> {
+        let mut client = self.client().await?;
+        let tx = client.transaction().await?;
+        let group_id: u64 = clt.into();
+        let member_id: u64 = member.into();
+        // 锁定群主行以免与转让群主并发
+        let owner = lock_group_owner(&tx, clt).await?;
+        let row = tx
+            .query_opt("SELECT 1 FROM group_members WHERE group_id = $1 AND member_id = $2", &[&id_of(clt), &id_of(member)])
+            .await?;
+        if row.is_none() {
+            return Err(BtcmDataError::NotFound(format!("member {} of group {}", member_id, group_id)));
+        }
+        if owner == Some(member_id) {
+            return Err(BtcmDataError::Conflict(format!("member {} is the owner of group {}", member_id, group_id)));
+        }
+        tx.execute(
+            "INSERT INTO group_admins (group_id, member_id) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+            &[&id_of(clt), &id_of(member)],
+        )
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn demote_admin(&self, clt: ClientID, member: ClientID) -> Result<bool, BtcmDataError> {
+        let client = self.client().await?;
+        let removed = client
+            .execute("DELETE FROM group_admins WHERE group_id = $1 AND member_id = $2", &[&id_of(clt), &id_of(member)])
+            .await?;
+        Ok(removed > 0)
+    }
+
+    async fn get_member_role(&self, clt: ClientID, member: ClientID) -> Result<Option<GroupRole>, BtcmDataError> {
+        let client = self.client().await?;
+        let row = client
+            .query_opt(
+                "SELECT f.value = m.member_id::TEXT, a.member_id IS NOT NULL FROM group_members m \
+                 LEFT JOIN group_fields f ON f.group_id = m.group_id AND f.field = 'owner' \
+                 LEFT JOIN group_admins a ON a.group_id = m.group_id AND a.member_id = m.member_id \
+                 WHERE m.group_id = $1 AND m.member_id = $2",
+                &[&id_of(clt), &id_of(member)],
+            )
+            .await?;
+        Ok(row.map(|row| match (row.get::<_, Option<bool>>(0), row.get::<_, bool>(1)) {
+            (Some(true), _) => GroupRole::Owner,
+            (_, true) => GroupRole::Admin,
+            _ => GroupRole::Member,
+        }))
+    }
+
+    async fn get_group_admins(&self, clt: ClientID) -> Result<HashSet<u64>, BtcmDataError> {
+        let client = self.client().await?;
+        query_ids(&client, "SELECT member_id FROM group_admins WHERE group_id = $1", id_of(clt)).await
+    }
+
+    async fn get_group(&self, clt: ClientID) -> Result<HashSet<u64>, BtcmDataError> {
+        let client = self.client().await?;
+        query_ids(&client, "SELECT member_id FROM group_members WHERE group_id = $1", id_of(clt)).await
+    }
+
+    async fn exists_group(&self, clt: ClientID) -> Result<bool, BtcmDataError> {
+        let client = self.client().await?;
+        let row = client.query_opt("SELECT 1 FROM groups WHERE group_id = $1", &[&id_of(clt)]).await?;
+        Ok(row.is_some())
+    }
+
+    async fn remove_group(&self, clt: ClientID) -> Result<bool, BtcmDataError> {
+        let client = self.client().await?;
+        // 成员、管理员、群组信息和群组联系人通过外键级联删除
+        let removed = client.execute("DELETE FROM groups WHERE group_id = $1", &[&id_of(clt)]).await?;
+        Ok(removed > 0)
+    }
+
+    async fn groups_of_user(&self, clt: ClientID) -> Result<HashSet<u64>, BtcmDataError> {
+        let client = self.client().await?;
+        query_ids(&client, "SELECT group_id FROM group_members WHERE member_id = $1", id_of(clt)).await
+    }
+
+    async fn common_groups(&self, a: ClientID, b: ClientID) -> Result<HashSet<u64>, BtcmDataError> {
+        let client = self.client().await?;
+        let rows = client
+            .query(
+                "SELECT a.group_id FROM group_members a JOIN group_members b ON a.group_id = b.group_id \
+                 WHERE a.member_id = $1 AND b.member_id = $2",
+                &[&id_of(a), &id_of(b)],
+            )
+            .await?;
+        Ok(rows.iter().map(|row| row.get::<_, i64>(0) as u64).collect())
+    }
+}
+
+#[async_trait]
+impl DeviceStore for PostgresStore {
+    async fn add_dev2clt(&self, clt: ClientID, devs: &HashSet<u64>) -> Result<(), BtcmDataError> {
+        let mut client = self.client().await?;
+        let tx = client.transaction().await?;
+        update_ids(&tx, "INSERT INTO client_devices (client_id, device_id) VALUES ($1, $2) ON CONFLICT DO NOTHING", id_of(clt), devs).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn del_dev4clt(&self, clt: ClientID, devs: &HashSet<u64>) -> Result<(), BtcmDataError> {
+        let mut client = self.client().await?;
+        let tx = client.transaction().await?;
+        update_ids(&tx, "DELETE FROM client_devices WHERE client_id = $1 AND device_id = $2", id_of(clt), devs).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn get_devclt_set(&self, clt: ClientID) -> Result<HashSet<u64>, BtcmDataError> {
+        let client = self.client().await?;
+        query_ids(&client, "SELECT device_id FROM client_devices WHERE client_id = $1", id_of(clt)).await
+    }
+
+    async fn exists_devclt(&self, clt: ClientID) -> Result<bool, BtcmDataError> {
+        let client = self.client().await?;
+        let row = client
+            .query_opt("SELECT 1 FROM client_devices WHERE client_id = $1 LIMIT 1", &[&id_of(clt)])
+            .await?;
+        Ok(row.is_some())
+    }
+
+    async fn remove_devclt_set(&self, clt: ClientID) -> Result<bool, BtcmDataError> {
+        let client = self.client().await?;
+        // 设备信息通过外键级联删除
+        let removed = client.execute("DELETE FROM client_devices WHERE client_id = $1", &[&id_of(clt)]).await?;
+        Ok(removed > 0)
+    }
+
+    async fn add_dev2clt_hash(&self, clt: ClientID, dev: u32, device: &Device) -> Result<(), BtcmDataError> {
+        let mut client = self.client().await?;
+        let tx = client.transaction().await?;
+        let dev = dev as i64;
+        tx.execute(
+            "INSERT INTO client_devices (client_id, device_id) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+            &[&id_of(clt), &dev],
+        )
+        .await?;
+        tx.execute("DELETE FROM device_fields WHERE client_id = $1 AND device_id = $2", &[&id_of(clt), &dev]).await?;
+        let stmt = tx
+            .prepare("INSERT INTO device_fields (client_id, device_id, field, value) VALUES ($1, $2, $3, $4)")
+            .await?;
+        for (field, value) in device.to_fields() {
+            tx.execute(&stmt, &[&id_of(clt), &dev, &field, &value]).await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn get_device(&self, clt: ClientID, dev: u32) -> Result<Option<Device>, BtcmDataError> {
+        let client = self.client().await?;
+        let rows = client
+            .query(
+                "SELECT field, value FROM device_fields WHERE client_id = $1 AND device_id = $2",
+                &[&id_of(clt), &(dev as i64)],
+            )
+            .await?;
+        if rows.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(Device::from_fields(rows.iter().map(|row| (row.get(0), row.get(1))).collect())?))
+    }
+
+    async fn get_device_fields(&self, clt: ClientID, dev: u32, fields: &[&str]) -> Result<HashMap<String, String>, BtcmDataError> {
+        let client = self.client().await?;
+        let rows = client
+            .query(
+                "SELECT field, value FROM device_fields WHERE client_id = $1 AND device_id = $2 AND field = ANY($3)",
+                &[&id_of(clt), &(dev as i64), &fields],
+            )
+            .await?;
+        Ok(rows.iter().map(|row| (row.get(0), row.get(1))).collect())
+    }
+
+    async fn set_device_fields(&self, clt: ClientID, dev: u32, fields: &HashMap<String, String>) -> Result<(), BtcmDataError> {
+        Device::check_fields(fields)?;
+        let mut client = self.client().await?;
+        let tx = client.transaction().await?;
+        let dev = dev as i64;
+        // 设备信息存在时device_fields中至少有一行，锁住这些行防止并发的整体替换
+        let rows = tx
+            .query("SELECT 1 FROM device_fields WHERE client_id = $1 AND device_id = $2 FOR UPDATE", &[&id_of(clt), &dev])
+            .await?;
+        if rows.is_empty() {
+            let user_id: u64 = clt.into();
+            return Err(BtcmDataError::NotFound(format!("device {} of client {}", dev, user_id)));
+        }
+        let stmt = tx
+            .prepare(
+                "INSERT INTO device_fields (client_id, device_id, field, value) VALUES ($1, $2, $3, $4)
+                 ON CONFLICT (client_id, device_id, field) DO UPDATE SET value = excluded.value",
+            )
+            .await?;
+        for (field, value) in fields {
+            tx.execute(&stmt, &[&id_of(clt), &dev, field, value]).await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn delete_device_fields(&self, clt: ClientID, dev: u32, fields: &[&str]) -> Result<usize, BtcmDataError> {
+        let client = self.client().await?;
+        let removed = client
+            .execute(
+                "DELETE FROM device_fields WHERE client_id = $1 AND device_id = $2 AND field = ANY($3)",
+                &[&id_of(clt), &(dev as i64), &fields],
+            )
+            .await?;
+        Ok(removed as usize)
+    }
+
+    async fn exists_device(&self, clt: ClientID, dev: u32) -> Result<bool, BtcmDataError> {
+        let client = self.client().await?;
+        let row = client
+            .query_opt(
+                "SELECT 1 FROM device_fields WHERE client_id = $1 AND device_id = $2 LIMIT 1",
+                &[&id_of(clt), &(dev as i64)],
+            )
+            .await?;
+        Ok(row.is_some())
+    }
+
+    async fn remove_device(&self, clt: ClientID, dev: u32) -> Result<bool, BtcmDataError> {
+        let client = self.client().await?;
+        let removed = client
+            .execute(
+                "DELETE FROM device_fields WHERE client_id = $1 AND device_id = $2",
+                &[&id_of(clt), &(dev as i64)],
+            )
+            .await?;
+        Ok(removed > 0)
+    }
+}
+
+#[async_trait]
+impl AccountStore for PostgresStore {
+    /// 在一个事务中完成，联系人包括所有把该用户加为联系人的用户。
+    /// 用户拥有的群组转让给ID最小的管理员，没有管理员时转让给ID最小的其他成员，没有其他成员时删除群组。
+    async fn delete_account(&self, clt: ClientID, reason: Option<&str>) -> Result<AccountDeletionReport, BtcmDataError> {
+        let mut client = self.client().await?;
+        let id = id_of(clt);
+        let tx = client.transaction().await?;
+        let mut report = AccountDeletionReport {
+            user_removed: soft_delete_user(&tx, clt, reason).await?,
+            ..Default::default()
+        };
+        let rows = tx
+            .query("DELETE FROM user_contacts WHERE client_id = $1 OR contact_id = $1 RETURNING client_id", &[&id])
+            .await?;
+        report.contacts = rows.iter().map(|row| row.get::<_, i64>(0)).filter(|c| *c != id).map(|c| c as u64).collect();
+        let rows = tx.query("SELECT group_id FROM group_members WHERE member_id = $1", &[&id]).await?;
+        for row in rows {
+            let group = row.get::<_, i64>(0) as u64;
+            let gid = ClientID::from(group);
+            if lock_group_owner(&tx, gid).await? == Some(id as u64) {
+                let next: Option<i64> = tx
+                    .query_one(
+                        "SELECT COALESCE(
+                             (SELECT MIN(member_id) FROM group_admins WHERE group_id = $1 AND member_id <> $2),
+                             (SELECT MIN(member_id) FROM group_members WHERE group_id = $1 AND member_id <> $2))",
+                        &[&id_of(gid), &id],
+                    )
+                    .await?
+                    .get(0);
+                match next {
+                    Some(owner) => {
+                        set_group_owner(&tx, gid, owner as u64).await?;
+                        report.transferred_groups.insert(group, owner as u64);
+                    }
+                    None => {
+                        tx.execute("DELETE FROM groups WHERE group_id = $1", &[&id_of(gid)]).await?;
+                        report.removed_groups.insert(group);
+                        continue;
+                    }
+                }
+            }
+            report.groups.insert(group);
+        }
+        tx.execute("DELETE FROM group_admins WHERE member_id = $1", &[&id]).await?;
+        tx.execute("DELETE FROM group_members WHERE member_id = $1", &[&id]).await?;
+        // 设备信息通过外键级联删除
+        let rows = tx.query("DELETE FROM client_devices WHERE client_id = $1 RETURNING device_id", &[&id]).await?;
+        report.devices = rows.iter().map(|row| device_id(row.get::<_, i64>(0) as u64)).collect::<Result<_, _>>()?;
+        tx.execute("DELETE FROM group_contacts WHERE client_id = $1", &[&id]).await?;
+        tx.execute("DELETE FROM blocklist WHERE client_id = $1", &[&id]).await?;
+        tx.commit().await?;
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 连接本地PostgreSQL，测试默认忽略，例如:
+    /// `BTCMDATA_POSTGRES_URL="host=localhost user=postgres dbname=btcm_test" cargo test -- --ignored`
+    async fn test_store() -> PostgresStore {
+        let url = std::env::var("BTCMDATA_POSTGRES_URL").expect("BTCMDATA_POSTGRES_URL is not set");
+        PostgresStore::connect(&url).await.expect("Failed to connect to PostgreSQL")
+    }
+
+    /// 删除测试用的用户和群组，外键级联删除其余的行
+    async fn cleanup(store: &PostgresStore) {
+        let client = store.client().await.unwrap();
+        client.execute("DELETE FROM groups WHERE group_id = 900100", &[]).await.unwrap();
+        client.execute("DELETE FROM users WHERE client_id BETWEEN 900001 AND 900004", &[]).await.unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore = "requires BTCMDATA_POSTGRES_URL"]
+    async fn users_groups_and_devices() {
+        let store = test_store().await;
+        cleanup(&store).await;
+        let (owner, member, group) = (ClientID::from(900_001), ClientID::from(900_002), ClientID::from(900_100));
+        let user = User::new("John");
+
+        store.add_user(owner, &user).await.unwrap();
+        store.add_user(member, &user).await.unwrap();
+        let stored = store.get_user(owner).await.unwrap().unwrap();
+        assert_eq!(stored.nickname(), user.nickname());
+        assert!(matches!(store.update_user(owner, &user).await, Err(BtcmDataError::Conflict(_))));
+        assert_eq!(store.update_user(owner, &stored).await.unwrap(), stored.version() + 1);
+        let fields: HashMap<String, String> = [("avatar".to_string(), "a.png".to_string())].into_iter().collect();
+        store.set_user_fields(owner, &fields).await.unwrap();
+        assert_eq!(store.get_user_fields(owner, &["avatar", "phone"]).await.unwrap(), fields);
+        assert_eq!(store.delete_user_fields(owner, &["avatar"]).await.unwrap(), 1);
+        let username: HashMap<String, String> = [("username".to_string(), "john_900002".to_string())].into_iter().collect();
+        store.set_user_fields(member, &username).await.unwrap();
+        assert!(matches!(store.set_user_fields(owner, &username).await, Err(BtcmDataError::Duplicate(_))));
+        assert_eq!(store.find_user_by(UniqueField::Username, "john_900002").await.unwrap(), Some(900_002));
+        let found = store.search_users_by_nickname("jO", 0, 100).await.unwrap();
+        assert!(found.contains(&900_001) && found.contains(&900_002));
+        store.add_user(ClientID::from(900_003), &User::new("J_n%")).await.unwrap();
+        store.add_user(ClientID::from(900_004), &User::new("Éva")).await.unwrap();
+        assert_eq!(store.search_users_by_nickname("j_", 0, 100).await.unwrap(), vec![900_003]);
+        assert!(!store.search_users_by_nickname("j%", 0, 100).await.unwrap().contains(&900_003));
+        assert!(!store.search_users_by_nickname("é", 0, 100).await.unwrap().contains(&900_004));
+        assert!(store.search_users_by_nickname("É", 0, 100).await.unwrap().contains(&900_004));
+
+        // 外键约束:联系人必须是已存在的用户
+        let unknown: HashSet<u64> = [900_999].into_iter().collect();
+        assert!(matches!(store.add_user_contacts(owner, &unknown).await, Err(BtcmDataError::NotFound(_))));
+
+        store.block_user(member, owner).await.unwrap();
+        assert!(matches!(store.follow(owner, member).await, Err(BtcmDataError::Blocked(_))));
+        assert!(matches!(store.add_user_contacts(owner, &[900_002].into_iter().collect()).await, Err(BtcmDataError::Blocked(_))));
+        assert!(store.unblock_user(member, owner).await.unwrap());
+        store.add_contact(owner, member).await.unwrap();
+        assert!(store.get_user_contacts(member).await.unwrap().contains(&900_001));
+
+        let members: HashSet<u64> = [900_001, 900_002].into_iter().collect();
+        store.create_group(group, &GroupInfo::new("team", owner), &members).await.unwrap();
+        assert_eq!(store.get_group(group).await.unwrap(), members);
+        let owners: HashSet<u64> = [900_001].into_iter().collect();
+        assert!(matches!(store.del_group(group, &owners).await, Err(BtcmDataError::Conflict(_))));
+        store.block_user(member, owner).await.unwrap();
+        assert!(matches!(store.add_group(group, &[900_002].into_iter().collect()).await, Err(BtcmDataError::Blocked(_))));
+        assert!(store.unblock_user(member, owner).await.unwrap());
+
+        let admin = ClientID::from(900_003);
+        store.add_group(group, &[900_003].into_iter().collect()).await.unwrap();
+        store.promote_admin(group, admin).await.unwrap();
+        assert!(matches!(store.promote_admin(group, owner).await, Err(BtcmDataError::Conflict(_))));
+        assert!(matches!(store.promote_admin(group, ClientID::from(900_004)).await, Err(BtcmDataError::NotFound(_))));
+        assert_eq!(store.get_member_role(group, owner).await.unwrap(), Some(GroupRole::Owner));
+        assert_eq!(store.get_member_role(group, admin).await.unwrap(), Some(GroupRole::Admin));
+        assert_eq!(store.get_member_role(group, member).await.unwrap(), Some(GroupRole::Member));
+        assert_eq!(store.get_member_role(group, ClientID::from(900_004)).await.unwrap(), None);
+        store.del_group(group, &[900_003].into_iter().collect()).await.unwrap();
+        assert!(store.get_group_admins(group).await.unwrap().is_empty());
+        store.add_group(group, &[900_003].into_iter().collect()).await.unwrap();
+        store.promote_admin(group, admin).await.unwrap();
+        assert_eq!(store.get_group_admins(group).await.unwrap(), [900_003].into_iter().collect());
+
+        store.add_dev2clt_hash(owner, 1, &Device::default()).await.unwrap();
+        assert!(store.get_devclt_set(owner).await.unwrap().contains(&1));
+        assert!(store.remove_devclt_set(owner).await.unwrap());
+        assert!(!store.exists_device(owner, 1).await.unwrap());
+
+        store.add_dev2clt(owner, &[2].into_iter().collect()).await.unwrap();
+        let report = store.delete_account(owner, Some("test")).await.unwrap();
+        assert!(*report.user_removed());
+        assert_eq!(*report.contacts(), [900_002].into_iter().collect());
+        assert_eq!(*report.groups(), [900_100].into_iter().collect());
+        // 管理员优先于ID更小的普通成员成为新群主，并且不再是管理员
+        assert_eq!(*report.transferred_groups(), [(900_100, 900_003)].into_iter().collect());
+        assert_eq!(*store.get_group_info(group).await.unwrap().unwrap().owner(), 900_003);
+        assert!(store.get_group_admins(group).await.unwrap().is_empty());
+        assert_eq!(*report.devices(), [2].into_iter().collect());
+        assert!(store.get_user_contacts(member).await.unwrap().is_empty());
+
+        assert!(store.remove_group(group).await.unwrap());
+        assert!(store.remove_user(member, None).await.unwrap());
+        assert!(!store.remove_user(owner, None).await.unwrap());
+        let deleted = store.list_deleted_users(0, 100).await.unwrap();
+        assert!(deleted.iter().any(|d| *d.client_id() == 900_001 && d.reason().as_deref() == Some("test")));
+        store.restore_user(member).await.unwrap();
+        assert_eq!(store.purge_deleted_users(Duration::from_secs(3600)).await.unwrap(), 0);
+        assert_eq!(store.find_user_by(UniqueField::Username, "john_900002").await.unwrap(), Some(900_002));
+
+        cleanup(&store).await;
+    }
+
+    #[tokio::test]
+    #[ignore = "requires BTCMDATA_POSTGRES_URL"]
+    async fn reconnects_after_the_connection_drops() {
+        let store = test_store().await;
+        let client = store.client().await.unwrap();
+        assert!(client.execute("SELECT pg_terminate_backend(pg_backend_pid())", &[]).await.is_err());
+        for _ in 0..100 {
+            if client.is_closed() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert!(client.is_closed());
+        drop(client);
+        assert!(!store.exists_user(ClientID::from(900_999)).await.unwrap());
+    }
+}
@@ -0,0 +1,171 @@
+use std::collections::HashSet;
+use btcmbase::client::ClientID;
+use redis::aio::MultiplexedConnection;
+use crate::error::BtcmDataError;
+use crate::model::{device_id, AccountDeletionReport};
+use super::{blocklist, cursor, device, friends, groups, inbox, outbox, presence, remarks, route, users};
+
+/// 异步函数，注销账号:软删除用户资料，并删除与用户相关的联系人、黑名单、备注、群组成员关系、
+/// 设备(包括收件箱、发件箱和同步游标)、在线状态、网关路由和好友申请(包括已处理的申请)。
+///
+/// 用户会从其联系人的`conts_user:`集合中移除；只关注了该用户而不在该用户联系人中的用户无法
+/// 通过索引找到，他们的集合保持不变。用户拥有的群组转让给ID最小的管理员，没有管理员时转让给
+/// ID最小的其他成员，没有其他成员时删除群组。
+///
+/// 各步骤分别执行，不是原子的；中途失败后可以再次调用，已删除的用户会继续清理剩余的数据。
+///
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `clt`: 用户ID。
+/// - `reason`: 删除原因，见`users::remove_user`。
+///
+/// # 返回值
+/// 返回本次注销涉及的数据，用户不存在也没有被删除过时返回`BtcmDataError::NotFound`；
+/// 设备集合中有无法转换为u32的设备ID时返回`BtcmDataError::TypeMismatch`，不删除任何数据。
+///
+/// # 示例
+/// ```rust,no_run
+/// use btcmbase::client::ClientID;
+/// use btcmdata::redis::account::delete_account;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let client = redis::Client::open("redis://127.0.0.1/").expect("Failed to connect to Redis");
+///     let con = client.get_multiplexed_tokio_connection().await.expect("Failed to get Redis connection");
+///
+///     let report = delete_account(&con, ClientID::from(1001), Some("requested by user")).await.unwrap();
+///     println!("Left groups: {:?}, transferred: {:?}", report.groups(), report.transferred_groups());
+/// }
+/// ```
+pub async fn delete_account(con: &MultiplexedConnection, clt: ClientID, reason: Option<&str>) -> Result<AccountDeletionReport, BtcmDataError> {
+    let mut rcon = con.clone();
+    let user_id: u64 = clt.into();
+    // 先检查设备ID，无法转换为u32的设备ID说明数据有误，此时不删除任何数据
+    let devices = device::get_devclt_set(con, clt)
+        .await?
+        .into_iter()
+        .map(device_id)
+        .collect::<Result<Vec<u32>, _>>()?;
+    let mut report = AccountDeletionReport {
+        user_removed: users::remove_user(&mut rcon, clt, reason).await?,
+        ..Default::default()
+    };
+
+    // 从联系人的集合中移除用户，再删除用户自己的联系人、群组联系人、黑名单和备注
+    let contacts = users::get_user_contacts(con, clt).await?;
+    if !contacts.is_empty() {
+        let mut pipe = redis::pipe();
+        for contact in &contacts {
+            pipe.srem(users::get_user_conts_key(ClientID::from(*contact)), user_id);
+        }
+        let removed: Vec<usize> = pipe.query_async(&mut rcon).await?;
+        report.contacts = contacts.iter().zip(removed).filter(|(_, n)| *n > 0).map(|(c, _)| *c).collect();
+    }
+    remarks::remove_contact_remarks(con, clt, &contacts).await?;
+    let _: () = redis::pipe()
+        .atomic()
+        .del(users::get_user_conts_key(clt)).ignore()
+        .del(users::get_group_conts_key(clt)).ignore()
+        .del(blocklist::get_blocklist_key(clt)).ignore()
+        .query_async(&mut rcon)
+        .await?;
+
+    // 退出群组，用户是群主时先转让群主，没有其他成员时删除群组
+    for group in groups::groups_of_user(con, clt).await? {
+        let gid = ClientID::from(group);
+        let owned = groups::get_group_info(con, gid).await?.is_some_and(|info| *info.owner() == user_id);
+        if owned {
+            let mut admins: Vec<u64> = groups::get_group_admins(con, gid).await?.into_iter().filter(|m| *m != user_id).collect();
+            if admins.is_empty() {
+                admins = groups::get_group(con, gid).await?.into_iter().filter(|m| *m != user_id).collect();
+            }
+            match admins.into_iter().min() {
+                Some(owner) => {
+                    groups::transfer_owner(con, gid, ClientID::from(owner)).await?;
+                    report.transferred_groups.insert(group, owner);
+                }
+                None => {
+                    groups::remove_group(&mut rcon, gid).await?;
+                    report.removed_groups.insert(group);
+                    continue;
+                }
+            }
+        }
+        groups::del_group(con, gid, &HashSet::from([user_id])).await?;
+        report.groups.insert(group);
+    }
+
+    // 删除设备信息及每个设备的收件箱、发件箱和同步游标
+    for dev in devices {
+        device::remove_device(&mut rcon, clt, dev).await?;
+        inbox::remove_inbox(con, clt, dev).await?;
+        outbox::remove_outbox(con, clt, dev).await?;
+        cursor::remove_cursors(con, clt, dev).await?;
+        report.devices.insert(dev);
+    }
+    device::remove_devclt_set(&mut rcon, clt).await?;
+    presence::remove_presence(con, clt).await?;
+    route::remove_routes(con, clt).await?;
+
+    report.friend_requests = friends::remove_friend_requests(con, clt).await?;
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use redis::AsyncCommands;
+    use super::*;
+    use crate::model::{Device, GroupInfo, User};
+    use crate::redis::test_connection;
+
+    #[tokio::test]
+    #[ignore = "requires BTCMDATA_REDIS_URL"]
+    async fn report_lists_what_was_removed() {
+        let con = test_connection().await;
+        let mut rcon = con.clone();
+        let (clt, friend, member) = (ClientID::from(922_001), ClientID::from(922_002), ClientID::from(922_003));
+        let (shared, solo, legacy) = (ClientID::from(922_100), ClientID::from(922_101), ClientID::from(922_102));
+        for group in [shared, solo, legacy] {
+            groups::remove_group(&mut rcon, group).await.unwrap();
+        }
+        for user in [clt, friend, member] {
+            users::add_user(&con, user, &User::new("Cascade")).await.unwrap();
+            let _: () = rcon.del(users::get_user_conts_key(user)).await.unwrap();
+        }
+        users::add_contact(&con, clt, friend).await.unwrap();
+        groups::create_group(&con, shared, &GroupInfo::new("shared", clt), &HashSet::from([922_003])).await.unwrap();
+        groups::create_group(&con, solo, &GroupInfo::new("solo", clt), &HashSet::new()).await.unwrap();
+        device::add_dev2clt(&con, clt, &HashSet::from([1])).await.unwrap();
+        device::add_dev2clt_hash(&con, clt, 1, &Device::default()).await.unwrap();
+        inbox::push_inbox(&con, clt, 1, b"hello", 10).await.unwrap();
+
+        // 反向索引之前写入的成员关系补建索引后同样会被清理
+        let _: () = rcon.sadd("group:922102", 922_001).await.unwrap();
+        assert!(groups::backfill_user_groups(&con).await.unwrap() >= 3);
+        assert!(groups::groups_of_user(&con, clt).await.unwrap().contains(&922_102));
+
+        // 设备ID无法转换为u32时不删除任何数据
+        let invalid = HashSet::from([u64::from(u32::MAX) + 1]);
+        device::add_dev2clt(&con, clt, &invalid).await.unwrap();
+        assert!(matches!(delete_account(&con, clt, None).await, Err(BtcmDataError::TypeMismatch(_))));
+        assert!(users::exists_user(&mut rcon, clt).await.unwrap());
+        device::del_dev4clt(&con, clt, &invalid).await.unwrap();
+
+        let report = delete_account(&con, clt, Some("test")).await.unwrap();
+        assert!(*report.user_removed());
+        assert_eq!(*report.contacts(), HashSet::from([922_002]));
+        assert_eq!(*report.groups(), HashSet::from([922_100, 922_102]));
+        assert_eq!(*report.transferred_groups(), HashMap::from([(922_100, 922_003)]));
+        assert_eq!(*report.removed_groups(), HashSet::from([922_101]));
+        assert_eq!(*report.devices(), HashSet::from([1]));
+        assert!(users::get_user_contacts(&con, friend).await.unwrap().is_empty());
+        assert_eq!(inbox::inbox_len(&con, clt, 1).await.unwrap(), 0);
+        assert_eq!(*groups::get_group_info(&con, shared).await.unwrap().unwrap().owner(), 922_003);
+
+        // 再次调用时只剩下已删除的用户
+        let again = delete_account(&con, clt, None).await.unwrap();
+        assert_eq!(again, AccountDeletionReport::default());
+        groups::remove_group(&mut rcon, shared).await.unwrap();
+    }
+}
@@ -0,0 +1,167 @@
+use std::collections::HashSet;
+use btcmbase::client::ClientID;
+use redis::{aio::MultiplexedConnection, AsyncCommands};
+use crate::error::BtcmDataError;
+
+// 每个用户一个黑名单集合，保存被该用户拉黑的用户ID
+// blocklist:1001 -> Set  被1001拉黑的用户
+static BLOCKLIST_PREFIX: &str = "blocklist:";
+
+/// 获取用户黑名单键的函数
+pub(crate) fn get_blocklist_key(clt: ClientID) -> String {
+    let user_id: u64 = clt.into();
+    format!("{}{}", BLOCKLIST_PREFIX, user_id)
+}
+
+/// 异步函数，将用户加入黑名单。
+///
+/// 拉黑之后，双方之间的`users::add_contact`、`users::add_user_contacts`、`users::follow`、
+/// 好友申请和同意好友申请都会返回`BtcmDataError::Blocked`，拉黑者也不能再被加入被拉黑者
+/// 作为群主的群组；已有的联系人关系和群组成员关系不受影响。
+///
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `clt`: 拉黑的用户ID。
+/// - `blocked`: 被拉黑的用户ID。
+///
+/// # 示例
+/// ```rust,no_run
+/// use btcmbase::client::ClientID;
+/// use btcmdata::redis::blocklist::{block_user, is_blocked};
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let client = redis::Client::open("redis://127.0.0.1/").expect("Failed to connect to Redis");
+///     let con = client.get_multiplexed_tokio_connection().await.expect("Failed to get Redis connection");
+///     let (alice, bob) = (ClientID::from(1001), ClientID::from(1002));
+///
+///     block_user(&con, alice, bob).await.unwrap();
+///     assert!(is_blocked(&con, alice, bob).await.unwrap());
+/// }
+/// ```
+pub async fn block_user(con: &MultiplexedConnection, clt: ClientID, blocked: ClientID) -> Result<(), BtcmDataError> {
+    let mut con = con.clone();
+    let blocked: u64 = blocked.into();
+    let _: () = con.sadd(get_blocklist_key(clt), blocked).await?;
+    Ok(())
+}
+
+/// 异步函数，将用户移出黑名单。
+///
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `clt`: 拉黑的用户ID。
+/// - `blocked`: 被拉黑的用户ID。
+///
+/// # 返回值
+/// 返回用户是否在黑名单中并被移除。
+pub async fn unblock_user(con: &MultiplexedConnection, clt: ClientID, blocked: ClientID) -> Result<bool, BtcmDataError> {
+    let mut con = con.clone();
+    let blocked: u64 = blocked.into();
+    let removed: usize = con.srem(get_blocklist_key(clt), blocked).await?;
+    Ok(removed > 0)
+}
+
+/// 异步函数，获取用户的黑名单。
+///
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `clt`: 用户ID。
+///
+/// # 返回值
+/// 返回一个HashSet<u64>，包含被该用户拉黑的所有用户ID。
+pub async fn get_blocklist(con: &MultiplexedConnection, clt: ClientID) -> Result<HashSet<u64>, BtcmDataError> {
+    let mut con = con.clone();
+    let result: HashSet<u64> = con.smembers(get_blocklist_key(clt)).await?;
+    Ok(result)
+}
+
+/// 异步函数，判断`clt`是否拉黑了`other`，只检查一个方向。
+///
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `clt`: 用户ID。
+/// - `other`: 另一个用户ID。
+///
+/// # 返回值
+/// `other`在`clt`的黑名单中时返回true。
+pub async fn is_blocked(con: &MultiplexedConnection, clt: ClientID, other: ClientID) -> Result<bool, BtcmDataError> {
+    let mut con = con.clone();
+    let other: u64 = other.into();
+    let result: bool = con.sismember(get_blocklist_key(clt), other).await?;
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use crate::model::{FriendRequestState, GroupInfo};
+    use crate::redis::{friends, groups, test_connection, users};
+
+    #[tokio::test]
+    #[ignore = "requires BTCMDATA_REDIS_URL"]
+    async fn blocks_bulk_contacts_and_group_members() {
+        let con = test_connection().await;
+        let (alice, bob, carol, group) = (ClientID::from(914_001), ClientID::from(914_002), ClientID::from(914_003), ClientID::from(914_100));
+        let mut rcon = con.clone();
+        groups::remove_group(&mut rcon, group).await.unwrap();
+        for clt in [alice, bob, carol] {
+            let _: () = rcon.del(&[get_blocklist_key(clt), users::get_user_conts_key(clt)]).await.unwrap();
+        }
+        block_user(&con, carol, alice).await.unwrap();
+
+        // 任意一个联系人被拉黑时整批拒绝
+        let contacts = HashSet::from([914_002, 914_003]);
+        assert!(matches!(users::add_user_contacts(&con, alice, &contacts).await, Err(BtcmDataError::Blocked(_))));
+        assert!(users::get_user_contacts(&con, alice).await.unwrap().is_empty());
+        users::add_user_contacts(&con, alice, &HashSet::from([914_002])).await.unwrap();
+
+        // 拉黑了群主的用户不能加入群组
+        let info = GroupInfo::new("blocked", alice);
+        assert!(matches!(groups::create_group(&con, group, &info, &contacts).await, Err(BtcmDataError::Blocked(_))));
+        assert!(groups::get_group_info(&con, group).await.unwrap().is_none());
+        groups::create_group(&con, group, &info, &HashSet::from([914_002])).await.unwrap();
+        assert!(matches!(groups::add_group(&con, group, &HashSet::from([914_003])).await, Err(BtcmDataError::Blocked(_))));
+        assert_eq!(groups::get_group(&con, group).await.unwrap(), HashSet::from([914_001, 914_002]));
+        groups::remove_group(&mut rcon, group).await.unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore = "requires BTCMDATA_REDIS_URL"]
+    async fn blocks_friend_requests_in_both_directions() {
+        let con = test_connection().await;
+        let (alice, bob) = (ClientID::from(914_004), ClientID::from(914_005));
+        let mut rcon = con.clone();
+        for clt in [alice, bob] {
+            friends::remove_friend_requests(&con, clt).await.unwrap();
+            let _: () = rcon.del(&[get_blocklist_key(clt), users::get_user_conts_key(clt)]).await.unwrap();
+        }
+        let ttl = Duration::from_secs(60);
+
+        for (blocker, blocked) in [(alice, bob), (bob, alice)] {
+            block_user(&con, blocker, blocked).await.unwrap();
+            assert!(matches!(friends::send_friend_request(&con, alice, bob, None, ttl).await, Err(BtcmDataError::Blocked(_))));
+            assert!(matches!(friends::send_friend_request(&con, bob, alice, None, ttl).await, Err(BtcmDataError::Blocked(_))));
+            unblock_user(&con, blocker, blocked).await.unwrap();
+        }
+
+        // 申请发出后被拉黑时不能同意，申请保持待处理
+        friends::send_friend_request(&con, alice, bob, None, ttl).await.unwrap();
+        for (blocker, blocked) in [(alice, bob), (bob, alice)] {
+            block_user(&con, blocker, blocked).await.unwrap();
+            assert!(matches!(friends::accept_friend_request(&con, alice, bob).await, Err(BtcmDataError::Blocked(_))));
+            let request = friends::get_friend_request(&con, alice, bob).await.unwrap().unwrap();
+            assert_eq!(*request.state(), FriendRequestState::Pending);
+            assert_eq!(friends::list_incoming_friend_requests(&con, bob).await.unwrap().len(), 1);
+            assert!(users::get_user_contacts(&con, bob).await.unwrap().is_empty());
+            unblock_user(&con, blocker, blocked).await.unwrap();
+        }
+        friends::accept_friend_request(&con, alice, bob).await.unwrap();
+
+        for clt in [alice, bob] {
+            friends::remove_friend_requests(&con, clt).await.unwrap();
+            let _: () = rcon.del(users::get_user_conts_key(clt)).await.unwrap();
+        }
+    }
+}
@@ -0,0 +1,257 @@
+use std::collections::{BTreeSet, HashMap};
+use btcmbase::client::ClientID;
+use redis::{aio::MultiplexedConnection, AsyncCommands};
+use crate::error::BtcmDataError;
+use crate::model::Conversation;
+use super::{groups, sequence, users};
+
+// 每个客户端设备的同步游标，记录每个会话已投递和已读到的消息序号
+// cursor_delivered:1001:1 -> Hash  会话 -> 已投递的最大序号
+// cursor_read:1001:1      -> Hash  会话 -> 已读的最大序号
+static CURSOR_DELIVERED_PREFIX: &str = "cursor_delivered:";
+static CURSOR_READ_PREFIX: &str = "cursor_read:";
+
+/// 根据客户端ID和设备ID获取已投递游标键的函数
+fn get_delivered_key(clt: ClientID, dev: u32) -> String {
+    let user_id: u64 = clt.into();
+    format!("{}{}:{}", CURSOR_DELIVERED_PREFIX, user_id, dev)
+}
+
+/// 根据客户端ID和设备ID获取已读游标键的函数
+fn get_read_key(clt: ClientID, dev: u32) -> String {
+    let user_id: u64 = clt.into();
+    format!("{}{}:{}", CURSOR_READ_PREFIX, user_id, dev)
+}
+
+/// 只前进的游标更新:新序号大于当前序号时才写入，返回更新后的游标。
+/// KEYS[1..n] = 需要更新的游标哈希, ARGV[1] = 会话, ARGV[2] = 新序号
+static ADVANCE_SCRIPT: &str = r"
+local new = tonumber(ARGV[2])
+local result = 0
+for i, key in ipairs(KEYS) do
+    local cur = tonumber(redis.call('HGET', key, ARGV[1]) or '0')
+    if new > cur then
+        redis.call('HSET', key, ARGV[1], ARGV[2])
+        cur = new
+    end
+    if i == 1 then
+        result = cur
+    end
+end
+return result
+";
+
+/// 设备在一个会话中的同步位置
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SyncCursor {
+    /// 已投递到设备的最大序号
+    pub delivered: u64,
+    /// 设备上已读的最大序号
+    pub read: u64,
+}
+
+/// 设备落后的会话
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConversationLag {
+    pub conv: Conversation,
+    pub cursor: SyncCursor,
+    /// 会话当前的最大序号
+    pub max_seq: u64,
+}
+
+/// 异步函数，前进设备在会话中的已投递游标，游标只会前进不会后退。
+///
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `clt`: 客户端ID。
+/// - `dev`: 设备ID。
+/// - `conv`: 会话。
+/// - `seq`: 已投递的消息序号。
+///
+/// # 返回值
+/// 返回更新后的已投递游标，`seq`小于当前游标时返回当前游标。
+///
+/// # 示例
+/// ```rust,no_run
+/// use btcmbase::client::ClientID;
+/// use btcmdata::model::Conversation;
+/// use btcmdata::redis::cursor::advance_delivered;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let client = redis::Client::open("redis://127.0.0.1/").expect("Failed to connect to Redis");
+///     let con = client.get_multiplexed_tokio_connection().await.expect("Failed to get Redis connection");
+///     let client_id = ClientID::from(1001);
+///     let conv = Conversation::user(client_id, ClientID::from(1002));
+///
+///     let delivered = advance_delivered(&con, client_id, 1, conv, 42).await.unwrap();
+///     println!("Delivered: {}", delivered);
+/// }
+/// ```
+pub async fn advance_delivered(con: &MultiplexedConnection, clt: ClientID, dev: u32, conv: Conversation, seq: u64) -> Result<u64, BtcmDataError> {
+    let mut con = con.clone();
+    let result: u64 = redis::Script::new(ADVANCE_SCRIPT)
+        .key(get_delivered_key(clt, dev))
+        .arg(conv.to_string())
+        .arg(seq)
+        .invoke_async(&mut con)
+        .await?;
+    Ok(result)
+}
+
+/// 异步函数，前进设备在会话中的已读游标，游标只会前进不会后退。
+/// 已读的消息一定已投递，因此已投递游标会同时前进到`seq`。
+///
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `clt`: 客户端ID。
+/// - `dev`: 设备ID。
+/// - `conv`: 会话。
+/// - `seq`: 已读的消息序号。
+///
+/// # 返回值
+/// 返回更新后的已读游标，`seq`小于当前游标时返回当前游标。
+pub async fn advance_read(con: &MultiplexedConnection, clt: ClientID, dev: u32, conv: Conversation, seq: u64) -> Result<u64, BtcmDataError> {
+    let mut con = con.clone();
+    let result: u64 = redis::Script::new(ADVANCE_SCRIPT)
+        .key(get_read_key(clt, dev))
+        .key(get_delivered_key(clt, dev))
+        .arg(conv.to_string())
+        .arg(seq)
+        .invoke_async(&mut con)
+        .await?;
+    Ok(result)
+}
+
+/// 异步函数，获取设备在会话中的同步游标。
+///
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `clt`: 客户端ID。
+/// - `dev`: 设备ID。
+/// - `conv`: 会话。
+///
+/// # 返回值
+/// 返回设备的同步游标，尚未同步过的会话游标为0。
+pub async fn get_cursor(con: &MultiplexedConnection, clt: ClientID, dev: u32, conv: Conversation) -> Result<SyncCursor, BtcmDataError> {
+    let mut con = con.clone();
+    let field = conv.to_string();
+    let (delivered, read): (Option<u64>, Option<u64>) = redis::pipe()
+        .hget(get_delivered_key(clt, dev), &field)
+        .hget(get_read_key(clt, dev), &field)
+        .query_async(&mut con)
+        .await?;
+    Ok(SyncCursor { delivered: delivered.unwrap_or(0), read: read.unwrap_or(0) })
+}
+
+/// 异步函数，获取设备落后的所有会话，即已投递游标小于会话最大序号的会话，
+/// 重新连接的设备据此只拉取缺失的消息。
+///
+/// 检查的会话包括设备已同步过的会话、客户端的联系人单聊会话和客户端所在群组的群聊会话。
+///
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `clt`: 客户端ID。
+/// - `dev`: 设备ID。
+///
+/// # 返回值
+/// 返回一个Vec<ConversationLag>，包含所有落后的会话。
+///
+/// # 示例
+/// ```rust,no_run
+/// use btcmbase::client::ClientID;
+/// use btcmdata::redis::cursor::get_lagging_conversations;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let client = redis::Client::open("redis://127.0.0.1/").expect("Failed to connect to Redis");
+///     let con = client.get_multiplexed_tokio_connection().await.expect("Failed to get Redis connection");
+///
+///     for lag in get_lagging_conversations(&con, ClientID::from(1001), 1).await.unwrap() {
+///         println!("{}: {} -> {}", lag.conv, lag.cursor.delivered, lag.max_seq);
+///     }
+/// }
+/// ```
+pub async fn get_lagging_conversations(con: &MultiplexedConnection, clt: ClientID, dev: u32) -> Result<Vec<ConversationLag>, BtcmDataError> {
+    let mut rcon = con.clone();
+    let (delivered, read): (HashMap<String, u64>, HashMap<String, u64>) = redis::pipe()
+        .hgetall(get_delivered_key(clt, dev))
+        .hgetall(get_read_key(clt, dev))
+        .query_async(&mut rcon)
+        .await?;
+
+    let mut convs = BTreeSet::new();
+    for field in delivered.keys() {
+        convs.insert(field.parse::<Conversation>()?);
+    }
+    for contact in users::get_user_contacts(con, clt).await? {
+        convs.insert(Conversation::user(clt, ClientID::from(contact)));
+    }
+    for group in groups::groups_of_user(con, clt).await? {
+        convs.insert(Conversation::Group(group));
+    }
+
+    let convs: Vec<Conversation> = convs.into_iter().collect();
+    let max_seqs = sequence::get_max_seqs(con, &convs).await?;
+    let result = convs
+        .into_iter()
+        .zip(max_seqs)
+        .filter_map(|(conv, max_seq)| {
+            let field = conv.to_string();
+            let cursor = SyncCursor {
+                delivered: delivered.get(&field).copied().unwrap_or(0),
+                read: read.get(&field).copied().unwrap_or(0),
+            };
+            (cursor.delivered < max_seq).then_some(ConversationLag { conv, cursor, max_seq })
+        })
+        .collect();
+    Ok(result)
+}
+
+/// 异步函数，删除设备的全部同步游标。
+///
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `clt`: 客户端ID。
+/// - `dev`: 设备ID。
+///
+/// # 返回值
+/// 返回一个Result<bool, BtcmDataError>，表示游标是否存在并被删除。
+pub async fn remove_cursors(con: &MultiplexedConnection, clt: ClientID, dev: u32) -> Result<bool, BtcmDataError> {
+    let mut con = con.clone();
+    let removed: usize = con.del(&[get_delivered_key(clt, dev), get_read_key(clt, dev)]).await?;
+    Ok(removed > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::redis::test_connection;
+
+    #[tokio::test]
+    #[ignore = "requires BTCMDATA_REDIS_URL"]
+    async fn cursors_only_move_forward() {
+        let con = test_connection().await;
+        let mut rcon = con.clone();
+        let clt = ClientID::from(908_001);
+        let conv = Conversation::group(ClientID::from(908_100));
+        remove_cursors(&con, clt, 1).await.unwrap();
+        let _: () = rcon.del(format!("seq:{}", conv)).await.unwrap();
+
+        assert_eq!(advance_delivered(&con, clt, 1, conv, 5).await.unwrap(), 5);
+        assert_eq!(advance_delivered(&con, clt, 1, conv, 3).await.unwrap(), 5);
+        // 已读游标前进时已投递游标跟随前进
+        assert_eq!(advance_read(&con, clt, 1, conv, 7).await.unwrap(), 7);
+        assert_eq!(advance_read(&con, clt, 1, conv, 2).await.unwrap(), 7);
+        assert_eq!(get_cursor(&con, clt, 1, conv).await.unwrap(), SyncCursor { delivered: 7, read: 7 });
+        assert_eq!(get_cursor(&con, clt, 2, conv).await.unwrap(), SyncCursor::default());
+
+        assert!(get_lagging_conversations(&con, clt, 1).await.unwrap().is_empty());
+        sequence::alloc_seq(&con, conv, 9).await.unwrap();
+        let lags = get_lagging_conversations(&con, clt, 1).await.unwrap();
+        assert_eq!(lags, vec![ConversationLag { conv, cursor: SyncCursor { delivered: 7, read: 7 }, max_seq: 9 }]);
+
+        assert!(remove_cursors(&con, clt, 1).await.unwrap());
+        assert_eq!(get_cursor(&con, clt, 1, conv).await.unwrap(), SyncCursor::default());
+    }
+}
@@ -1,6 +1,9 @@
 use std::collections::{HashMap, HashSet};
 use btcmbase::client::ClientID;
-use redis::{aio::MultiplexedConnection, AsyncCommands, RedisResult};
+use redis::{aio::MultiplexedConnection, AsyncCommands};
+use crate::error::BtcmDataError;
+use crate::model::Device;
+use super::users::get_hash_fields;
 
 /// Redis中客户端设备相关键的前缀
 static CLIENT_DEVICE_PREFIX: &str = "client_device:";
@@ -19,25 +22,26 @@ fn get_clt_dev_list_key(clt: ClientID) -> String {
 /// - `devs`: HashSet<u64>，包含要添加的设备ID集合。
 /// 
 /// # 示例
-/// ```rust
+/// ```rust,no_run
 /// use std::collections::HashSet;
 /// use btcmbase::client::ClientID;
-/// use redis::{aio::MultiplexedConnection, AsyncCommands, RedisResult};
+/// use btcmdata::redis::device::add_dev2clt;
 /// 
 /// #[tokio::main]
 /// async fn main() {
 ///     let client = redis::Client::open("redis://127.0.0.1/").expect("Failed to connect to Redis");
-///     let mut con = client.get_async_connection().await.expect("Failed to get Redis connection");
+///     let con = client.get_multiplexed_tokio_connection().await.expect("Failed to get Redis connection");
 ///     let client_id = ClientID::from(1001);
 ///     let devices: HashSet<u64> = [1, 2, 3].iter().cloned().collect();
 ///     
-///     add_dev2clt(&mut con, client_id, &devices).await;
+///     add_dev2clt(&con, client_id, &devices).await.unwrap();
 /// }
 /// ```
-pub async fn add_dev2clt(con: &MultiplexedConnection, clt: ClientID, devs: &HashSet<u64>) {
+pub async fn add_dev2clt(con: &MultiplexedConnection, clt: ClientID, devs: &HashSet<u64>) -> Result<(), BtcmDataError> {
     let mut con = con.clone();
     let key = get_clt_dev_list_key(clt);
-    let _: () = redis::cmd("SADD").arg(key).arg(devs).query_async(&mut con).await.unwrap();
+    let _: () = redis::cmd("SADD").arg(key).arg(devs).query_async(&mut con).await?;
+    Ok(())
 }
 
 /// 异步函数，从客户端的设备列表中删除指定的设备ID集合。
@@ -48,25 +52,26 @@ pub async fn add_dev2clt(con: &MultiplexedConnection, clt: ClientID, devs: &Hash
 /// - `devs`: HashSet<u64>，包含要删除的设备ID集合。
 /// 
 /// # 示例
-/// ```rust
+/// ```rust,no_run
 /// use std::collections::HashSet;
 /// use btcmbase::client::ClientID;
-/// use redis::{aio::MultiplexedConnection, AsyncCommands, RedisResult};
+/// use btcmdata::redis::device::del_dev4clt;
 /// 
 /// #[tokio::main]
 /// async fn main() {
 ///     let client = redis::Client::open("redis://127.0.0.1/").expect("Failed to connect to Redis");
-///     let mut con = client.get_async_connection().await.expect("Failed to get Redis connection");
+///     let con = client.get_multiplexed_tokio_connection().await.expect("Failed to get Redis connection");
 ///     let client_id = ClientID::from(1001);
 ///     let devices_to_remove: HashSet<u64> = [2, 3].iter().cloned().collect();
 ///     
-///     del_dev4clt(&mut con, client_id, &devices_to_remove).await;
+///     del_dev4clt(&con, client_id, &devices_to_remove).await.unwrap();
 /// }
 /// ```
-pub async fn del_dev4clt(con: &MultiplexedConnection, clt: ClientID, devs: &HashSet<u64>) {
+pub async fn del_dev4clt(con: &MultiplexedConnection, clt: ClientID, devs: &HashSet<u64>) -> Result<(), BtcmDataError> {
     let mut con = con.clone();
     let key = get_clt_dev_list_key(clt);
-    let _: () = redis::cmd("SREM").arg(key).arg(devs).query_async(&mut con).await.unwrap();
+    let _: () = redis::cmd("SREM").arg(key).arg(devs).query_async(&mut con).await?;
+    Ok(())
 }
 
 /// 异步函数，获取客户端的设备列表。
@@ -79,26 +84,26 @@ pub async fn del_dev4clt(con: &MultiplexedConnection, clt: ClientID, devs: &Hash
 /// 返回一个HashSet<u64>，包含客户端的所有设备ID。
 /// 
 /// # 示例
-/// ```rust
+/// ```rust,no_run
 /// use std::collections::HashSet;
 /// use btcmbase::client::ClientID;
-/// use redis::{aio::MultiplexedConnection, AsyncCommands, RedisResult};
+/// use btcmdata::redis::device::get_devclt_set;
 /// 
 /// #[tokio::main]
 /// async fn main() {
 ///     let client = redis::Client::open("redis://127.0.0.1/").expect("Failed to connect to Redis");
-///     let mut con = client.get_async_connection().await.expect("Failed to get Redis connection");
+///     let con = client.get_multiplexed_tokio_connection().await.expect("Failed to get Redis connection");
 ///     let client_id = ClientID::from(1001);
 ///     
-///     let dev_set = get_devclt_set(&mut con, client_id).await;
+///     let dev_set = get_devclt_set(&con, client_id).await.unwrap();
 ///     println!("Device set: {:?}", dev_set);
 /// }
 /// ```
-pub async fn get_devclt_set(con: &MultiplexedConnection, clt: ClientID) -> HashSet<u64> {
+pub async fn get_devclt_set(con: &MultiplexedConnection, clt: ClientID) -> Result<HashSet<u64>, BtcmDataError> {
     let mut con = con.clone();
     let key = get_clt_dev_list_key(clt);
-    let result: HashSet<u64> = redis::cmd("SMEMBERS").arg(key).query_async(&mut con).await.unwrap();
-    return result;
+    let result: HashSet<u64> = redis::cmd("SMEMBERS").arg(key).query_async(&mut con).await?;
+    Ok(result)
 }
 
 /// 异步函数，检查客户端的设备列表是否存在。
@@ -108,27 +113,27 @@ pub async fn get_devclt_set(con: &MultiplexedConnection, clt: ClientID) -> HashS
 /// - `clt`: 客户端ID。
 /// 
 /// # 返回值
-/// 返回一个RedisResult<bool>，表示设备列表是否存在。
+/// 返回一个Result<bool, BtcmDataError>，表示设备列表是否存在。
 /// 
 /// # 示例
-/// ```rust
+/// ```rust,no_run
 /// use btcmbase::client::ClientID;
-/// use redis::{aio::MultiplexedConnection, AsyncCommands, RedisResult};
+/// use btcmdata::redis::device::exists_devclt;
 /// 
 /// #[tokio::main]
 /// async fn main() {
 ///     let client = redis::Client::open("redis://127.0.0.1/").expect("Failed to connect to Redis");
-///     let mut con = client.get_async_connection().await.expect("Failed to get Redis connection");
+///     let mut con = client.get_multiplexed_tokio_connection().await.expect("Failed to get Redis connection");
 ///     let client_id = ClientID::from(1001);
 ///     
 ///     let exists = exists_devclt(&mut con, client_id).await.unwrap();
 ///     println!("Device list exists: {}", exists);
 /// }
 /// ```
-pub async fn exists_devclt(con: &mut MultiplexedConnection, clt: ClientID) -> RedisResult<bool> {
+pub async fn exists_devclt(con: &mut MultiplexedConnection, clt: ClientID) -> Result<bool, BtcmDataError> {
     let mut con = con.clone();
     let key = get_clt_dev_list_key(clt);
-    let result: bool = con.exists(key).await.unwrap();
+    let result: bool = con.exists(key).await?;
     Ok(result)
 }
 
@@ -139,27 +144,27 @@ pub async fn exists_devclt(con: &mut MultiplexedConnection, clt: ClientID) -> Re
 /// - `clt`: 客户端ID。
 /// 
 /// # 返回值
-/// 返回一个RedisResult<bool>，表示是否成功删除设备列表。
+/// 返回一个Result<bool, BtcmDataError>，表示是否成功删除设备列表。
 /// 
 /// # 示例
-/// ```rust
+/// ```rust,no_run
 /// use btcmbase::client::ClientID;
-/// use redis::{aio::MultiplexedConnection, AsyncCommands, RedisResult};
+/// use btcmdata::redis::device::remove_devclt_set;
 /// 
 /// #[tokio::main]
 /// async fn main() {
 ///     let client = redis::Client::open("redis://127.0.0.1/").expect("Failed to connect to Redis");
-///     let mut con = client.get_async_connection().await.expect("Failed to get Redis connection");
+///     let mut con = client.get_multiplexed_tokio_connection().await.expect("Failed to get Redis connection");
 ///     let client_id = ClientID::from(1001);
 ///     
 ///     let removed = remove_devclt_set(&mut con, client_id).await.unwrap();
 ///     println!("Device list removed: {}", removed);
 /// }
 /// ```
-pub async fn remove_devclt_set(con: &mut MultiplexedConnection, clt: ClientID) -> RedisResult<bool> {
+pub async fn remove_devclt_set(con: &mut MultiplexedConnection, clt: ClientID) -> Result<bool, BtcmDataError> {
     let mut con = con.clone();
     let key = get_clt_dev_list_key(clt);
-    let result: bool = con.del(key).await.unwrap();
+    let result: bool = con.del(key).await?;
     Ok(result)
 }
 
@@ -169,35 +174,46 @@ fn get_clt_dev_hash_key(clt: ClientID, dev: u32) -> String {
     format!("{}{}:{}", CLIENT_DEVICE_PREFIX, user_id, dev)
 }
 
-/// 异步函数，将设备信息哈希添加到客户端的设备哈希中。
+/// 异步函数，写入客户端指定设备的设备信息哈希，整体替换原有的设备信息。
 /// 
 /// # 参数
 /// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
 /// - `clt`: 客户端ID。
 /// - `dev`: 设备ID。
-/// - `hm`: HashMap<String, String>，包含设备信息的哈希映射。
+/// - `device`: 设备信息。
 /// 
 /// # 示例
-/// ```rust
-/// use std::collections::HashMap;
+/// ```rust,no_run
 /// use btcmbase::client::ClientID;
-/// use redis::{aio::MultiplexedConnection, AsyncCommands, RedisResult};
+/// use btcmdata::model::Device;
+/// use btcmdata::redis::device::add_dev2clt_hash;
 /// 
 /// #[tokio::main]
 /// async fn main() {
 ///     let client = redis::Client::open("redis://127.0.0.1/").expect("Failed to connect to Redis");
-///     let mut con = client.get_async_connection().await.expect("Failed to get Redis connection");
+///     let con = client.get_multiplexed_tokio_connection().await.expect("Failed to get Redis connection");
 ///     let client_id = ClientID::from(1001);
 ///     let device_id = 1;
-///     let device_info: HashMap<String, String> = [("name", "Device1"), ("type", "Smartphone")].iter().cloned().collect();
+///     let mut device = Device::default();
+///     device.set_name(Some("Device1".to_string()));
+///     device.set_kind(Some("Smartphone".to_string()));
 ///     
-///     add_dev2clt_hash(&mut con, client_id, device_id, &device_info).await;
+///     add_dev2clt_hash(&con, client_id, device_id, &device).await.unwrap();
 /// }
 /// ```
-pub async fn add_dev2clt_hash(con: &MultiplexedConnection, clt: ClientID, dev: u32, hm: &HashMap<String, String>) {
+pub async fn add_dev2clt_hash(con: &MultiplexedConnection, clt: ClientID, dev: u32, device: &Device) -> Result<(), BtcmDataError> {
     let mut con = con.clone();
     let user_key = get_clt_dev_hash_key(clt, dev);
-    let _: () = redis::cmd("HSET").arg(user_key).arg(hm).query_async(&mut con).await.unwrap();
+    // 在一个事务中删除旧哈希并写入新字段，避免残留已不存在的字段
+    let _: () = redis::pipe()
+        .atomic()
+        .del(&user_key)
+        .ignore()
+        .hset_multiple(&user_key, &device.to_fields())
+        .ignore()
+        .query_async(&mut con)
+        .await?;
+    Ok(())
 }
 
 /// 异步函数，获取客户端的指定设备信息。
@@ -208,30 +224,132 @@ pub async fn add_dev2clt_hash(con: &MultiplexedConnection, clt: ClientID, dev: u
 /// - `dev`: 设备ID。
 /// 
 /// # 返回值
-/// 返回一个HashMap<String, String>，包含设备信息的哈希映射。
+/// 返回设备信息，设备信息不存在时返回None。
 /// 
 /// # 示例
-/// ```rust
-/// use std::collections::HashMap;
+/// ```rust,no_run
 /// use btcmbase::client::ClientID;
-/// use redis::{aio::MultiplexedConnection, AsyncCommands, RedisResult};
+/// use btcmdata::redis::device::get_device;
 /// 
 /// #[tokio::main]
 /// async fn main() {
 ///     let client = redis::Client::open("redis://127.0.0.1/").expect("Failed to connect to Redis");
-///     let mut con = client.get_async_connection().await.expect("Failed to get Redis connection");
+///     let con = client.get_multiplexed_tokio_connection().await.expect("Failed to get Redis connection");
 ///     let client_id = ClientID::from(1001);
 ///     let device_id = 1;
 ///     
-///     let device_info = get_device(&mut con, client_id, device_id).await;
-///     println!("Device info: {:?}", device_info);
+///     let device = get_device(&con, client_id, device_id).await.unwrap();
+///     println!("Device info: {:?}", device);
 /// }
 /// ```
-pub async fn get_device(con: &MultiplexedConnection, clt: ClientID, dev: u32) -> HashMap<String, String> {
+pub async fn get_device(con: &MultiplexedConnection, clt: ClientID, dev: u32) -> Result<Option<Device>, BtcmDataError> {
     let mut con = con.clone();
     let user_key = get_clt_dev_hash_key(clt, dev);
-    let result: HashMap<String, String> = redis::cmd("HGETALL").arg(user_key).query_async(&mut con).await.unwrap();
-    return result;
+    let result: HashMap<String, String> = redis::cmd("HGETALL").arg(user_key).query_async(&mut con).await?;
+    if result.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(Device::from_fields(result)?))
+}
+
+/// 更新已存在的哈希中的字段，哈希不存在时不写入。
+/// KEYS[1] = 哈希, ARGV = 字段和值
+/// 返回 1 成功, 0 哈希不存在
+static SET_FIELDS_SCRIPT: &str = r"
+if redis.call('EXISTS', KEYS[1]) == 0 then
+    return 0
+end
+redis.call('HSET', KEYS[1], unpack(ARGV))
+return 1
+";
+
+/// 在脚本中更新已存在的哈希中的字段，哈希不存在时返回NotFound
+async fn set_hash_fields(con: &MultiplexedConnection, key: String, fields: &HashMap<String, String>, name: impl FnOnce() -> String) -> Result<(), BtcmDataError> {
+    if fields.is_empty() {
+        return Ok(());
+    }
+    let mut con = con.clone();
+    let script = redis::Script::new(SET_FIELDS_SCRIPT);
+    let mut invocation = script.prepare_invoke();
+    invocation.key(key);
+    for (field, value) in fields {
+        invocation.arg(field).arg(value);
+    }
+    let updated: bool = invocation.invoke_async(&mut con).await?;
+    if !updated {
+        return Err(BtcmDataError::NotFound(name()));
+    }
+    Ok(())
+}
+
+/// 使用HDEL删除哈希中的指定字段
+async fn delete_hash_fields(con: &MultiplexedConnection, key: String, fields: &[&str]) -> Result<usize, BtcmDataError> {
+    if fields.is_empty() {
+        return Ok(0);
+    }
+    let mut con = con.clone();
+    let removed: usize = con.hdel(key, fields).await?;
+    Ok(removed)
+}
+
+/// 异步函数，只读取设备信息中的指定字段。
+/// 
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `clt`: 客户端ID。
+/// - `dev`: 设备ID。
+/// - `fields`: 要读取的字段名。
+/// 
+/// # 返回值
+/// 返回字段名到值的HashMap，不存在的字段不包含在结果中。
+pub async fn get_device_fields(con: &MultiplexedConnection, clt: ClientID, dev: u32, fields: &[&str]) -> Result<HashMap<String, String>, BtcmDataError> {
+    get_hash_fields(con, get_clt_dev_hash_key(clt, dev), fields).await
+}
+
+/// 异步函数，只更新设备信息中的指定字段，例如更新最后在线时间，其余字段保持不变。
+/// 
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `clt`: 客户端ID。
+/// - `dev`: 设备ID。
+/// - `fields`: 要写入的字段名和值。
+/// 
+/// # 返回值
+/// 设备信息不存在时返回`BtcmDataError::NotFound`，字段值不符合`Device`的字段类型时返回`BtcmDataError::TypeMismatch`。
+/// 
+/// # 示例
+/// ```rust,no_run
+/// use std::collections::HashMap;
+/// use btcmbase::client::ClientID;
+/// use btcmdata::redis::device::set_device_fields;
+/// 
+/// #[tokio::main]
+/// async fn main() {
+///     let client = redis::Client::open("redis://127.0.0.1/").expect("Failed to connect to Redis");
+///     let con = client.get_multiplexed_tokio_connection().await.expect("Failed to get Redis connection");
+///     let fields: HashMap<String, String> = [("last_seen".to_string(), "1700000000000".to_string())].into_iter().collect();
+///     
+///     set_device_fields(&con, ClientID::from(1001), 1, &fields).await.unwrap();
+/// }
+/// ```
+pub async fn set_device_fields(con: &MultiplexedConnection, clt: ClientID, dev: u32, fields: &HashMap<String, String>) -> Result<(), BtcmDataError> {
+    Device::check_fields(fields)?;
+    let user_id: u64 = clt.into();
+    set_hash_fields(con, get_clt_dev_hash_key(clt, dev), fields, || format!("device {} of client {}", dev, user_id)).await
+}
+
+/// 异步函数，删除设备信息中的指定字段。
+/// 
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `clt`: 客户端ID。
+/// - `dev`: 设备ID。
+/// - `fields`: 要删除的字段名。
+/// 
+/// # 返回值
+/// 返回实际删除的字段数。
+pub async fn delete_device_fields(con: &MultiplexedConnection, clt: ClientID, dev: u32, fields: &[&str]) -> Result<usize, BtcmDataError> {
+    delete_hash_fields(con, get_clt_dev_hash_key(clt, dev), fields).await
 }
 
 /// 异步函数，检查客户端的指定设备信息是否存在。
@@ -242,17 +360,17 @@ pub async fn get_device(con: &MultiplexedConnection, clt: ClientID, dev: u32) ->
 /// - `dev`: 设备ID。
 /// 
 /// # 返回值
-/// 返回一个RedisResult<bool>，表示设备信息是否存在。
+/// 返回一个Result<bool, BtcmDataError>，表示设备信息是否存在。
 /// 
 /// # 示例
-/// ```rust
+/// ```rust,no_run
 /// use btcmbase::client::ClientID;
-/// use redis::{aio::MultiplexedConnection, AsyncCommands, RedisResult};
+/// use btcmdata::redis::device::exists_device;
 /// 
 /// #[tokio::main]
 /// async fn main() {
 ///     let client = redis::Client::open("redis://127.0.0.1/").expect("Failed to connect to Redis");
-///     let mut con = client.get_async_connection().await.expect("Failed to get Redis connection");
+///     let mut con = client.get_multiplexed_tokio_connection().await.expect("Failed to get Redis connection");
 ///     let client_id = ClientID::from(1001);
 ///     let device_id = 1;
 ///     
@@ -260,10 +378,10 @@ pub async fn get_device(con: &MultiplexedConnection, clt: ClientID, dev: u32) ->
 ///     println!("Device info exists: {}", exists);
 /// }
 /// ```
-pub async fn exists_device(con: &mut MultiplexedConnection, clt: ClientID, dev: u32) -> RedisResult<bool> {
+pub async fn exists_device(con: &mut MultiplexedConnection, clt: ClientID, dev: u32) -> Result<bool, BtcmDataError> {
     let mut con = con.clone();
     let user_key = get_clt_dev_hash_key(clt, dev);
-    let result: bool = con.exists(user_key).await.unwrap();
+    let result: bool = con.exists(user_key).await?;
     Ok(result)
 }
 
@@ -275,17 +393,17 @@ pub async fn exists_device(con: &mut MultiplexedConnection, clt: ClientID, dev:
 /// - `dev`: 设备ID。
 /// 
 /// # 返回值
-/// 返回一个RedisResult<bool>，表示是否成功删除设备信息。
+/// 返回一个Result<bool, BtcmDataError>，表示是否成功删除设备信息。
 /// 
 /// # 示例
-/// ```rust
+/// ```rust,no_run
 /// use btcmbase::client::ClientID;
-/// use redis::{aio::MultiplexedConnection, AsyncCommands, RedisResult};
+/// use btcmdata::redis::device::remove_device;
 /// 
 /// #[tokio::main]
 /// async fn main() {
 ///     let client = redis::Client::open("redis://127.0.0.1/").expect("Failed to connect to Redis");
-///     let mut con = client.get_async_connection().await.expect("Failed to get Redis connection");
+///     let mut con = client.get_multiplexed_tokio_connection().await.expect("Failed to get Redis connection");
 ///     let client_id = ClientID::from(1001);
 ///     let device_id = 1;
 ///     
@@ -293,10 +411,10 @@ pub async fn exists_device(con: &mut MultiplexedConnection, clt: ClientID, dev:
 ///     println!("Device removed: {}", removed);
 /// }
 /// ```
-pub async fn remove_device(con: &mut MultiplexedConnection, clt: ClientID, dev: u32) -> RedisResult<bool> {
+pub async fn remove_device(con: &mut MultiplexedConnection, clt: ClientID, dev: u32) -> Result<bool, BtcmDataError> {
     let mut con = con.clone();
     let user_key = get_clt_dev_hash_key(clt, dev);
-    let result: bool = con.del(user_key).await.unwrap();
+    let result: bool = con.del(user_key).await?;
     Ok(result)
 }
 
@@ -398,4 +516,37 @@ pub async fn remove_device(con: &mut MultiplexedConnection, clt: ClientID, dev:
 //     // let user_key = get_del_user_key(clt);
 //     // let _: () = redis::cmd("HSET").arg(user_key).arg(hm).query_async(&mut con).await.unwrap();
 //     Ok(result)
-// }
\ No newline at end of file
+// }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::redis::test_connection;
+
+    #[tokio::test]
+    #[ignore = "requires BTCMDATA_REDIS_URL"]
+    async fn partial_field_reads_and_updates() {
+        let con = test_connection().await;
+        let mut rcon = con.clone();
+        let clt = ClientID::from(917_001);
+        remove_device(&mut rcon, clt, 1).await.unwrap();
+
+        let fields: HashMap<String, String> = [("last_seen".to_string(), "1000".to_string())].into_iter().collect();
+        assert!(matches!(set_device_fields(&con, clt, 1, &fields).await, Err(BtcmDataError::NotFound(_))));
+        let mut device = Device::default();
+        device.set_os(Some("linux".to_string()));
+        add_dev2clt_hash(&con, clt, 1, &device).await.unwrap();
+
+        // 只写入指定字段，其余字段保持不变
+        set_device_fields(&con, clt, 1, &fields).await.unwrap();
+        let read = get_device_fields(&con, clt, 1, &["last_seen", "os", "name"]).await.unwrap();
+        assert_eq!(read.len(), 2);
+        assert_eq!((read["last_seen"].as_str(), read["os"].as_str()), ("1000", "linux"));
+        let invalid: HashMap<String, String> = [("last_seen".to_string(), "soon".to_string())].into_iter().collect();
+        assert!(matches!(set_device_fields(&con, clt, 1, &invalid).await, Err(BtcmDataError::TypeMismatch(_))));
+
+        assert_eq!(delete_device_fields(&con, clt, 1, &["os", "name"]).await.unwrap(), 1);
+        let device = get_device(&con, clt, 1).await.unwrap().unwrap();
+        assert_eq!((*device.last_seen(), device.os()), (1000, &None));
+        assert!(remove_device(&mut rcon, clt, 1).await.unwrap());
+    }
+}
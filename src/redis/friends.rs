@@ -0,0 +1,514 @@
+use std::collections::{BTreeSet, HashMap};
+use std::time::Duration;
+use btcmbase::client::ClientID;
+use redis::{aio::MultiplexedConnection, AsyncCommands};
+use crate::error::BtcmDataError;
+use crate::model::{FriendRequest, FriendRequestState};
+use crate::now_millis;
+use super::{blocklist, users};
+
+// 好友申请，每对(申请人, 接收人)一个哈希，待处理的申请按过期时间索引
+// friend_req:1001:1002  -> Hash  from/to/state/message/created_at/updated_at/expires_at
+// friend_req_out:1001   -> ZSet  接收人 -> 过期时间(毫秒)，1001发出的待处理申请
+// friend_req_in:1002    -> ZSet  申请人 -> 过期时间(毫秒)，1002收到的待处理申请
+// friend_req_peers:1001 -> Set   与1001之间有申请哈希(任意状态、任意方向)的用户，用于注销时删除已处理的申请
+static FRIEND_REQ_PREFIX: &str = "friend_req:";
+static FRIEND_REQ_OUT_PREFIX: &str = "friend_req_out:";
+static FRIEND_REQ_IN_PREFIX: &str = "friend_req_in:";
+static FRIEND_REQ_PEERS_PREFIX: &str = "friend_req_peers:";
+
+/// 获取好友申请哈希键的函数
+fn get_friend_req_key(from: u64, to: u64) -> String {
+    format!("{}{}:{}", FRIEND_REQ_PREFIX, from, to)
+}
+
+/// 获取用户发出的待处理申请索引键的函数
+fn get_friend_req_out_key(clt: u64) -> String {
+    format!("{}{}", FRIEND_REQ_OUT_PREFIX, clt)
+}
+
+/// 获取用户收到的待处理申请索引键的函数
+fn get_friend_req_in_key(clt: u64) -> String {
+    format!("{}{}", FRIEND_REQ_IN_PREFIX, clt)
+}
+
+/// 获取与用户之间有申请哈希的用户集合键的函数
+fn get_friend_req_peers_key(clt: u64) -> String {
+    format!("{}{}", FRIEND_REQ_PEERS_PREFIX, clt)
+}
+
+/// 发出好友申请:任意一方拉黑了另一方或同一对用户已有未过期的待处理申请时拒绝，否则覆盖旧申请。
+/// KEYS[1] = friend_req, KEYS[2] = 申请人的friend_req_out, KEYS[3] = 接收人的friend_req_in,
+/// KEYS[4] = 申请人的blocklist, KEYS[5] = 接收人的blocklist,
+/// KEYS[6] = 申请人的friend_req_peers, KEYS[7] = 接收人的friend_req_peers
+/// ARGV[1] = 申请人, ARGV[2] = 接收人, ARGV[3] = 当前时间, ARGV[4] = 过期时间, ARGV[5] = 附言(可选)
+/// 返回 1 成功, 0 已有待处理的申请, -1 被拉黑
+static SEND_SCRIPT: &str = r"
+if redis.call('SISMEMBER', KEYS[4], ARGV[2]) == 1 or redis.call('SISMEMBER', KEYS[5], ARGV[1]) == 1 then
+    return -1
+end
+local state = redis.call('HGET', KEYS[1], 'state')
+local expires_at = tonumber(redis.call('HGET', KEYS[1], 'expires_at') or '0')
+if state == 'pending' and expires_at > tonumber(ARGV[3]) then
+    return 0
+end
+redis.call('DEL', KEYS[1])
+redis.call('HSET', KEYS[1], 'from', ARGV[1], 'to', ARGV[2], 'state', 'pending',
+    'created_at', ARGV[3], 'updated_at', ARGV[3], 'expires_at', ARGV[4])
+if ARGV[5] then
+    redis.call('HSET', KEYS[1], 'message', ARGV[5])
+end
+redis.call('ZADD', KEYS[2], ARGV[4], ARGV[2])
+redis.call('ZADD', KEYS[3], ARGV[4], ARGV[1])
+redis.call('SADD', KEYS[6], ARGV[2])
+redis.call('SADD', KEYS[7], ARGV[1])
+return 1
+";
+
+/// 处理待处理的好友申请，同意时在同一个脚本中建立双向联系人关系。
+/// KEYS[1] = friend_req, KEYS[2] = 申请人的friend_req_out, KEYS[3] = 接收人的friend_req_in,
+/// KEYS[4] = 申请人的conts_user, KEYS[5] = 接收人的conts_user, KEYS[6] = 申请人的blocklist, KEYS[7] = 接收人的blocklist
+/// ARGV[1] = 申请人, ARGV[2] = 接收人, ARGV[3] = 当前时间, ARGV[4] = 新状态
+/// 返回 1 成功, 0 申请不存在, -1 申请不是待处理状态, -2 申请已过期(标记为expired), -3 同意时被拉黑(申请保持待处理)
+static TRANSITION_SCRIPT: &str = r"
+local state = redis.call('HGET', KEYS[1], 'state')
+if not state then
+    return 0
+end
+if state ~= 'pending' then
+    return -1
+end
+if ARGV[4] == 'accepted' and
+    (redis.call('SISMEMBER', KEYS[6], ARGV[2]) == 1 or redis.call('SISMEMBER', KEYS[7], ARGV[1]) == 1) then
+    return -3
+end
+redis.call('ZREM', KEYS[2], ARGV[2])
+redis.call('ZREM', KEYS[3], ARGV[1])
+if tonumber(redis.call('HGET', KEYS[1], 'expires_at')) <= tonumber(ARGV[3]) then
+    redis.call('HSET', KEYS[1], 'state', 'expired', 'updated_at', ARGV[3])
+    return -2
+end
+redis.call('HSET', KEYS[1], 'state', ARGV[4], 'updated_at', ARGV[3])
+if ARGV[4] == 'accepted' then
+    redis.call('SADD', KEYS[4], ARGV[2])
+    redis.call('SADD', KEYS[5], ARGV[1])
+end
+return 1
+";
+
+/// 将已过期的待处理申请标记为expired并移出两端的索引，过期时间在读取后被重新发出的申请推迟时跳过。
+/// KEYS[1] = 索引(friend_req_out或friend_req_in), KEYS[2i] = 第i个对方的friend_req, KEYS[2i+1] = 第i个对方的索引(方向相反),
+/// ARGV[1] = 当前时间, ARGV[2] = 索引所属用户, ARGV[3..] = 对方(第i个对方为ARGV[i+2])
+/// 返回标记为过期的申请数
+static EXPIRE_SCRIPT: &str = r"
+local count = 0
+for i = 1, #ARGV - 2 do
+    local other = ARGV[i + 2]
+    local score = redis.call('ZSCORE', KEYS[1], other)
+    if score and tonumber(score) <= tonumber(ARGV[1]) then
+        if redis.call('HGET', KEYS[2 * i], 'state') == 'pending' then
+            redis.call('HSET', KEYS[2 * i], 'state', 'expired', 'updated_at', ARGV[1])
+        end
+        redis.call('ZREM', KEYS[1], other)
+        redis.call('ZREM', KEYS[2 * i + 1], ARGV[2])
+        count = count + 1
+    end
+end
+return count
+";
+
+/// 删除用户发出和收到的所有申请(包括已处理的)及对方索引中的记录。调用方传入的对方必须包含
+/// 两个索引和friend_req_peers中的全部用户，否则说明读取后有新的申请，返回-1，调用方重新读取后重试。
+/// KEYS[1] = 用户的friend_req_out, KEYS[2] = 用户的friend_req_in, KEYS[3] = 用户的friend_req_peers,
+/// 第i个对方依次对应KEYS[5i-1..5i+3] = 用户发给对方的friend_req, 对方发给用户的friend_req,
+/// 对方的friend_req_in, 对方的friend_req_out, 对方的friend_req_peers
+/// ARGV[1] = 用户, ARGV[2..] = 对方(第i个对方为ARGV[i+1])
+/// 返回删除的申请数
+static REMOVE_ALL_SCRIPT: &str = r"
+local known = {}
+for i = 2, #ARGV do
+    known[ARGV[i]] = true
+end
+local others = redis.call('SMEMBERS', KEYS[3])
+for _, key in ipairs({KEYS[1], KEYS[2]}) do
+    for _, other in ipairs(redis.call('ZRANGE', key, 0, -1)) do
+        table.insert(others, other)
+    end
+end
+for _, other in ipairs(others) do
+    if not known[other] then
+        return -1
+    end
+end
+local count = 0
+for i = 1, #ARGV - 1 do
+    local base = 5 * i - 1
+    count = count + redis.call('DEL', KEYS[base], KEYS[base + 1])
+    redis.call('ZREM', KEYS[base + 2], ARGV[1])
+    redis.call('ZREM', KEYS[base + 3], ARGV[1])
+    redis.call('SREM', KEYS[base + 4], ARGV[1])
+end
+redis.call('DEL', KEYS[1], KEYS[2], KEYS[3])
+return count
+";
+
+/// 异步函数，向另一个用户发出好友申请。
+///
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `from`: 申请人。
+/// - `to`: 接收人。
+/// - `message`: 申请附言。
+/// - `ttl`: 申请的有效期，超过有效期未处理的申请变为`Expired`。
+///
+/// # 返回值
+/// 返回新的好友申请；申请人和接收人相同，或双方之间已有同方向未过期的待处理申请时返回
+/// `BtcmDataError::Conflict`，任意一方拉黑了另一方时返回`BtcmDataError::Blocked`。
+///
+/// # 示例
+/// ```rust,no_run
+/// use std::time::Duration;
+/// use btcmbase::client::ClientID;
+/// use btcmdata::redis::friends::{accept_friend_request, send_friend_request};
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let client = redis::Client::open("redis://127.0.0.1/").expect("Failed to connect to Redis");
+///     let con = client.get_multiplexed_tokio_connection().await.expect("Failed to get Redis connection");
+///     let (alice, bob) = (ClientID::from(1001), ClientID::from(1002));
+///
+///     send_friend_request(&con, alice, bob, Some("hi"), Duration::from_secs(7 * 24 * 3600)).await.unwrap();
+///     accept_friend_request(&con, alice, bob).await.unwrap();
+/// }
+/// ```
+pub async fn send_friend_request(con: &MultiplexedConnection, from: ClientID, to: ClientID, message: Option<&str>, ttl: Duration) -> Result<FriendRequest, BtcmDataError> {
+    let mut con = con.clone();
+    let (from_id, to_id): (u64, u64) = (from.into(), to.into());
+    if from_id == to_id {
+        return Err(BtcmDataError::Conflict(format!("user {} cannot send a friend request to itself", from_id)));
+    }
+    let now = now_millis();
+    let expires_at = now.saturating_add(u64::try_from(ttl.as_millis()).unwrap_or(u64::MAX));
+
+    let script = redis::Script::new(SEND_SCRIPT);
+    let mut invocation = script.prepare_invoke();
+    invocation
+        .key(get_friend_req_key(from_id, to_id))
+        .key(get_friend_req_out_key(from_id))
+        .key(get_friend_req_in_key(to_id))
+        .key(blocklist::get_blocklist_key(from))
+        .key(blocklist::get_blocklist_key(to))
+        .key(get_friend_req_peers_key(from_id))
+        .key(get_friend_req_peers_key(to_id))
+        .arg(from_id)
+        .arg(to_id)
+        .arg(now)
+        .arg(expires_at);
+    if let Some(message) = message {
+        invocation.arg(message);
+    }
+    let code: i64 = invocation.invoke_async(&mut con).await?;
+    match code {
+        1 => {}
+        0 => return Err(BtcmDataError::Conflict(format!("friend request from {} to {} is already pending", from_id, to_id))),
+        _ => return Err(BtcmDataError::Blocked(format!("friend request from {} to {} is blocked", from_id, to_id))),
+    }
+    Ok(FriendRequest {
+        from: from_id,
+        to: to_id,
+        state: FriendRequestState::Pending,
+        message: message.map(str::to_string),
+        created_at: now,
+        updated_at: now,
+        expires_at,
+    })
+}
+
+/// 执行一次状态转换并将脚本返回码转换为错误
+async fn transition(con: &MultiplexedConnection, from: ClientID, to: ClientID, state: FriendRequestState) -> Result<(), BtcmDataError> {
+    let mut con = con.clone();
+    let (from_id, to_id): (u64, u64) = (from.into(), to.into());
+    let code: i64 = redis::Script::new(TRANSITION_SCRIPT)
+        .key(get_friend_req_key(from_id, to_id))
+        .key(get_friend_req_out_key(from_id))
+        .key(get_friend_req_in_key(to_id))
+        .key(users::get_user_conts_key(from))
+        .key(users::get_user_conts_key(to))
+        .key(blocklist::get_blocklist_key(from))
+        .key(blocklist::get_blocklist_key(to))
+        .arg(from_id)
+        .arg(to_id)
+        .arg(now_millis())
+        .arg(state.as_str())
+        .invoke_async(&mut con)
+        .await?;
+    match code {
+        1 => Ok(()),
+        0 => Err(BtcmDataError::NotFound(format!("friend request from {} to {}", from_id, to_id))),
+        -2 => Err(BtcmDataError::Conflict(format!("friend request from {} to {} has expired", from_id, to_id))),
+        -3 => Err(BtcmDataError::Blocked(format!("friend request from {} to {} is blocked", from_id, to_id))),
+        _ => Err(BtcmDataError::Conflict(format!("friend request from {} to {} is not pending", from_id, to_id))),
+    }
+}
+
+/// 异步函数，接收人同意好友申请，并在同一个脚本中建立双向联系人关系。
+///
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `from`: 申请人。
+/// - `to`: 接收人。
+///
+/// # 返回值
+/// 申请不存在时返回`BtcmDataError::NotFound`，申请不是待处理状态或已过期时返回`BtcmDataError::Conflict`，
+/// 任意一方拉黑了另一方时返回`BtcmDataError::Blocked`，申请保持待处理。
+pub async fn accept_friend_request(con: &MultiplexedConnection, from: ClientID, to: ClientID) -> Result<(), BtcmDataError> {
+    transition(con, from, to, FriendRequestState::Accepted).await
+}
+
+/// 异步函数，接收人拒绝好友申请。
+///
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `from`: 申请人。
+/// - `to`: 接收人。
+///
+/// # 返回值
+/// 申请不存在时返回`BtcmDataError::NotFound`，申请不是待处理状态或已过期时返回`BtcmDataError::Conflict`。
+pub async fn reject_friend_request(con: &MultiplexedConnection, from: ClientID, to: ClientID) -> Result<(), BtcmDataError> {
+    transition(con, from, to, FriendRequestState::Rejected).await
+}
+
+/// 异步函数，申请人撤回好友申请。
+///
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `from`: 申请人。
+/// - `to`: 接收人。
+///
+/// # 返回值
+/// 申请不存在时返回`BtcmDataError::NotFound`，申请不是待处理状态或已过期时返回`BtcmDataError::Conflict`。
+pub async fn cancel_friend_request(con: &MultiplexedConnection, from: ClientID, to: ClientID) -> Result<(), BtcmDataError> {
+    transition(con, from, to, FriendRequestState::Cancelled).await
+}
+
+/// 异步函数，获取两个用户之间指定方向的好友申请。
+///
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `from`: 申请人。
+/// - `to`: 接收人。
+///
+/// # 返回值
+/// 返回好友申请，不存在时返回None。
+pub async fn get_friend_request(con: &MultiplexedConnection, from: ClientID, to: ClientID) -> Result<Option<FriendRequest>, BtcmDataError> {
+    let mut con = con.clone();
+    let hm: HashMap<String, String> = con.hgetall(get_friend_req_key(from.into(), to.into())).await?;
+    if hm.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(FriendRequest::from_fields(hm, now_millis())?))
+}
+
+/// 读取索引中未过期的申请
+async fn list_pending(con: &MultiplexedConnection, index_key: String, clt: u64, outgoing: bool) -> Result<Vec<FriendRequest>, BtcmDataError> {
+    let mut con = con.clone();
+    let now = now_millis();
+    let others: Vec<u64> = con.zrangebyscore(index_key, format!("({}", now), "+inf").await?;
+    if others.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut pipe = redis::pipe();
+    for other in &others {
+        let (from, to) = if outgoing { (clt, *other) } else { (*other, clt) };
+        pipe.hgetall(get_friend_req_key(from, to));
+    }
+    let hms: Vec<HashMap<String, String>> = pipe.query_async(&mut con).await?;
+    let mut result = Vec::new();
+    for hm in hms.into_iter().filter(|hm| !hm.is_empty()) {
+        let request = FriendRequest::from_fields(hm, now)?;
+        if request.state == FriendRequestState::Pending {
+            result.push(request);
+        }
+    }
+    Ok(result)
+}
+
+/// 异步函数，列出用户收到的所有待处理好友申请，按过期时间从早到晚排序。
+///
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `clt`: 接收人。
+///
+/// # 返回值
+/// 返回一个Vec<FriendRequest>，不包含已过期的申请。
+pub async fn list_incoming_friend_requests(con: &MultiplexedConnection, clt: ClientID) -> Result<Vec<FriendRequest>, BtcmDataError> {
+    let clt: u64 = clt.into();
+    list_pending(con, get_friend_req_in_key(clt), clt, false).await
+}
+
+/// 异步函数，列出用户发出的所有待处理好友申请，按过期时间从早到晚排序。
+///
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `clt`: 申请人。
+///
+/// # 返回值
+/// 返回一个Vec<FriendRequest>，不包含已过期的申请。
+pub async fn list_outgoing_friend_requests(con: &MultiplexedConnection, clt: ClientID) -> Result<Vec<FriendRequest>, BtcmDataError> {
+    let clt: u64 = clt.into();
+    list_pending(con, get_friend_req_out_key(clt), clt, true).await
+}
+
+/// 异步函数，将用户收到和发出的已过期申请标记为`Expired`并移出待处理索引。
+///
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `clt`: 用户ID。
+///
+/// # 返回值
+/// 返回标记为过期的申请数。
+pub async fn expire_friend_requests(con: &MultiplexedConnection, clt: ClientID) -> Result<usize, BtcmDataError> {
+    let mut con = con.clone();
+    let clt: u64 = clt.into();
+    let now = now_millis();
+    let script = redis::Script::new(EXPIRE_SCRIPT);
+    let mut expired = 0;
+    for outgoing in [true, false] {
+        let index_key = if outgoing { get_friend_req_out_key(clt) } else { get_friend_req_in_key(clt) };
+        let others: Vec<u64> = con.zrangebyscore(&index_key, "-inf", now).await?;
+        if others.is_empty() {
+            continue;
+        }
+        let mut invocation = script.prepare_invoke();
+        invocation.key(index_key).arg(now).arg(clt);
+        for other in &others {
+            if outgoing {
+                invocation.key(get_friend_req_key(clt, *other)).key(get_friend_req_in_key(*other));
+            } else {
+                invocation.key(get_friend_req_key(*other, clt)).key(get_friend_req_out_key(*other));
+            }
+            invocation.arg(*other);
+        }
+        let count: usize = invocation.invoke_async(&mut con).await?;
+        expired += count;
+    }
+    Ok(expired)
+}
+
+/// 异步函数，删除用户发出和收到的所有好友申请，包括已处理的申请，用于注销账号。
+///
+/// # 返回值
+/// 返回删除的申请数。
+pub(crate) async fn remove_friend_requests(con: &MultiplexedConnection, clt: ClientID) -> Result<usize, BtcmDataError> {
+    let mut con = con.clone();
+    let clt: u64 = clt.into();
+    let script = redis::Script::new(REMOVE_ALL_SCRIPT);
+    // 先读取所有对方以便把对方的键作为KEYS传给脚本，读取后有新的申请时脚本返回-1，重新读取后重试
+    loop {
+        let (outgoing, incoming, peers): (Vec<u64>, Vec<u64>, Vec<u64>) = redis::pipe()
+            .zrange(get_friend_req_out_key(clt), 0, -1)
+            .zrange(get_friend_req_in_key(clt), 0, -1)
+            .smembers(get_friend_req_peers_key(clt))
+            .query_async(&mut con)
+            .await?;
+        let others: BTreeSet<u64> = outgoing.into_iter().chain(incoming).chain(peers).collect();
+        let mut invocation = script.prepare_invoke();
+        invocation
+            .key(get_friend_req_out_key(clt))
+            .key(get_friend_req_in_key(clt))
+            .key(get_friend_req_peers_key(clt))
+            .arg(clt);
+        for other in &others {
+            invocation
+                .key(get_friend_req_key(clt, *other))
+                .key(get_friend_req_key(*other, clt))
+                .key(get_friend_req_in_key(*other))
+                .key(get_friend_req_out_key(*other))
+                .key(get_friend_req_peers_key(*other))
+                .arg(*other);
+        }
+        let removed: i64 = invocation.invoke_async(&mut con).await?;
+        if removed >= 0 {
+            return Ok(removed as usize);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::redis::test_connection;
+
+    #[tokio::test]
+    #[ignore = "requires BTCMDATA_REDIS_URL"]
+    async fn requests_and_account_cleanup() {
+        let con = test_connection().await;
+        let (alice, bob, carol) = (ClientID::from(913_001), ClientID::from(913_002), ClientID::from(913_003));
+        for clt in [alice, bob, carol] {
+            remove_friend_requests(&con, clt).await.unwrap();
+        }
+        let ttl = Duration::from_secs(60);
+
+        assert!(matches!(send_friend_request(&con, alice, alice, None, ttl).await, Err(BtcmDataError::Conflict(_))));
+        send_friend_request(&con, alice, bob, Some("hi"), ttl).await.unwrap();
+        assert!(matches!(send_friend_request(&con, alice, bob, None, ttl).await, Err(BtcmDataError::Conflict(_))));
+        reject_friend_request(&con, alice, bob).await.unwrap();
+        send_friend_request(&con, carol, alice, None, Duration::ZERO).await.unwrap();
+        assert_eq!(expire_friend_requests(&con, alice).await.unwrap(), 1);
+        assert_eq!(*get_friend_request(&con, carol, alice).await.unwrap().unwrap().state(), FriendRequestState::Expired);
+        send_friend_request(&con, bob, alice, None, ttl).await.unwrap();
+        assert_eq!(list_outgoing_friend_requests(&con, bob).await.unwrap().len(), 1);
+
+        // 已处理的申请也被删除，对方的索引中不再有记录
+        assert_eq!(remove_friend_requests(&con, alice).await.unwrap(), 3);
+        assert!(get_friend_request(&con, alice, bob).await.unwrap().is_none());
+        assert!(get_friend_request(&con, carol, alice).await.unwrap().is_none());
+        assert!(list_outgoing_friend_requests(&con, bob).await.unwrap().is_empty());
+        assert_eq!(remove_friend_requests(&con, bob).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    #[ignore = "requires BTCMDATA_REDIS_URL"]
+    async fn accept_cancel_and_stale_requests() {
+        let mut con = test_connection().await;
+        let (alice, bob, carol) = (ClientID::from(913_004), ClientID::from(913_005), ClientID::from(913_006));
+        for clt in [alice, bob, carol] {
+            remove_friend_requests(&con, clt).await.unwrap();
+            let _: () = con.del(users::get_user_conts_key(clt)).await.unwrap();
+        }
+        let ttl = Duration::from_secs(60);
+
+        // 同意后双方互为联系人，申请从两端的索引中移除
+        send_friend_request(&con, alice, bob, None, ttl).await.unwrap();
+        accept_friend_request(&con, alice, bob).await.unwrap();
+        assert_eq!(*get_friend_request(&con, alice, bob).await.unwrap().unwrap().state(), FriendRequestState::Accepted);
+        assert!(con.sismember::<_, _, bool>(users::get_user_conts_key(alice), 913_005).await.unwrap());
+        assert!(con.sismember::<_, _, bool>(users::get_user_conts_key(bob), 913_004).await.unwrap());
+        assert!(list_outgoing_friend_requests(&con, alice).await.unwrap().is_empty());
+        assert!(list_incoming_friend_requests(&con, bob).await.unwrap().is_empty());
+
+        // 申请人撤回
+        send_friend_request(&con, carol, alice, None, ttl).await.unwrap();
+        cancel_friend_request(&con, carol, alice).await.unwrap();
+        assert_eq!(*get_friend_request(&con, carol, alice).await.unwrap().unwrap().state(), FriendRequestState::Cancelled);
+        assert!(list_incoming_friend_requests(&con, alice).await.unwrap().is_empty());
+
+        // 已拒绝的申请不能再同意
+        send_friend_request(&con, carol, bob, None, ttl).await.unwrap();
+        reject_friend_request(&con, carol, bob).await.unwrap();
+        assert!(matches!(accept_friend_request(&con, carol, bob).await, Err(BtcmDataError::Conflict(_))));
+        assert_eq!(*get_friend_request(&con, carol, bob).await.unwrap().unwrap().state(), FriendRequestState::Rejected);
+
+        // 同意已过期的申请时标记为expired
+        send_friend_request(&con, bob, carol, None, Duration::ZERO).await.unwrap();
+        assert!(matches!(accept_friend_request(&con, bob, carol).await, Err(BtcmDataError::Conflict(_))));
+        assert_eq!(*get_friend_request(&con, bob, carol).await.unwrap().unwrap().state(), FriendRequestState::Expired);
+        assert!(list_incoming_friend_requests(&con, carol).await.unwrap().is_empty());
+        assert!(!con.sismember::<_, _, bool>(users::get_user_conts_key(carol), 913_005).await.unwrap());
+
+        for clt in [alice, bob, carol] {
+            remove_friend_requests(&con, clt).await.unwrap();
+            let _: () = con.del(users::get_user_conts_key(clt)).await.unwrap();
+        }
+    }
+}
@@ -1,10 +1,35 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use btcmbase::client::ClientID;
 
 #[allow(unused_imports)]
-use redis::{aio::MultiplexedConnection, AsyncCommands, RedisResult};
+use redis::{aio::MultiplexedConnection, AsyncCommands};
+use crate::error::BtcmDataError;
+use crate::model::{GroupInfo, GroupRole};
+use super::blocklist;
 
-/// 异步函数，将指定用户添加到指定群组中。
+/// 添加群组成员:任意一个成员拉黑了群主时全部拒绝，没有群组信息(群主)的群组不检查。
+/// KEYS[1] = group, KEYS[2] = group_info, 第i个成员: KEYS[1 + i * 2] = user_groups, KEYS[2 + i * 2] = blocklist
+/// ARGV[1] = 群组ID, ARGV[1 + i] = 第i个成员
+/// 返回 0 成功, 否则返回第一个拉黑了群主的成员的序号i
+static ADD_GROUP_SCRIPT: &str = r"
+local owner = redis.call('HGET', KEYS[2], 'owner')
+if owner then
+    for i = 1, #ARGV - 1 do
+        if redis.call('SISMEMBER', KEYS[2 + i * 2], owner) == 1 then
+            return i
+        end
+    end
+end
+for i = 1, #ARGV - 1 do
+    redis.call('SADD', KEYS[1], ARGV[1 + i])
+    redis.call('SADD', KEYS[1 + i * 2], ARGV[1])
+end
+return 0
+";
+
+/// 异步函数，将指定用户添加到指定群组中，同时维护用户所在群组的反向索引。
+/// 
+/// 任意一个用户拉黑了群主时返回`BtcmDataError::Blocked`，不添加任何用户。
 /// 
 /// # 参数
 /// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
@@ -12,58 +37,97 @@ use redis::{aio::MultiplexedConnection, AsyncCommands, RedisResult};
 /// - `hs`: HashSet<u64>，包含要添加到群组的用户ID集合。
 /// 
 /// # 示例
-/// ```rust
+/// ```rust,no_run
 /// use std::collections::HashSet;
 /// use btcmbase::client::ClientID;
-/// use redis::{aio::MultiplexedConnection, AsyncCommands, RedisResult};
+/// use btcmdata::redis::groups::add_group;
 /// 
 /// #[tokio::main]
 /// async fn main() {
 ///     let client = redis::Client::open("redis://127.0.0.1/").expect("Failed to connect to Redis");
-///     let mut con = client.get_async_connection().await.expect("Failed to get Redis connection");
+///     let con = client.get_multiplexed_tokio_connection().await.expect("Failed to get Redis connection");
 ///     let user_id = ClientID::from(123);
 ///     let users_to_add: HashSet<u64> = [456, 789].iter().cloned().collect();
 ///     
-///     add_group(&mut con, user_id, &users_to_add).await;
+///     add_group(&con, user_id, &users_to_add).await.unwrap();
 /// }
 /// ```
-pub async fn add_group(con: &MultiplexedConnection, clt: ClientID, hs: &HashSet<u64>) {
+pub async fn add_group(con: &MultiplexedConnection, clt: ClientID, hs: &HashSet<u64>) -> Result<(), BtcmDataError> {
+    if hs.is_empty() {
+        return Ok(());
+    }
     let mut con = con.clone();
-    let key = get_group_key(clt);
+    let group_id: u64 = clt.into();
+    let members: Vec<u64> = hs.iter().copied().collect();
     
-    // 使用cmd函数构建一个sadd命令，将HashSet中的数据写入Redis的set结构中
-    let _: () = redis::cmd("SADD").arg(key).arg(hs).query_async(&mut con).await.unwrap();
+    // 在同一个脚本中检查黑名单，并写入成员集合和每个成员的反向索引
+    let script = redis::Script::new(ADD_GROUP_SCRIPT);
+    let mut invocation = script.prepare_invoke();
+    invocation.key(get_group_key(clt)).key(get_group_info_key(clt)).arg(group_id);
+    for member in &members {
+        invocation
+            .key(get_user_groups_key(*member))
+            .key(blocklist::get_blocklist_key(ClientID::from(*member)))
+            .arg(*member);
+    }
+    let blocked: usize = invocation.invoke_async(&mut con).await?;
+    if blocked > 0 {
+        return Err(BtcmDataError::Blocked(format!("user {} has blocked the owner of group {}", members[blocked - 1], group_id)));
+    }
+    Ok(())
 }
 
-/// 异步函数，从指定群组中删除指定用户。
+/// 异步函数，从指定群组中删除指定用户，被删除的管理员同时失去管理员身份。
 /// 
 /// # 参数
 /// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
 /// - `clt`: 指定的用户ID。
 /// - `hs`: HashSet<u64>，包含要从群组中删除的用户ID集合。
 /// 
+/// # 返回值
+/// `hs`中包含群主时拒绝删除，返回`BtcmDataError::Conflict`，需要先转让群主。
+/// 
 /// # 示例
-/// ```rust
+/// ```rust,no_run
 /// use std::collections::HashSet;
 /// use btcmbase::client::ClientID;
-/// use redis::{aio::MultiplexedConnection, AsyncCommands, RedisResult};
+/// use btcmdata::redis::groups::del_group;
 /// 
 /// #[tokio::main]
 /// async fn main() {
 ///     let client = redis::Client::open("redis://127.0.0.1/").expect("Failed to connect to Redis");
-///     let mut con = client.get_async_connection().await.expect("Failed to get Redis connection");
+///     let con = client.get_multiplexed_tokio_connection().await.expect("Failed to get Redis connection");
 ///     let user_id = ClientID::from(123);
 ///     let users_to_remove: HashSet<u64> = [456, 789].iter().cloned().collect();
 ///     
-///     del_group(&mut con, user_id, &users_to_remove).await;
+///     del_group(&con, user_id, &users_to_remove).await.unwrap();
 /// }
 /// ```
-pub async fn del_group(con: &MultiplexedConnection, clt: ClientID, hs: &HashSet<u64>) {
+pub async fn del_group(con: &MultiplexedConnection, clt: ClientID, hs: &HashSet<u64>) -> Result<(), BtcmDataError> {
+    if hs.is_empty() {
+        return Ok(());
+    }
     let mut con = con.clone();
-    let key = get_group_key(clt);
     
-    // 使用cmd函数构建一个srem命令，将HashSet中的数据从Redis的set结构中删除
-    let _: () = redis::cmd("SREM").arg(key).arg(hs).query_async(&mut con).await.unwrap();
+    let group_id: u64 = clt.into();
+    let members: Vec<u64> = hs.iter().copied().collect();
+    
+    // 在脚本中检查群主并从成员集合、管理员集合和成员的反向索引中删除
+    let script = redis::Script::new(DEL_GROUP_SCRIPT);
+    let mut invocation = script.prepare_invoke();
+    invocation
+        .key(get_group_key(clt))
+        .key(get_group_info_key(clt))
+        .key(get_group_admins_key(clt))
+        .arg(group_id);
+    for member in &members {
+        invocation.key(get_user_groups_key(*member)).arg(*member);
+    }
+    let removed: bool = invocation.invoke_async(&mut con).await?;
+    if !removed {
+        return Err(BtcmDataError::Conflict(format!("cannot remove the owner of group {}", group_id)));
+    }
+    Ok(())
 }
 
 /// 异步函数，获取指定群组中的所有用户ID。
@@ -76,28 +140,28 @@ pub async fn del_group(con: &MultiplexedConnection, clt: ClientID, hs: &HashSet<
 /// 返回一个包含群组中所有用户ID的HashSet<u64>。
 /// 
 /// # 示例
-/// ```rust
+/// ```rust,no_run
 /// use std::collections::HashSet;
 /// use btcmbase::client::ClientID;
-/// use redis::{aio::MultiplexedConnection, AsyncCommands, RedisResult};
+/// use btcmdata::redis::groups::get_group;
 /// 
 /// #[tokio::main]
 /// async fn main() {
 ///     let client = redis::Client::open("redis://127.0.0.1/").expect("Failed to connect to Redis");
-///     let mut con = client.get_async_connection().await.expect("Failed to get Redis connection");
+///     let con = client.get_multiplexed_tokio_connection().await.expect("Failed to get Redis connection");
 ///     let user_id = ClientID::from(123);
 ///     
-///     let group_members = get_group(&mut con, user_id).await;
+///     let group_members = get_group(&con, user_id).await.unwrap();
 ///     println!("Group members: {:?}", group_members);
 /// }
 /// ```
-pub async fn get_group(con: &MultiplexedConnection, clt: ClientID) -> HashSet<u64> {
+pub async fn get_group(con: &MultiplexedConnection, clt: ClientID) -> Result<HashSet<u64>, BtcmDataError> {
     let mut con = con.clone();
     let key = get_group_key(clt);
     
     // 使用cmd函数构建一个smembers命令，获取Redis中的set结构中的数据，返回一个HashSet<u64>
-    let result: HashSet<u64> = redis::cmd("SMEMBERS").arg(key).query_async(&mut con).await.unwrap();
-    return result;
+    let result: HashSet<u64> = redis::cmd("SMEMBERS").arg(key).query_async(&mut con).await?;
+    Ok(result)
 }
 
 /// 异步函数，检查指定群组是否存在。
@@ -107,62 +171,561 @@ pub async fn get_group(con: &MultiplexedConnection, clt: ClientID) -> HashSet<u6
 /// - `clt`: 指定的用户ID。
 /// 
 /// # 返回值
-/// 返回一个RedisResult<bool>，表示群组是否存在。
+/// 返回一个Result<bool, BtcmDataError>，表示群组是否存在。
 /// 
 /// # 示例
-/// ```rust
+/// ```rust,no_run
 /// use btcmbase::client::ClientID;
-/// use redis::{aio::MultiplexedConnection, AsyncCommands, RedisResult};
+/// use btcmdata::redis::groups::exists_group;
 /// 
 /// #[tokio::main]
 /// async fn main() {
 ///     let client = redis::Client::open("redis://127.0.0.1/").expect("Failed to connect to Redis");
-///     let mut con = client.get_async_connection().await.expect("Failed to get Redis connection");
+///     let mut con = client.get_multiplexed_tokio_connection().await.expect("Failed to get Redis connection");
 ///     let user_id = ClientID::from(123);
 ///     
-///     let group_exists = exists_group(&mut con, user_id).await;
+///     let group_exists = exists_group(&mut con, user_id).await.unwrap();
 ///     println!("Group exists: {:?}", group_exists);
 /// }
 /// ```
-pub async fn exists_group(con: &mut MultiplexedConnection, clt: ClientID) -> RedisResult<bool> {
+pub async fn exists_group(con: &mut MultiplexedConnection, clt: ClientID) -> Result<bool, BtcmDataError> {
     let mut con = con.clone();
     let group_key = get_group_key(clt);
     
     // 调用redis-rs提供的exists方法，返回一个布尔值
-    let result: bool = con.exists(group_key).await.unwrap();
+    let result: bool = con.exists(group_key).await?;
     Ok(result)
 }
 
-/// 异步函数，从Redis中删除指定群组。
+/// 异步函数，从Redis中删除指定群组，包括群组成员集合、群组信息、管理员集合和成员的反向索引。
 /// 
 /// # 参数
 /// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
 /// - `clt`: 指定的用户ID。
 /// 
 /// # 返回值
-/// 返回一个RedisResult<bool>，表示群组是否成功删除。
+/// 返回一个Result<bool, BtcmDataError>，表示群组是否成功删除。
 /// 
 /// # 示例
-/// ```rust
+/// ```rust,no_run
 /// use btcmbase::client::ClientID;
-/// use redis::{aio::MultiplexedConnection, AsyncCommands, RedisResult};
+/// use btcmdata::redis::groups::remove_group;
 /// 
 /// #[tokio::main]
 /// async fn main() {
 ///     let client = redis::Client::open("redis://127.0.0.1/").expect("Failed to connect to Redis");
-///     let mut con = client.get_async_connection().await.expect("Failed to get Redis connection");
+///     let mut con = client.get_multiplexed_tokio_connection().await.expect("Failed to get Redis connection");
 ///     let user_id = ClientID::from(123);
 ///     
-///     let group_removed = remove_group(&mut con, user_id).await;
+///     let group_removed = remove_group(&mut con, user_id).await.unwrap();
 ///     println!("Group removed: {:?}", group_removed);
 /// }
 /// ```
-pub async fn remove_group(con: &mut MultiplexedConnection, clt: ClientID) -> RedisResult<bool> {
+pub async fn remove_group(con: &mut MultiplexedConnection, clt: ClientID) -> Result<bool, BtcmDataError> {
     let mut con = con.clone();
-    let group_key = get_group_key(clt);
+    let group_id: u64 = clt.into();
     
-    // 调用redis-rs提供的del方法，返回一个布尔值表示是否成功删除
-    let result: bool = con.del(group_key).await.unwrap();
+    // 先读取成员以便把每个成员的反向索引作为KEYS传给脚本，脚本在成员集合被并发修改时返回-1，重新读取后重试
+    let script = redis::Script::new(REMOVE_GROUP_SCRIPT);
+    loop {
+        let members: Vec<u64> = con.smembers(get_group_key(clt)).await?;
+        let mut invocation = script.prepare_invoke();
+        invocation
+            .key(get_group_key(clt))
+            .key(get_group_info_key(clt))
+            .key(get_group_admins_key(clt))
+            .arg(group_id);
+        for member in &members {
+            invocation.key(get_user_groups_key(*member)).arg(*member);
+        }
+        // 返回删除的键数
+        let removed: i64 = invocation.invoke_async(&mut con).await?;
+        if removed >= 0 {
+            return Ok(removed > 0);
+        }
+    }
+}
+
+/// 删除群组成员:成员中包含群主时拒绝删除。
+/// KEYS[1] = group, KEYS[2] = group_info, KEYS[3] = group_admins, KEYS[4..] = 各成员的user_groups,
+/// ARGV[1] = 群组ID, ARGV[2..] = 成员(与KEYS[4..]一一对应)
+static DEL_GROUP_SCRIPT: &str = r"
+local owner = redis.call('HGET', KEYS[2], 'owner')
+for i = 2, #ARGV do
+    if ARGV[i] == owner then
+        return 0
+    end
+end
+for i = 2, #ARGV do
+    redis.call('SREM', KEYS[1], ARGV[i])
+    redis.call('SREM', KEYS[3], ARGV[i])
+    redis.call('SREM', KEYS[i + 2], ARGV[1])
+end
+return 1
+";
+
+/// 删除群组:成员集合与调用方读取的一致时，先从每个成员的反向索引中删除群组，再删除群组的全部键。
+/// 成员集合在读取后被修改时返回-1，调用方重新读取成员后重试。
+/// KEYS[1] = group, KEYS[2] = group_info, KEYS[3] = group_admins, KEYS[4..] = 各成员的user_groups,
+/// ARGV[1] = 群组ID, ARGV[2..] = 成员(与KEYS[4..]一一对应)
+static REMOVE_GROUP_SCRIPT: &str = r"
+if redis.call('SCARD', KEYS[1]) ~= #ARGV - 1 then
+    return -1
+end
+for i = 2, #ARGV do
+    if redis.call('SISMEMBER', KEYS[1], ARGV[i]) == 0 then
+        return -1
+    end
+end
+for i = 2, #ARGV do
+    redis.call('SREM', KEYS[i + 2], ARGV[1])
+end
+return redis.call('DEL', KEYS[1], KEYS[2], KEYS[3])
+";
+
+/// 设置管理员:必须是群组成员且不是群主。
+/// 返回1表示成功，0表示不是群组成员，-1表示是群主。
+/// KEYS[1] = group, KEYS[2] = group_info, KEYS[3] = group_admins, ARGV[1] = 成员
+static PROMOTE_ADMIN_SCRIPT: &str = r"
+if redis.call('SISMEMBER', KEYS[1], ARGV[1]) == 0 then
+    return 0
+end
+if redis.call('HGET', KEYS[2], 'owner') == ARGV[1] then
+    return -1
+end
+redis.call('SADD', KEYS[3], ARGV[1])
+return 1
+";
+
+/// 转让群主:新群主必须是群组成员，原群主成为普通成员，新群主不再是管理员。
+/// 返回1表示成功，0表示新群主不是群组成员，-1表示群组信息不存在。
+/// KEYS[1] = group, KEYS[2] = group_info, KEYS[3] = group_admins, ARGV[1] = 新群主
+static TRANSFER_OWNER_SCRIPT: &str = r"
+if redis.call('EXISTS', KEYS[2]) == 0 then
+    return -1
+end
+if redis.call('SISMEMBER', KEYS[1], ARGV[1]) == 0 then
+    return 0
+end
+redis.call('HSET', KEYS[2], 'owner', ARGV[1])
+redis.call('SREM', KEYS[3], ARGV[1])
+return 1
+";
+
+/// 创建群组:群组信息不存在且没有成员拉黑群主时，在同一个脚本中写入群组信息哈希、成员集合和成员的反向索引。
+/// KEYS[1] = group_info, KEYS[2] = group, 第i个成员: KEYS[1 + i * 2] = user_groups, KEYS[2 + i * 2] = blocklist
+/// ARGV[1] = 字段数n, ARGV[2] = 群组ID, ARGV[3] = 群主, ARGV[4..2n+3] = 字段和值, ARGV[3 + 2n + i] = 第i个成员
+/// 返回 -1 群组已存在, 0 成功, 否则返回第一个拉黑了群主的成员的序号i
+static CREATE_GROUP_SCRIPT: &str = r"
+if redis.call('EXISTS', KEYS[1]) == 1 then
+    return -1
+end
+local n = tonumber(ARGV[1])
+local m = (#KEYS - 2) / 2
+for i = 1, m do
+    if redis.call('SISMEMBER', KEYS[2 + i * 2], ARGV[3]) == 1 then
+        return i
+    end
+end
+redis.call('HSET', KEYS[1], unpack(ARGV, 4, 3 + n * 2))
+for i = 1, m do
+    redis.call('SADD', KEYS[2], ARGV[3 + n * 2 + i])
+    redis.call('SADD', KEYS[1 + i * 2], ARGV[2])
+end
+return 0
+";
+
+/// 修改已存在群组的一个信息字段，未提供值时删除该字段。
+/// KEYS[1] = group_info, ARGV[1] = 字段, ARGV[2] = 值(可选)
+static SET_GROUP_FIELD_SCRIPT: &str = r"
+if redis.call('EXISTS', KEYS[1]) == 0 then
+    return 0
+end
+if #ARGV > 1 then
+    redis.call('HSET', KEYS[1], ARGV[1], ARGV[2])
+else
+    redis.call('HDEL', KEYS[1], ARGV[1])
+end
+return 1
+";
+
+/// 异步函数，创建群组，原子地写入群组信息和成员集合，群主会自动加入成员集合。
+/// 
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `clt`: 群组ID。
+/// - `info`: 群组信息。
+/// - `hs`: HashSet<u64>，包含群组的初始成员ID集合。
+/// 
+/// # 返回值
+/// 群组信息已存在时返回`BtcmDataError::Conflict`；任意一个成员拉黑了群主时返回`BtcmDataError::Blocked`。
+/// 
+/// # 示例
+/// ```rust,no_run
+/// use std::collections::HashSet;
+/// use btcmbase::client::ClientID;
+/// use btcmdata::model::GroupInfo;
+/// use btcmdata::redis::groups::create_group;
+/// 
+/// #[tokio::main]
+/// async fn main() {
+///     let client = redis::Client::open("redis://127.0.0.1/").expect("Failed to connect to Redis");
+///     let con = client.get_multiplexed_tokio_connection().await.expect("Failed to get Redis connection");
+///     let group_id = ClientID::from(3001);
+///     let info = GroupInfo::new("family", ClientID::from(123));
+///     let members: HashSet<u64> = [456, 789].iter().cloned().collect();
+///     
+///     create_group(&con, group_id, &info, &members).await.unwrap();
+/// }
+/// ```
+pub async fn create_group(con: &MultiplexedConnection, clt: ClientID, info: &GroupInfo, hs: &HashSet<u64>) -> Result<(), BtcmDataError> {
+    let mut con = con.clone();
+    let group_id: u64 = clt.into();
+    let fields = info.to_fields();
+    let mut members = hs.clone();
+    members.insert(*info.owner());
+    let members: Vec<u64> = members.into_iter().collect();
+
+    let script = redis::Script::new(CREATE_GROUP_SCRIPT);
+    let mut invocation = script.prepare_invoke();
+    invocation
+        .key(get_group_info_key(clt))
+        .key(get_group_key(clt))
+        .arg(fields.len())
+        .arg(group_id)
+        .arg(*info.owner());
+    for (field, value) in &fields {
+        invocation.arg(*field).arg(value);
+    }
+    for member in &members {
+        invocation
+            .key(get_user_groups_key(*member))
+            .key(blocklist::get_blocklist_key(ClientID::from(*member)))
+            .arg(*member);
+    }
+    let result: i64 = invocation.invoke_async(&mut con).await?;
+    match result {
+        -1 => Err(BtcmDataError::Conflict(format!("group {} already exists", group_id))),
+        0 => Ok(()),
+        i => Err(BtcmDataError::Blocked(format!("user {} has blocked the owner of group {}", members[i as usize - 1], group_id))),
+    }
+}
+
+/// 异步函数，获取群组信息。
+/// 
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `clt`: 群组ID。
+/// 
+/// # 返回值
+/// 返回群组信息，群组信息不存在时返回None。
+pub async fn get_group_info(con: &MultiplexedConnection, clt: ClientID) -> Result<Option<GroupInfo>, BtcmDataError> {
+    let mut con = con.clone();
+    let hm: HashMap<String, String> = con.hgetall(get_group_info_key(clt)).await?;
+    if hm.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(GroupInfo::from_fields(hm)?))
+}
+
+/// 修改群组信息中的一个字段，群组信息不存在时返回NotFound
+async fn set_group_field(con: &MultiplexedConnection, clt: ClientID, field: &str, value: Option<&str>) -> Result<(), BtcmDataError> {
+    let mut con = con.clone();
+    let script = redis::Script::new(SET_GROUP_FIELD_SCRIPT);
+    let mut invocation = script.prepare_invoke();
+    invocation.key(get_group_info_key(clt)).arg(field);
+    if let Some(value) = value {
+        invocation.arg(value);
+    }
+    let updated: bool = invocation.invoke_async(&mut con).await?;
+    if !updated {
+        let group_id: u64 = clt.into();
+        return Err(BtcmDataError::NotFound(format!("group {}", group_id)));
+    }
+    Ok(())
+}
+
+/// 异步函数，修改群组名称。
+/// 
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `clt`: 群组ID。
+/// - `name`: 新的群组名称。
+/// 
+/// # 返回值
+/// 群组信息不存在时返回`BtcmDataError::NotFound`。
+pub async fn set_group_name(con: &MultiplexedConnection, clt: ClientID, name: &str) -> Result<(), BtcmDataError> {
+    set_group_field(con, clt, "name", Some(name)).await
+}
+
+/// 异步函数，修改群组头像，为None时清除头像。
+/// 
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `clt`: 群组ID。
+/// - `avatar`: 新的群组头像。
+/// 
+/// # 返回值
+/// 群组信息不存在时返回`BtcmDataError::NotFound`。
+pub async fn set_group_avatar(con: &MultiplexedConnection, clt: ClientID, avatar: Option<&str>) -> Result<(), BtcmDataError> {
+    set_group_field(con, clt, "avatar", avatar).await
+}
+
+/// 异步函数，修改群组简介，为None时清除简介。
+/// 
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `clt`: 群组ID。
+/// - `description`: 新的群组简介。
+/// 
+/// # 返回值
+/// 群组信息不存在时返回`BtcmDataError::NotFound`。
+pub async fn set_group_description(con: &MultiplexedConnection, clt: ClientID, description: Option<&str>) -> Result<(), BtcmDataError> {
+    set_group_field(con, clt, "description", description).await
+}
+
+/// 异步函数，将群组成员设置为管理员。
+/// 
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `clt`: 群组ID。
+/// - `member`: 成员ID。
+/// 
+/// # 返回值
+/// 成员不在群组中时返回`BtcmDataError::NotFound`，成员是群主时返回`BtcmDataError::Conflict`。
+/// 
+/// # 示例
+/// ```rust,no_run
+/// use btcmbase::client::ClientID;
+/// use btcmdata::redis::groups::promote_admin;
+/// 
+/// #[tokio::main]
+/// async fn main() {
+///     let client = redis::Client::open("redis://127.0.0.1/").expect("Failed to connect to Redis");
+///     let con = client.get_multiplexed_tokio_connection().await.expect("Failed to get Redis connection");
+///     
+///     promote_admin(&con, ClientID::from(3001), ClientID::from(456)).await.unwrap();
+/// }
+/// ```
+pub async fn promote_admin(con: &MultiplexedConnection, clt: ClientID, member: ClientID) -> Result<(), BtcmDataError> {
+    let mut con = con.clone();
+    let member_id: u64 = member.into();
+    let status: i32 = redis::Script::new(PROMOTE_ADMIN_SCRIPT)
+        .key(get_group_key(clt))
+        .key(get_group_info_key(clt))
+        .key(get_group_admins_key(clt))
+        .arg(member_id)
+        .invoke_async(&mut con)
+        .await?;
+    let group_id: u64 = clt.into();
+    match status {
+        1 => Ok(()),
+        0 => Err(BtcmDataError::NotFound(format!("member {} of group {}", member_id, group_id))),
+        _ => Err(BtcmDataError::Conflict(format!("member {} is the owner of group {}", member_id, group_id))),
+    }
+}
+
+/// 异步函数，取消群组成员的管理员身份，成员仍保留在群组中。
+/// 
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `clt`: 群组ID。
+/// - `member`: 成员ID。
+/// 
+/// # 返回值
+/// 返回一个Result<bool, BtcmDataError>，表示成员原来是否是管理员。
+pub async fn demote_admin(con: &MultiplexedConnection, clt: ClientID, member: ClientID) -> Result<bool, BtcmDataError> {
+    let mut con = con.clone();
+    let member_id: u64 = member.into();
+    let removed: bool = con.srem(get_group_admins_key(clt), member_id).await?;
+    Ok(removed)
+}
+
+/// 异步函数，将群主转让给另一个群组成员，原群主成为普通成员。
+/// 
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `clt`: 群组ID。
+/// - `new_owner`: 新群主ID。
+/// 
+/// # 返回值
+/// 群组信息不存在或新群主不在群组中时返回`BtcmDataError::NotFound`。
+pub async fn transfer_owner(con: &MultiplexedConnection, clt: ClientID, new_owner: ClientID) -> Result<(), BtcmDataError> {
+    let mut con = con.clone();
+    let owner_id: u64 = new_owner.into();
+    let status: i32 = redis::Script::new(TRANSFER_OWNER_SCRIPT)
+        .key(get_group_key(clt))
+        .key(get_group_info_key(clt))
+        .key(get_group_admins_key(clt))
+        .arg(owner_id)
+        .invoke_async(&mut con)
+        .await?;
+    let group_id: u64 = clt.into();
+    match status {
+        1 => Ok(()),
+        0 => Err(BtcmDataError::NotFound(format!("member {} of group {}", owner_id, group_id))),
+        _ => Err(BtcmDataError::NotFound(format!("group {}", group_id))),
+    }
+}
+
+/// 异步函数，获取群组成员的角色。
+/// 
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `clt`: 群组ID。
+/// - `member`: 成员ID。
+/// 
+/// # 返回值
+/// 返回成员的角色，不是群组成员时返回None。
+/// 
+/// # 示例
+/// ```rust,no_run
+/// use btcmbase::client::ClientID;
+/// use btcmdata::model::GroupRole;
+/// use btcmdata::redis::groups::get_member_role;
+/// 
+/// #[tokio::main]
+/// async fn main() {
+///     let client = redis::Client::open("redis://127.0.0.1/").expect("Failed to connect to Redis");
+///     let con = client.get_multiplexed_tokio_connection().await.expect("Failed to get Redis connection");
+///     
+///     let role = get_member_role(&con, ClientID::from(3001), ClientID::from(456)).await.unwrap();
+///     println!("Is admin: {}", role == Some(GroupRole::Admin));
+/// }
+/// ```
+pub async fn get_member_role(con: &MultiplexedConnection, clt: ClientID, member: ClientID) -> Result<Option<GroupRole>, BtcmDataError> {
+    let mut con = con.clone();
+    let member_id: u64 = member.into();
+    let (is_member, owner, is_admin): (bool, Option<u64>, bool) = redis::pipe()
+        .sismember(get_group_key(clt), member_id)
+        .hget(get_group_info_key(clt), "owner")
+        .sismember(get_group_admins_key(clt), member_id)
+        .query_async(&mut con)
+        .await?;
+    let role = if !is_member {
+        None
+    } else if owner == Some(member_id) {
+        Some(GroupRole::Owner)
+    } else if is_admin {
+        Some(GroupRole::Admin)
+    } else {
+        Some(GroupRole::Member)
+    };
+    Ok(role)
+}
+
+/// 异步函数，获取群组的所有管理员ID(不包括群主)。
+/// 
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `clt`: 群组ID。
+/// 
+/// # 返回值
+/// 返回一个包含所有管理员ID的HashSet<u64>。
+pub async fn get_group_admins(con: &MultiplexedConnection, clt: ClientID) -> Result<HashSet<u64>, BtcmDataError> {
+    let mut con = con.clone();
+    let result: HashSet<u64> = con.smembers(get_group_admins_key(clt)).await?;
+    Ok(result)
+}
+
+/// 异步函数，获取用户所在的所有群组ID。
+/// 
+/// 结果来自`add_group`/`del_group`/`create_group`/`remove_group`原子维护的反向索引，
+/// 与群组成员集合保持一致；`conts_group:`中保存的是用户自己的群组联系人，两者含义不同。
+/// 反向索引引入之前写入的成员关系需要先执行一次`backfill_user_groups`。
+/// 
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `clt`: 用户ID。
+/// 
+/// # 返回值
+/// 返回一个包含所有群组ID的HashSet<u64>。
+/// 
+/// # 示例
+/// ```rust,no_run
+/// use btcmbase::client::ClientID;
+/// use btcmdata::redis::groups::groups_of_user;
+/// 
+/// #[tokio::main]
+/// async fn main() {
+///     let client = redis::Client::open("redis://127.0.0.1/").expect("Failed to connect to Redis");
+///     let con = client.get_multiplexed_tokio_connection().await.expect("Failed to get Redis connection");
+///     
+///     let groups = groups_of_user(&con, ClientID::from(123)).await.unwrap();
+///     println!("Groups: {:?}", groups);
+/// }
+/// ```
+pub async fn groups_of_user(con: &MultiplexedConnection, clt: ClientID) -> Result<HashSet<u64>, BtcmDataError> {
+    let mut con = con.clone();
+    let user_id: u64 = clt.into();
+    let result: HashSet<u64> = con.smembers(get_user_groups_key(user_id)).await?;
+    Ok(result)
+}
+
+/// 补建反向索引:成员仍在群组中时将群组加入其user_groups。
+/// KEYS[1] = group, KEYS[1 + i] = 第i个成员的user_groups
+/// ARGV[1] = 群组ID, ARGV[1 + i] = 第i个成员
+static BACKFILL_USER_GROUPS_SCRIPT: &str = r"
+for i = 2, #ARGV do
+    if redis.call('SISMEMBER', KEYS[1], ARGV[i]) == 1 then
+        redis.call('SADD', KEYS[i], ARGV[1])
+    end
+end
+return 1
+";
+
+/// 异步函数，根据群组成员集合补建用户所在群组的反向索引(`user_groups:`)。
+/// 
+/// 反向索引引入之前写入的成员关系不在索引中，`groups_of_user`、`common_groups`和注销账号时
+/// 退出群组都依赖该索引，升级后需要执行一次；重复执行没有副作用。
+/// 用SCAN遍历`group:*`，执行期间的成员变化由各函数自行维护索引，已退出群组的成员不会被加入索引。
+/// 
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// 
+/// # 返回值
+/// 返回处理的群组数。
+pub async fn backfill_user_groups(con: &MultiplexedConnection) -> Result<usize, BtcmDataError> {
+    let mut keys: Vec<String> = Vec::new();
+    let mut scan_con = con.clone();
+    let mut iter = scan_con.scan_match::<_, String>(format!("{}*", GROUP_PREFIX)).await?;
+    while let Some(key) = iter.next_item().await {
+        keys.push(key);
+    }
+    drop(iter);
+
+    let mut con = con.clone();
+    let script = redis::Script::new(BACKFILL_USER_GROUPS_SCRIPT);
+    let mut groups = 0;
+    for key in keys {
+        let Ok(group_id) = key[GROUP_PREFIX.len()..].parse::<u64>() else {
+            continue;
+        };
+        let members: Vec<u64> = con.smembers(&key).await?;
+        let mut invocation = script.prepare_invoke();
+        invocation.key(&key).arg(group_id);
+        for member in &members {
+            invocation.key(get_user_groups_key(*member)).arg(*member);
+        }
+        let _: () = invocation.invoke_async(&mut con).await?;
+        groups += 1;
+    }
+    Ok(groups)
+}
+
+/// 异步函数，获取两个用户共同所在的群组ID。
+/// 
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `a`: 第一个用户ID。
+/// - `b`: 第二个用户ID。
+/// 
+/// # 返回值
+/// 返回一个包含共同群组ID的HashSet<u64>。
+pub async fn common_groups(con: &MultiplexedConnection, a: ClientID, b: ClientID) -> Result<HashSet<u64>, BtcmDataError> {
+    let mut con = con.clone();
+    let (a, b): (u64, u64) = (a.into(), b.into());
+    let result: HashSet<u64> = con.sinter(&[get_user_groups_key(a), get_user_groups_key(b)]).await?;
     Ok(result)
 }
 
@@ -178,7 +741,7 @@ static GROUP_PREFIX: &str = "group:";
 /// 返回一个字符串，表示与指定用户ID相关的群组键。
 /// 
 /// # 示例
-/// ```rust
+/// ```rust,ignore
 /// use btcmbase::client::ClientID;
 /// 
 /// let user_id = ClientID::from(123);
@@ -191,6 +754,32 @@ fn get_group_key(clt: ClientID) -> String {
     format!("{}{}", GROUP_PREFIX, group_id)
 }
 
+/// 静态变量，表示群组管理员键的前缀。
+static GROUP_ADMINS_PREFIX: &str = "group_admins:";
+
+/// 获取群组管理员键的函数。
+fn get_group_admins_key(clt: ClientID) -> String {
+    let group_id: u64 = clt.into();
+    format!("{}{}", GROUP_ADMINS_PREFIX, group_id)
+}
+
+/// 静态变量，表示用户所在群组(反向索引)键的前缀。
+static USER_GROUPS_PREFIX: &str = "user_groups:";
+
+/// 获取用户所在群组键的函数。
+fn get_user_groups_key(user_id: u64) -> String {
+    format!("{}{}", USER_GROUPS_PREFIX, user_id)
+}
+
+/// 静态变量，表示群组信息键的前缀。
+static GROUP_INFO_PREFIX: &str = "group_info:";
+
+/// 获取群组信息键的函数。
+fn get_group_info_key(clt: ClientID) -> String {
+    let group_id: u64 = clt.into();
+    format!("{}{}", GROUP_INFO_PREFIX, group_id)
+}
+
 // use std::collections::HashSet;
 // use btcmbase::client::ClientID;
 
@@ -244,3 +833,93 @@ fn get_group_key(clt: ClientID) -> String {
 //     format!("{}{}", GROUP_PREFIX, group_id)
 // }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::redis::test_connection;
+
+    #[tokio::test]
+    #[ignore = "requires BTCMDATA_REDIS_URL"]
+    async fn info_is_kept_next_to_members() {
+        let con = test_connection().await;
+        let mut rcon = con.clone();
+        let group = ClientID::from(909_100);
+        remove_group(&mut rcon, group).await.unwrap();
+
+        let mut info = GroupInfo::new("family", ClientID::from(909_001));
+        info.set_avatar(Some("a.png".to_string()));
+        create_group(&con, group, &info, &HashSet::from([909_002])).await.unwrap();
+        assert_eq!(get_group_info(&con, group).await.unwrap(), Some(info.clone()));
+        assert_eq!(get_group(&con, group).await.unwrap(), HashSet::from([909_001, 909_002]));
+        assert!(matches!(create_group(&con, group, &info, &HashSet::new()).await, Err(BtcmDataError::Conflict(_))));
+
+        set_group_name(&con, group, "friends").await.unwrap();
+        set_group_avatar(&con, group, None).await.unwrap();
+        set_group_description(&con, group, Some("weekend")).await.unwrap();
+        let updated = get_group_info(&con, group).await.unwrap().unwrap();
+        assert_eq!((updated.name().as_str(), updated.avatar(), updated.description().as_deref()), ("friends", &None, Some("weekend")));
+
+        assert!(remove_group(&mut rcon, group).await.unwrap());
+        assert_eq!(get_group_info(&con, group).await.unwrap(), None);
+        assert!(matches!(set_group_name(&con, group, "gone").await, Err(BtcmDataError::NotFound(_))));
+    }
+
+    #[tokio::test]
+    #[ignore = "requires BTCMDATA_REDIS_URL"]
+    async fn owner_transfer_and_admins() {
+        let con = test_connection().await;
+        let mut rcon = con.clone();
+        let group = ClientID::from(910_100);
+        let (owner, admin, member) = (ClientID::from(910_001), ClientID::from(910_002), ClientID::from(910_003));
+        remove_group(&mut rcon, group).await.unwrap();
+        create_group(&con, group, &GroupInfo::new("roles", owner), &HashSet::from([910_002, 910_003])).await.unwrap();
+
+        promote_admin(&con, group, admin).await.unwrap();
+        assert!(matches!(promote_admin(&con, group, owner).await, Err(BtcmDataError::Conflict(_))));
+        assert!(matches!(promote_admin(&con, group, ClientID::from(910_004)).await, Err(BtcmDataError::NotFound(_))));
+        assert_eq!(get_member_role(&con, group, owner).await.unwrap(), Some(GroupRole::Owner));
+        assert_eq!(get_member_role(&con, group, admin).await.unwrap(), Some(GroupRole::Admin));
+        assert_eq!(get_member_role(&con, group, member).await.unwrap(), Some(GroupRole::Member));
+        assert!(matches!(del_group(&con, group, &HashSet::from([910_001])).await, Err(BtcmDataError::Conflict(_))));
+
+        // 管理员成为群主后不再是管理员，原群主成为普通成员，可以被移出群组
+        assert!(matches!(transfer_owner(&con, group, ClientID::from(910_004)).await, Err(BtcmDataError::NotFound(_))));
+        transfer_owner(&con, group, admin).await.unwrap();
+        assert_eq!(get_member_role(&con, group, admin).await.unwrap(), Some(GroupRole::Owner));
+        assert_eq!(get_member_role(&con, group, owner).await.unwrap(), Some(GroupRole::Member));
+        assert!(get_group_admins(&con, group).await.unwrap().is_empty());
+        del_group(&con, group, &HashSet::from([910_001])).await.unwrap();
+        assert_eq!(get_member_role(&con, group, owner).await.unwrap(), None);
+
+        assert!(!demote_admin(&con, group, member).await.unwrap());
+        remove_group(&mut rcon, group).await.unwrap();
+        assert!(matches!(transfer_owner(&con, group, member).await, Err(BtcmDataError::NotFound(_))));
+    }
+
+    #[tokio::test]
+    #[ignore = "requires BTCMDATA_REDIS_URL"]
+    async fn user_groups_follow_membership() {
+        let con = test_connection().await;
+        let mut rcon = con.clone();
+        let (a, b) = (ClientID::from(911_001), ClientID::from(911_002));
+        let (first, second) = (ClientID::from(911_100), ClientID::from(911_101));
+        for group in [first, second] {
+            remove_group(&mut rcon, group).await.unwrap();
+        }
+
+        add_group(&con, first, &HashSet::from([911_001, 911_002])).await.unwrap();
+        create_group(&con, second, &GroupInfo::new("second", a), &HashSet::new()).await.unwrap();
+        assert_eq!(groups_of_user(&con, a).await.unwrap(), HashSet::from([911_100, 911_101]));
+        assert_eq!(common_groups(&con, a, b).await.unwrap(), HashSet::from([911_100]));
+
+        del_group(&con, first, &HashSet::from([911_002])).await.unwrap();
+        assert!(groups_of_user(&con, b).await.unwrap().is_empty());
+        assert!(common_groups(&con, a, b).await.unwrap().is_empty());
+
+        // 删除群组时从所有成员的反向索引中移除
+        assert!(remove_group(&mut rcon, first).await.unwrap());
+        assert!(remove_group(&mut rcon, second).await.unwrap());
+        assert!(groups_of_user(&con, a).await.unwrap().is_empty());
+    }
+}
@@ -1,7 +1,238 @@
+use btcmbase::client::ClientID;
+use redis::{aio::MultiplexedConnection, AsyncCommands};
+use redis::streams::{StreamMaxlen, StreamRangeReply};
+use crate::error::BtcmDataError;
 
 // 发给1001 client的所有设备的信息存储在收件箱里
 // 每个收件箱里只存储XXX条最新信息,超过的将会被丢掉
 // inbox:clientid:deviceid
-// inbox:1001
-#[allow(dead_code)]
+// inbox:1001:1
+//
+// 收件箱使用Redis Stream存储，消息ID(形如"1700000000000-0")单调递增，同时作为读取游标使用
 static INBOX_PREFIX:  &str = "inbox:";
+
+/// 消息内容在Stream条目中的字段名
+static INBOX_FIELD: &str = "msg";
+
+/// 每个收件箱默认保留的最新消息条数
+pub const DEFAULT_INBOX_CAPACITY: usize = 1000;
+
+/// 根据客户端ID和设备ID获取收件箱键的函数
+fn get_inbox_key(clt: ClientID, dev: u32) -> String {
+    let user_id: u64 = clt.into();
+    format!("{}{}:{}", INBOX_PREFIX, user_id, dev)
+}
+
+/// 收件箱中的一条消息
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InboxMessage {
+    /// 消息ID，同时作为读取游标
+    pub id: String,
+    /// 消息内容
+    pub payload: Vec<u8>,
+}
+
+/// 异步函数，向客户端指定设备的收件箱中写入一条消息，并将收件箱裁剪到最多`cap`条最新消息。
+///
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `clt`: 客户端ID。
+/// - `dev`: 设备ID。
+/// - `payload`: 消息内容。
+/// - `cap`: 收件箱容量，超出时最旧的消息被丢弃，通常为`DEFAULT_INBOX_CAPACITY`。
+///
+/// # 返回值
+/// 返回新消息的ID。
+///
+/// # 示例
+/// ```rust,no_run
+/// use btcmbase::client::ClientID;
+/// use btcmdata::redis::inbox::{push_inbox, DEFAULT_INBOX_CAPACITY};
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let client = redis::Client::open("redis://127.0.0.1/").expect("Failed to connect to Redis");
+///     let con = client.get_multiplexed_tokio_connection().await.expect("Failed to get Redis connection");
+///     let client_id = ClientID::from(1001);
+///
+///     let id = push_inbox(&con, client_id, 1, b"hello", DEFAULT_INBOX_CAPACITY).await.unwrap();
+///     println!("Message id: {}", id);
+/// }
+/// ```
+pub async fn push_inbox(con: &MultiplexedConnection, clt: ClientID, dev: u32, payload: &[u8], cap: usize) -> Result<String, BtcmDataError> {
+    let mut con = con.clone();
+    let key = get_inbox_key(clt, dev);
+
+    // 使用精确的MAXLEN，保证收件箱中不会超过cap条消息
+    let id: String = con.xadd_maxlen(key, StreamMaxlen::Equals(cap), "*", &[(INBOX_FIELD, payload)]).await?;
+    Ok(id)
+}
+
+/// 异步函数，读取收件箱中游标之后的消息，按消息ID从旧到新排列。
+///
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `clt`: 客户端ID。
+/// - `dev`: 设备ID。
+/// - `after`: 游标，即上次读到的最后一条消息ID，不包含该消息；为None时从最旧的消息开始读取。
+/// - `count`: 最多读取的消息条数。
+///
+/// # 返回值
+/// 返回一个Vec<InboxMessage>，包含读取到的消息。
+///
+/// # 示例
+/// ```rust,no_run
+/// use btcmbase::client::ClientID;
+/// use btcmdata::redis::inbox::read_inbox;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let client = redis::Client::open("redis://127.0.0.1/").expect("Failed to connect to Redis");
+///     let con = client.get_multiplexed_tokio_connection().await.expect("Failed to get Redis connection");
+///     let client_id = ClientID::from(1001);
+///
+///     let first = read_inbox(&con, client_id, 1, None, 100).await.unwrap();
+///     if let Some(last) = first.last() {
+///         let next = read_inbox(&con, client_id, 1, Some(&last.id), 100).await.unwrap();
+///         println!("Next page: {:?}", next);
+///     }
+/// }
+/// ```
+pub async fn read_inbox(con: &MultiplexedConnection, clt: ClientID, dev: u32, after: Option<&str>, count: usize) -> Result<Vec<InboxMessage>, BtcmDataError> {
+    let mut con = con.clone();
+    let key = get_inbox_key(clt, dev);
+
+    // "(id"表示不包含游标本身的区间(Redis 6.2+)
+    let start = match after {
+        Some(id) => format!("({}", id),
+        None => "-".to_string(),
+    };
+    let reply: StreamRangeReply = con.xrange_count(key, start, "+", count).await?;
+    reply
+        .ids
+        .into_iter()
+        .map(|entry| match entry.get::<Vec<u8>>(INBOX_FIELD) {
+            Some(payload) => Ok(InboxMessage { id: entry.id, payload }),
+            None => Err(BtcmDataError::TypeMismatch(format!("inbox entry {} has no {} field", entry.id, INBOX_FIELD))),
+        })
+        .collect()
+}
+
+/// 异步函数，确认(删除)收件箱中已投递的消息。
+///
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `clt`: 客户端ID。
+/// - `dev`: 设备ID。
+/// - `ids`: 已投递的消息ID。
+///
+/// # 返回值
+/// 返回实际删除的消息条数。
+///
+/// # 示例
+/// ```rust,no_run
+/// use btcmbase::client::ClientID;
+/// use btcmdata::redis::inbox::ack_inbox;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let client = redis::Client::open("redis://127.0.0.1/").expect("Failed to connect to Redis");
+///     let con = client.get_multiplexed_tokio_connection().await.expect("Failed to get Redis connection");
+///     let client_id = ClientID::from(1001);
+///
+///     let removed = ack_inbox(&con, client_id, 1, &["1700000000000-0".to_string()]).await.unwrap();
+///     println!("Acknowledged: {}", removed);
+/// }
+/// ```
+pub async fn ack_inbox(con: &MultiplexedConnection, clt: ClientID, dev: u32, ids: &[String]) -> Result<usize, BtcmDataError> {
+    if ids.is_empty() {
+        return Ok(0);
+    }
+    let mut con = con.clone();
+    let key = get_inbox_key(clt, dev);
+    let removed: usize = con.xdel(key, ids).await?;
+    Ok(removed)
+}
+
+/// 异步函数，将收件箱裁剪到最多`cap`条最新消息，用于调低容量后的整理。
+///
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `clt`: 客户端ID。
+/// - `dev`: 设备ID。
+/// - `cap`: 收件箱容量。
+///
+/// # 返回值
+/// 返回被丢弃的消息条数。
+pub async fn trim_inbox(con: &MultiplexedConnection, clt: ClientID, dev: u32, cap: usize) -> Result<usize, BtcmDataError> {
+    let mut con = con.clone();
+    let key = get_inbox_key(clt, dev);
+    let trimmed: usize = con.xtrim(key, StreamMaxlen::Equals(cap)).await?;
+    Ok(trimmed)
+}
+
+/// 异步函数，获取收件箱中的消息条数。
+///
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `clt`: 客户端ID。
+/// - `dev`: 设备ID。
+///
+/// # 返回值
+/// 返回收件箱中的消息条数。
+pub async fn inbox_len(con: &MultiplexedConnection, clt: ClientID, dev: u32) -> Result<usize, BtcmDataError> {
+    let mut con = con.clone();
+    let key = get_inbox_key(clt, dev);
+    let len: usize = con.xlen(key).await?;
+    Ok(len)
+}
+
+/// 异步函数，删除客户端指定设备的整个收件箱。
+///
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `clt`: 客户端ID。
+/// - `dev`: 设备ID。
+///
+/// # 返回值
+/// 返回一个Result<bool, BtcmDataError>，表示收件箱是否存在并被删除。
+pub async fn remove_inbox(con: &MultiplexedConnection, clt: ClientID, dev: u32) -> Result<bool, BtcmDataError> {
+    let mut con = con.clone();
+    let key = get_inbox_key(clt, dev);
+    let result: bool = con.del(key).await?;
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::redis::test_connection;
+
+    #[tokio::test]
+    #[ignore = "requires BTCMDATA_REDIS_URL"]
+    async fn capped_and_read_after_cursor() {
+        let con = test_connection().await;
+        let clt = ClientID::from(905_001);
+        remove_inbox(&con, clt, 1).await.unwrap();
+
+        // 超出容量时丢弃最旧的消息
+        let mut ids = Vec::new();
+        for payload in [b"m1", b"m2", b"m3", b"m4"] {
+            ids.push(push_inbox(&con, clt, 1, payload, 3).await.unwrap());
+        }
+        assert_eq!(inbox_len(&con, clt, 1).await.unwrap(), 3);
+        let all = read_inbox(&con, clt, 1, None, 10).await.unwrap();
+        assert_eq!(all.iter().map(|m| m.payload.as_slice()).collect::<Vec<_>>(), vec![b"m2", b"m3", b"m4"]);
+
+        // 游标本身不包含在结果中
+        let next = read_inbox(&con, clt, 1, Some(&ids[1]), 10).await.unwrap();
+        assert_eq!(next.iter().map(|m| m.id.clone()).collect::<Vec<_>>(), ids[2..].to_vec());
+        assert_eq!(read_inbox(&con, clt, 1, Some(&ids[1]), 1).await.unwrap().len(), 1);
+        assert!(read_inbox(&con, clt, 1, Some(&ids[3]), 10).await.unwrap().is_empty());
+
+        assert_eq!(ack_inbox(&con, clt, 1, &ids[1..3]).await.unwrap(), 2);
+        assert_eq!(trim_inbox(&con, clt, 1, 0).await.unwrap(), 1);
+        assert_eq!(inbox_len(&con, clt, 1).await.unwrap(), 0);
+        remove_inbox(&con, clt, 1).await.unwrap();
+    }
+}
@@ -3,6 +3,16 @@ pub mod groups;
 pub mod inbox;
 pub mod outbox;
 pub mod device;
+pub mod store;
+pub mod sequence;
+pub mod cursor;
+pub mod friends;
+pub mod blocklist;
+pub mod remarks;
+pub mod account;
+pub mod presence;
+pub mod route;
+pub mod node;
 
 
 use std::sync::Arc;
@@ -17,8 +27,8 @@ use once_cell::sync::OnceCell;
 ///
 /// # Examples
 ///
-/// ```rust
-/// use redis_db::init_redis_database;
+/// ```rust,no_run
+/// use btcmdata::redis::{get_redis_client, get_redis_connect, init_redis_database};
 ///
 /// #[tokio::main]
 /// async fn main() {
@@ -27,8 +37,8 @@ use once_cell::sync::OnceCell;
 ///     let manager = init_redis_database(redis_url).await.expect("Failed to initialize Redis database.");
 ///
 ///     // Access the Redis client and connection
-///     let client = manager.client;
-///     let connection = manager.connect;
+///     let client = get_redis_client().expect("Redis client");
+///     let connection = get_redis_connect().expect("Redis connection");
 ///
 ///     // Perform database operations...
 /// }
@@ -57,8 +67,8 @@ static SINGLETON_REDIS_DB_MANAGER: OnceCell<Arc<RedisDBManager>> = OnceCell::new
 ///
 /// # Examples
 ///
-/// ```rust
-/// use redis_db::init_redis_database;
+/// ```rust,no_run
+/// use btcmdata::redis::init_redis_database;
 ///
 /// #[tokio::main]
 /// async fn main() {
@@ -91,8 +101,8 @@ pub async fn init_redis_database(redis_url: &str) -> Result<Arc<RedisDBManager>,
 ///
 /// # Examples
 ///
-/// ```rust
-/// use redis_db::get_redis_dbmanager;
+/// ```rust,no_run
+/// use btcmdata::redis::get_redis_dbmanager;
 ///
 /// #[tokio::main]
 /// async fn main() {
@@ -116,8 +126,8 @@ pub fn get_redis_dbmanager() -> Option<Arc<RedisDBManager>> {
 ///
 /// # Examples
 ///
-/// ```rust
-/// use redis_db::get_redis_client;
+/// ```rust,no_run
+/// use btcmdata::redis::get_redis_client;
 ///
 /// #[tokio::main]
 /// async fn main() {
@@ -141,8 +151,8 @@ pub fn get_redis_client() -> Option<redis::Client> {
 ///
 /// # Examples
 ///
-/// ```rust
-/// use redis_db::get_redis_connect;
+/// ```rust,no_run
+/// use btcmdata::redis::get_redis_connect;
 ///
 /// #[tokio::main]
 /// async fn main() {
@@ -156,3 +166,20 @@ pub fn get_redis_client() -> Option<redis::Client> {
 pub fn get_redis_connect() -> Option<MultiplexedConnection> {
     SINGLETON_REDIS_DB_MANAGER.get().map(|manager| manager.connect.clone())
 }
+
+/// 测试用:连接`BTCMDATA_REDIS_URL`指定的Redis，依赖Redis的测试默认忽略，例如:
+/// `BTCMDATA_REDIS_URL="redis://127.0.0.1/" cargo test -- --ignored`
+/// 未设置环境变量时panic，不会静默跳过。需要订阅时使用返回的Client建立新连接
+#[cfg(test)]
+pub(crate) async fn test_client() -> (redis::Client, MultiplexedConnection) {
+    let url = std::env::var("BTCMDATA_REDIS_URL").expect("BTCMDATA_REDIS_URL is not set");
+    let client = redis::Client::open(url).expect("Invalid Redis URL");
+    let con = client.get_multiplexed_tokio_connection().await.expect("Failed to connect to Redis");
+    (client, con)
+}
+
+/// 测试用:只需要命令连接时使用，见`test_client`
+#[cfg(test)]
+pub(crate) async fn test_connection() -> MultiplexedConnection {
+    test_client().await.1
+}
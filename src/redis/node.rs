@@ -0,0 +1,428 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+use futures_util::{Stream, StreamExt};
+use redis::{aio::MultiplexedConnection, AsyncCommands};
+use crate::error::BtcmDataError;
+use crate::model::parse_field;
+use crate::now_millis;
+
+// 服务器节点注册表，节点注册后定期续约，租约到期的节点视为下线
+// node:gw-01  -> Hash  address/role/capacity/registered_at，键的过期时间为租约到期时间
+// nodes       -> ZSet  节点ID -> 租约到期时间(毫秒)
+//
+// 节点加入和离开时向NODE_EVENTS_CHANNEL发布"join:<节点ID>"或"leave:<节点ID>"
+static NODE_PREFIX: &str = "node:";
+static NODES_KEY: &str = "nodes";
+static NODE_EVENTS_CHANNEL: &str = "node_events";
+
+/// 节点默认的租约时长，节点应以明显短于该值的间隔续约
+pub const DEFAULT_NODE_LEASE: Duration = Duration::from_secs(30);
+
+/// 获取节点信息哈希键的函数
+fn get_node_key(id: &str) -> String {
+    format!("{}{}", NODE_PREFIX, id)
+}
+
+/// 节点在集群中的角色
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NodeRole {
+    /// 接入客户端长连接的网关
+    Gateway,
+    /// 在网关之间转发消息的路由节点
+    Router,
+}
+
+impl NodeRole {
+    /// 角色在Redis哈希中的字符串形式
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NodeRole::Gateway => "gateway",
+            NodeRole::Router => "router",
+        }
+    }
+}
+
+impl fmt::Display for NodeRole {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for NodeRole {
+    type Err = BtcmDataError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "gateway" => Ok(NodeRole::Gateway),
+            "router" => Ok(NodeRole::Router),
+            _ => Err(BtcmDataError::TypeMismatch(format!("invalid node role: {}", s))),
+        }
+    }
+}
+
+/// 一个已注册的节点
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeInfo {
+    /// 节点ID，同时用作`route`中的节点ID
+    pub id: String,
+    /// 其他节点连接该节点的地址，如"10.0.0.1:7000"
+    pub address: String,
+    pub role: NodeRole,
+    /// 节点的容量，如可接入的最大连接数，由调用方约定含义
+    pub capacity: u32,
+    /// 注册的时间(毫秒)
+    pub registered_at: u64,
+    /// 租约的到期时间(毫秒)
+    pub expires_at: u64,
+}
+
+impl NodeInfo {
+    /// 从哈希字段解析节点信息
+    fn from_fields(id: String, hm: HashMap<String, String>, expires_at: u64) -> Result<Self, BtcmDataError> {
+        let role = hm
+            .get("role")
+            .ok_or_else(|| BtcmDataError::TypeMismatch(format!("node {} has no role", id)))?
+            .parse()?;
+        Ok(NodeInfo {
+            address: hm.get("address").cloned().unwrap_or_default(),
+            role,
+            capacity: parse_field(&hm, "capacity")?,
+            registered_at: parse_field(&hm, "registered_at")?,
+            expires_at,
+            id,
+        })
+    }
+}
+
+/// 节点成员变化事件
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NodeEvent {
+    /// 节点注册，此前不存在或租约已到期
+    Joined(String),
+    /// 节点注销或租约到期后被`expire_nodes`清理
+    Left(String),
+}
+
+impl FromStr for NodeEvent {
+    type Err = BtcmDataError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some(("join", id)) => Ok(NodeEvent::Joined(id.to_string())),
+            Some(("leave", id)) => Ok(NodeEvent::Left(id.to_string())),
+            _ => Err(BtcmDataError::TypeMismatch(format!("invalid node event: {}", s))),
+        }
+    }
+}
+
+/// 注册节点，整体替换原有的节点信息，节点此前不存在或租约已到期时发布加入事件。
+/// KEYS[1] = node, KEYS[2] = nodes, ARGV[1] = 节点ID, ARGV[2] = 租约到期时间, ARGV[3] = 当前时间,
+/// ARGV[4] = 事件频道, ARGV[5..] = 字段和值
+/// 节点新加入时返回1，否则返回0
+static REGISTER_SCRIPT: &str = r"
+local lease = redis.call('ZSCORE', KEYS[2], ARGV[1])
+local alive = lease and tonumber(lease) > tonumber(ARGV[3]) and redis.call('EXISTS', KEYS[1]) == 1
+local registered_at = alive and redis.call('HGET', KEYS[1], 'registered_at') or ARGV[3]
+redis.call('DEL', KEYS[1])
+redis.call('HSET', KEYS[1], 'registered_at', registered_at, unpack(ARGV, 5))
+redis.call('PEXPIREAT', KEYS[1], ARGV[2])
+redis.call('ZADD', KEYS[2], ARGV[2], ARGV[1])
+if alive then
+    return 0
+end
+redis.call('PUBLISH', ARGV[4], 'join:' .. ARGV[1])
+return 1
+";
+
+/// 延长节点的租约，节点不存在或租约已到期时不做修改。
+/// KEYS[1] = node, KEYS[2] = nodes, ARGV[1] = 节点ID, ARGV[2] = 租约到期时间, ARGV[3] = 当前时间
+/// 续约成功返回1，否则返回0
+static RENEW_SCRIPT: &str = r"
+local lease = redis.call('ZSCORE', KEYS[2], ARGV[1])
+if not lease or tonumber(lease) <= tonumber(ARGV[3]) or redis.call('EXISTS', KEYS[1]) == 0 then
+    return 0
+end
+redis.call('PEXPIREAT', KEYS[1], ARGV[2])
+redis.call('ZADD', KEYS[2], ARGV[2], ARGV[1])
+return 1
+";
+
+/// 注销节点并发布离开事件。
+/// KEYS[1] = node, KEYS[2] = nodes, ARGV[1] = 节点ID, ARGV[2] = 事件频道
+/// 节点存在时返回1，否则返回0
+static DEREGISTER_SCRIPT: &str = r"
+redis.call('DEL', KEYS[1])
+if redis.call('ZREM', KEYS[2], ARGV[1]) == 0 then
+    return 0
+end
+redis.call('PUBLISH', ARGV[2], 'leave:' .. ARGV[1])
+return 1
+";
+
+/// 清理租约已到期的节点，并为每个节点发布离开事件；调用方读取到期的节点后传入，脚本中再确认租约。
+/// KEYS[1] = nodes, KEYS[1 + i] = 第i个节点的node, ARGV[1] = 当前时间, ARGV[2] = 事件频道, ARGV[2 + i] = 第i个节点ID
+/// 返回被清理的节点ID
+static EXPIRE_SCRIPT: &str = r"
+local ids = {}
+for i = 3, #ARGV do
+    local lease = redis.call('ZSCORE', KEYS[1], ARGV[i])
+    if lease and tonumber(lease) <= tonumber(ARGV[1]) then
+        redis.call('ZREM', KEYS[1], ARGV[i])
+        redis.call('DEL', KEYS[i - 1])
+        redis.call('PUBLISH', ARGV[2], 'leave:' .. ARGV[i])
+        table.insert(ids, ARGV[i])
+    end
+end
+return ids
+";
+
+/// 异步函数，注册节点或在节点重启后重新注册，节点在`lease`内保持在线。
+///
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `id`: 节点ID。
+/// - `address`: 节点地址。
+/// - `role`: 节点角色。
+/// - `capacity`: 节点容量。
+/// - `lease`: 租约时长，一般为`DEFAULT_NODE_LEASE`。
+///
+/// # 返回值
+/// 节点此前不存在或租约已到期、因本次注册而加入时返回true。
+///
+/// # 示例
+/// ```rust,no_run
+/// use btcmdata::redis::node::{list_nodes, register_node, NodeRole, DEFAULT_NODE_LEASE};
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let client = redis::Client::open("redis://127.0.0.1/").expect("Failed to connect to Redis");
+///     let con = client.get_multiplexed_tokio_connection().await.expect("Failed to get Redis connection");
+///
+///     register_node(&con, "gw-01", "10.0.0.1:7000", NodeRole::Gateway, 10000, DEFAULT_NODE_LEASE).await.unwrap();
+///     let gateways = list_nodes(&con, Some(NodeRole::Gateway)).await.unwrap();
+///     println!("Gateways: {:?}", gateways);
+/// }
+/// ```
+pub async fn register_node(con: &MultiplexedConnection, id: &str, address: &str, role: NodeRole, capacity: u32, lease: Duration) -> Result<bool, BtcmDataError> {
+    let mut con = con.clone();
+    let now = now_millis();
+    let joined: i32 = redis::Script::new(REGISTER_SCRIPT)
+        .key(get_node_key(id))
+        .key(NODES_KEY)
+        .arg(id)
+        .arg(now.saturating_add(u64::try_from(lease.as_millis()).unwrap_or(u64::MAX)))
+        .arg(now)
+        .arg(NODE_EVENTS_CHANNEL)
+        .arg("address")
+        .arg(address)
+        .arg("role")
+        .arg(role.as_str())
+        .arg("capacity")
+        .arg(capacity)
+        .invoke_async(&mut con)
+        .await?;
+    Ok(joined == 1)
+}
+
+/// 异步函数，节点续约。
+///
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `id`: 节点ID。
+/// - `lease`: 从现在起的租约时长。
+///
+/// # 返回值
+/// 节点不存在或租约已到期时返回false，此时节点已被视为离开，应重新`register_node`。
+pub async fn renew_node(con: &MultiplexedConnection, id: &str, lease: Duration) -> Result<bool, BtcmDataError> {
+    let mut con = con.clone();
+    let now = now_millis();
+    let renewed: i32 = redis::Script::new(RENEW_SCRIPT)
+        .key(get_node_key(id))
+        .key(NODES_KEY)
+        .arg(id)
+        .arg(now.saturating_add(u64::try_from(lease.as_millis()).unwrap_or(u64::MAX)))
+        .arg(now)
+        .invoke_async(&mut con)
+        .await?;
+    Ok(renewed == 1)
+}
+
+/// 异步函数，节点正常下线时注销。
+///
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `id`: 节点ID。
+///
+/// # 返回值
+/// 返回节点是否存在并被注销。
+pub async fn deregister_node(con: &MultiplexedConnection, id: &str) -> Result<bool, BtcmDataError> {
+    let mut con = con.clone();
+    let removed: i32 = redis::Script::new(DEREGISTER_SCRIPT)
+        .key(get_node_key(id))
+        .key(NODES_KEY)
+        .arg(id)
+        .arg(NODE_EVENTS_CHANNEL)
+        .invoke_async(&mut con)
+        .await?;
+    Ok(removed == 1)
+}
+
+/// 异步函数，获取租约未到期的节点信息。
+///
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `id`: 节点ID。
+///
+/// # 返回值
+/// 返回节点信息，节点不存在或租约已到期时返回None。
+pub async fn get_node(con: &MultiplexedConnection, id: &str) -> Result<Option<NodeInfo>, BtcmDataError> {
+    let mut con = con.clone();
+    let (hm, lease): (HashMap<String, String>, Option<u64>) = redis::pipe()
+        .hgetall(get_node_key(id))
+        .zscore(NODES_KEY, id)
+        .query_async(&mut con)
+        .await?;
+    match lease {
+        Some(lease) if lease > now_millis() && !hm.is_empty() => Ok(Some(NodeInfo::from_fields(id.to_string(), hm, lease)?)),
+        _ => Ok(None),
+    }
+}
+
+/// 异步函数，列出租约未到期的节点。
+///
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `role`: 只列出该角色的节点，None表示全部节点。
+///
+/// # 返回值
+/// 返回按节点ID排序的节点信息。
+pub async fn list_nodes(con: &MultiplexedConnection, role: Option<NodeRole>) -> Result<Vec<NodeInfo>, BtcmDataError> {
+    let mut con = con.clone();
+    let alive: Vec<(String, u64)> = redis::cmd("ZRANGEBYSCORE")
+        .arg(NODES_KEY)
+        .arg(format!("({}", now_millis()))
+        .arg("+inf")
+        .arg("WITHSCORES")
+        .query_async(&mut con)
+        .await?;
+    if alive.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut pipe = redis::pipe();
+    for (id, _) in &alive {
+        pipe.hgetall(get_node_key(id));
+    }
+    let replies: Vec<HashMap<String, String>> = pipe.query_async(&mut con).await?;
+    let mut nodes = Vec::with_capacity(alive.len());
+    for ((id, lease), hm) in alive.into_iter().zip(replies) {
+        // 节点哈希与租约同时到期，可能在两次查询之间消失
+        if hm.is_empty() {
+            continue;
+        }
+        let node = NodeInfo::from_fields(id, hm, lease)?;
+        if role.is_none_or(|role| node.role == role) {
+            nodes.push(node);
+        }
+    }
+    nodes.sort_by(|a, b| a.id.cmp(&b.id));
+    Ok(nodes)
+}
+
+/// 异步函数，清理租约已到期的节点并发布离开事件，由任意节点定期调用。
+/// 网关离开后应再调用`route::remove_node_routes`清理其路由。
+///
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+///
+/// # 返回值
+/// 返回被清理的节点ID，同一个节点只会被一次调用清理。
+pub async fn expire_nodes(con: &MultiplexedConnection) -> Result<Vec<String>, BtcmDataError> {
+    let mut con = con.clone();
+    let now = now_millis();
+    // 节点信息的键由节点ID决定，先读取到期的节点
+    let ids: Vec<String> = con.zrangebyscore(NODES_KEY, "-inf", now).await?;
+    if ids.is_empty() {
+        return Ok(Vec::new());
+    }
+    let script = redis::Script::new(EXPIRE_SCRIPT);
+    let mut invocation = script.prepare_invoke();
+    invocation.key(NODES_KEY).arg(now).arg(NODE_EVENTS_CHANNEL);
+    for id in &ids {
+        invocation.key(get_node_key(id)).arg(id);
+    }
+    let expired: Vec<String> = invocation.invoke_async(&mut con).await?;
+    Ok(expired)
+}
+
+/// 异步函数，订阅节点成员变化。
+/// 订阅使用独立的连接，只能收到订阅之后的事件，应先订阅再调用`list_nodes`获取当前的节点。
+///
+/// # 参数
+/// - `client`: Redis客户端，用于建立订阅连接。
+///
+/// # 返回值
+/// 返回节点事件流，连接断开时结束。
+///
+/// # 示例
+/// ```rust,no_run
+/// use futures_util::StreamExt;
+/// use btcmdata::redis::node::{subscribe_nodes, NodeEvent};
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let client = redis::Client::open("redis://127.0.0.1/").expect("Failed to connect to Redis");
+///
+///     let mut events = Box::pin(subscribe_nodes(&client).await.unwrap());
+///     while let Some(event) = events.next().await {
+///         match event {
+///             NodeEvent::Joined(id) => println!("{} joined", id),
+///             NodeEvent::Left(id) => println!("{} left", id),
+///         }
+///     }
+/// }
+/// ```
+pub async fn subscribe_nodes(client: &redis::Client) -> Result<impl Stream<Item = NodeEvent>, BtcmDataError> {
+    let mut pubsub = client.get_async_connection().await?.into_pubsub();
+    pubsub.subscribe(NODE_EVENTS_CHANNEL).await?;
+    let events = pubsub.into_on_message().filter_map(|msg| async move {
+        msg.get_payload::<String>().ok().and_then(|payload| payload.parse().ok())
+    });
+    Ok(events)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::redis::test_client;
+
+    #[tokio::test]
+    #[ignore = "requires BTCMDATA_REDIS_URL"]
+    async fn register_renew_and_expire() {
+        let (client, con) = test_client().await;
+        let (gw, router) = ("test-gw-900001", "test-router-900001");
+        let mut events = Box::pin(subscribe_nodes(&client).await.unwrap());
+
+        assert!(register_node(&con, gw, "10.0.0.1:7000", NodeRole::Gateway, 100, DEFAULT_NODE_LEASE).await.unwrap());
+        assert!(!register_node(&con, gw, "10.0.0.1:7001", NodeRole::Gateway, 100, DEFAULT_NODE_LEASE).await.unwrap());
+        assert!(register_node(&con, router, "10.0.0.2:7000", NodeRole::Router, 0, Duration::ZERO).await.unwrap());
+        assert_eq!(events.next().await, Some(NodeEvent::Joined(gw.to_string())));
+        assert_eq!(events.next().await, Some(NodeEvent::Joined(router.to_string())));
+
+        // 租约为0的节点立即到期，不能续约
+        assert!(renew_node(&con, gw, DEFAULT_NODE_LEASE).await.unwrap());
+        assert!(!renew_node(&con, router, DEFAULT_NODE_LEASE).await.unwrap());
+        assert_eq!(get_node(&con, gw).await.unwrap().map(|node| node.address), Some("10.0.0.1:7001".to_string()));
+        assert_eq!(get_node(&con, router).await.unwrap(), None);
+        let gateways = list_nodes(&con, Some(NodeRole::Gateway)).await.unwrap();
+        assert!(gateways.iter().any(|node| node.id == gw));
+        assert!(!list_nodes(&con, None).await.unwrap().iter().any(|node| node.id == router));
+
+        assert!(expire_nodes(&con).await.unwrap().contains(&router.to_string()));
+        assert_eq!(events.next().await, Some(NodeEvent::Left(router.to_string())));
+        assert!(deregister_node(&con, gw).await.unwrap());
+        assert!(!deregister_node(&con, gw).await.unwrap());
+        assert_eq!(events.next().await, Some(NodeEvent::Left(gw.to_string())));
+    }
+}
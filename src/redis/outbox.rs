@@ -1,3 +1,236 @@
+use std::collections::HashMap;
+use std::time::Duration;
+use btcmbase::client::ClientID;
+use redis::{aio::MultiplexedConnection, AsyncCommands};
+use crate::error::BtcmDataError;
+use crate::now_millis;
 
-#[allow(dead_code)]
-static OUTBOX_PREFIX:  &str = "inbox:";
+// 每个发送端设备一个发件箱，保存已发送但尚未被服务器确认的消息
+// outbox:clientid:deviceid          -> Hash   消息ID -> 消息内容
+// outbox_pending:clientid:deviceid  -> ZSet   消息ID -> 最近一次发送时间(毫秒)
+static OUTBOX_PREFIX:  &str = "outbox:";
+
+/// 发件箱待确认索引键的前缀
+static OUTBOX_PENDING_PREFIX: &str = "outbox_pending:";
+
+/// 根据客户端ID和设备ID获取发件箱键的函数
+fn get_outbox_key(clt: ClientID, dev: u32) -> String {
+    let user_id: u64 = clt.into();
+    format!("{}{}:{}", OUTBOX_PREFIX, user_id, dev)
+}
+
+/// 根据客户端ID和设备ID获取发件箱待确认索引键的函数
+fn get_outbox_pending_key(clt: ClientID, dev: u32) -> String {
+    let user_id: u64 = clt.into();
+    format!("{}{}:{}", OUTBOX_PENDING_PREFIX, user_id, dev)
+}
+
+/// 发件箱中一条待确认的消息
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutboxMessage {
+    /// 发送端分配的消息ID
+    pub id: u64,
+    /// 消息内容
+    pub payload: Vec<u8>,
+    /// 最近一次发送的时间(毫秒)
+    pub sent_at: u64,
+}
+
+/// `list_pending_outbox`的管道返回值: (消息ID和发送时间, 消息ID到消息内容)
+type PendingReply = (Vec<(u64, f64)>, HashMap<u64, Vec<u8>>);
+
+/// 取出超时未确认的消息并将其发送时间更新为当前时间，保证同一条消息不会被多个网关同时重发。
+/// KEYS[1] = outbox, KEYS[2] = outbox_pending, ARGV[1] = 超时截止时间, ARGV[2] = 当前时间, ARGV[3] = 最大条数
+static RETRANSMIT_SCRIPT: &str = r"
+local ids = redis.call('ZRANGEBYSCORE', KEYS[2], '-inf', ARGV[1], 'LIMIT', 0, ARGV[3])
+local result = {}
+for _, id in ipairs(ids) do
+    local payload = redis.call('HGET', KEYS[1], id)
+    if payload then
+        redis.call('ZADD', KEYS[2], ARGV[2], id)
+        table.insert(result, id)
+        table.insert(result, payload)
+    else
+        redis.call('ZREM', KEYS[2], id)
+    end
+end
+return result
+";
+
+/// 异步函数，将一条已发送的消息写入发件箱，等待服务器确认。
+///
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `clt`: 发送端客户端ID。
+/// - `dev`: 发送端设备ID。
+/// - `id`: 消息ID。
+/// - `payload`: 消息内容。
+///
+/// # 示例
+/// ```rust,no_run
+/// use btcmbase::client::ClientID;
+/// use btcmdata::redis::outbox::enqueue_outbox;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let client = redis::Client::open("redis://127.0.0.1/").expect("Failed to connect to Redis");
+///     let con = client.get_multiplexed_tokio_connection().await.expect("Failed to get Redis connection");
+///     let client_id = ClientID::from(1001);
+///
+///     enqueue_outbox(&con, client_id, 1, 42, b"hello").await.unwrap();
+/// }
+/// ```
+pub async fn enqueue_outbox(con: &MultiplexedConnection, clt: ClientID, dev: u32, id: u64, payload: &[u8]) -> Result<(), BtcmDataError> {
+    let mut con = con.clone();
+    let _: () = redis::pipe()
+        .atomic()
+        .hset(get_outbox_key(clt, dev), id, payload)
+        .ignore()
+        .zadd(get_outbox_pending_key(clt, dev), id, now_millis())
+        .ignore()
+        .query_async(&mut con)
+        .await?;
+    Ok(())
+}
+
+/// 异步函数，服务器确认后从发件箱中删除消息。
+///
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `clt`: 发送端客户端ID。
+/// - `dev`: 发送端设备ID。
+/// - `ids`: 已确认的消息ID。
+///
+/// # 返回值
+/// 返回实际删除的消息条数。
+pub async fn ack_outbox(con: &MultiplexedConnection, clt: ClientID, dev: u32, ids: &[u64]) -> Result<usize, BtcmDataError> {
+    if ids.is_empty() {
+        return Ok(0);
+    }
+    let mut con = con.clone();
+    let (removed,): (usize,) = redis::pipe()
+        .atomic()
+        .hdel(get_outbox_key(clt, dev), ids)
+        .zrem(get_outbox_pending_key(clt, dev), ids)
+        .ignore()
+        .query_async(&mut con)
+        .await?;
+    Ok(removed)
+}
+
+/// 异步函数，列出发件箱中所有待确认的消息，按发送时间从早到晚排列。
+///
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `clt`: 发送端客户端ID。
+/// - `dev`: 发送端设备ID。
+///
+/// # 返回值
+/// 返回一个Vec<OutboxMessage>，包含所有待确认的消息。
+pub async fn list_pending_outbox(con: &MultiplexedConnection, clt: ClientID, dev: u32) -> Result<Vec<OutboxMessage>, BtcmDataError> {
+    let mut con = con.clone();
+    let (pending, mut payloads): PendingReply = redis::pipe()
+        .atomic()
+        .zrange_withscores(get_outbox_pending_key(clt, dev), 0, -1)
+        .hgetall(get_outbox_key(clt, dev))
+        .query_async(&mut con)
+        .await?;
+    let result = pending
+        .into_iter()
+        .filter_map(|(id, sent_at)| {
+            payloads.remove(&id).map(|payload| OutboxMessage { id, payload, sent_at: sent_at as u64 })
+        })
+        .collect();
+    Ok(result)
+}
+
+/// 异步函数，取出发送后超过`timeout`仍未确认的消息用于重发，同时将这些消息的发送时间更新为当前时间。
+///
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `clt`: 发送端客户端ID。
+/// - `dev`: 发送端设备ID。
+/// - `timeout`: 确认超时时间。
+/// - `count`: 最多取出的消息条数。
+///
+/// # 返回值
+/// 返回一个Vec<OutboxMessage>，包含需要重发的消息。
+///
+/// # 示例
+/// ```rust,no_run
+/// use std::time::Duration;
+/// use btcmbase::client::ClientID;
+/// use btcmdata::redis::outbox::take_retransmit_outbox;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let client = redis::Client::open("redis://127.0.0.1/").expect("Failed to connect to Redis");
+///     let con = client.get_multiplexed_tokio_connection().await.expect("Failed to get Redis connection");
+///     let client_id = ClientID::from(1001);
+///
+///     let expired = take_retransmit_outbox(&con, client_id, 1, Duration::from_secs(10), 100).await.unwrap();
+///     println!("Retransmit: {:?}", expired);
+/// }
+/// ```
+pub async fn take_retransmit_outbox(con: &MultiplexedConnection, clt: ClientID, dev: u32, timeout: Duration, count: usize) -> Result<Vec<OutboxMessage>, BtcmDataError> {
+    let mut con = con.clone();
+    let now = now_millis();
+    let deadline = now.saturating_sub(timeout.as_millis() as u64);
+    let reply: Vec<(u64, Vec<u8>)> = redis::Script::new(RETRANSMIT_SCRIPT)
+        .key(get_outbox_key(clt, dev))
+        .key(get_outbox_pending_key(clt, dev))
+        .arg(deadline)
+        .arg(now)
+        .arg(count)
+        .invoke_async(&mut con)
+        .await?;
+    let result = reply
+        .into_iter()
+        .map(|(id, payload)| OutboxMessage { id, payload, sent_at: now })
+        .collect();
+    Ok(result)
+}
+
+/// 异步函数，删除客户端指定设备的整个发件箱。
+///
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `clt`: 发送端客户端ID。
+/// - `dev`: 发送端设备ID。
+///
+/// # 返回值
+/// 返回一个Result<bool, BtcmDataError>，表示发件箱是否存在并被删除。
+pub async fn remove_outbox(con: &MultiplexedConnection, clt: ClientID, dev: u32) -> Result<bool, BtcmDataError> {
+    let mut con = con.clone();
+    let removed: usize = con.del(&[get_outbox_key(clt, dev), get_outbox_pending_key(clt, dev)]).await?;
+    Ok(removed > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::redis::test_connection;
+
+    #[tokio::test]
+    #[ignore = "requires BTCMDATA_REDIS_URL"]
+    async fn ack_and_retransmit() {
+        let con = test_connection().await;
+        let clt = ClientID::from(906_001);
+        remove_outbox(&con, clt, 1).await.unwrap();
+
+        enqueue_outbox(&con, clt, 1, 1, b"first").await.unwrap();
+        enqueue_outbox(&con, clt, 1, 2, b"second").await.unwrap();
+        assert_eq!(ack_outbox(&con, clt, 1, &[1, 3]).await.unwrap(), 1);
+        let pending = list_pending_outbox(&con, clt, 1).await.unwrap();
+        assert_eq!(pending.iter().map(|m| (m.id, m.payload.as_slice())).collect::<Vec<_>>(), vec![(2, b"second".as_slice())]);
+
+        // 取出后发送时间更新为当前时间，超时之前不会被再次取出
+        let taken = take_retransmit_outbox(&con, clt, 1, Duration::ZERO, 10).await.unwrap();
+        assert_eq!(taken.iter().map(|m| m.id).collect::<Vec<_>>(), vec![2]);
+        assert!(take_retransmit_outbox(&con, clt, 1, Duration::from_secs(60), 10).await.unwrap().is_empty());
+        assert_eq!(list_pending_outbox(&con, clt, 1).await.unwrap()[0].sent_at, taken[0].sent_at);
+
+        assert!(remove_outbox(&con, clt, 1).await.unwrap());
+        assert!(list_pending_outbox(&con, clt, 1).await.unwrap().is_empty());
+    }
+}
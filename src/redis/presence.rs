@@ -0,0 +1,216 @@
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+use btcmbase::client::ClientID;
+use redis::{aio::MultiplexedConnection, AsyncCommands};
+use crate::error::BtcmDataError;
+use crate::now_millis;
+
+// 每个客户端一个在线状态集合，设备每次心跳都把自己的过期时间往后推
+// presence:1001 -> ZSet  设备ID -> 过期时间(毫秒)
+//
+// 过期时间不晚于当前时间的设备视为离线，心跳时顺带清理；
+// 整个键的过期时间跟随最晚过期的设备，所有设备都不再心跳后自动删除
+static PRESENCE_PREFIX: &str = "presence:";
+
+/// 心跳默认的在线有效期，客户端应以明显短于该值的间隔发送心跳
+pub const DEFAULT_PRESENCE_TTL: Duration = Duration::from_secs(90);
+
+/// 根据客户端ID获取在线状态键的函数
+fn get_presence_key(clt: u64) -> String {
+    format!("{}{}", PRESENCE_PREFIX, clt)
+}
+
+/// 刷新设备的过期时间，清理已过期的设备，并将键的过期时间设置为最晚过期的设备。
+/// KEYS[1] = presence, ARGV[1] = 设备ID, ARGV[2] = 新的过期时间, ARGV[3] = 当前时间
+/// 设备此前离线时返回1，否则返回0
+static HEARTBEAT_SCRIPT: &str = r"
+local prev = redis.call('ZSCORE', KEYS[1], ARGV[1])
+redis.call('ZADD', KEYS[1], ARGV[2], ARGV[1])
+redis.call('ZREMRANGEBYSCORE', KEYS[1], '-inf', ARGV[3])
+local last = redis.call('ZRANGE', KEYS[1], -1, -1, 'WITHSCORES')
+if last[2] then
+    redis.call('PEXPIREAT', KEYS[1], last[2])
+end
+if prev and tonumber(prev) > tonumber(ARGV[3]) then
+    return 0
+end
+return 1
+";
+
+/// 在线设备的最小分数，过期时间必须晚于当前时间
+fn online_min() -> String {
+    format!("({}", now_millis())
+}
+
+/// 异步函数，记录设备的一次心跳，设备在`ttl`内保持在线。
+///
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `clt`: 客户端ID。
+/// - `dev`: 设备ID。
+/// - `ttl`: 在线有效期，一般为`DEFAULT_PRESENCE_TTL`。
+///
+/// # 返回值
+/// 设备此前离线、因这次心跳而上线时返回true，可用于通知联系人。
+///
+/// # 示例
+/// ```rust,no_run
+/// use btcmbase::client::ClientID;
+/// use btcmdata::redis::presence::{heartbeat, is_online, DEFAULT_PRESENCE_TTL};
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let client = redis::Client::open("redis://127.0.0.1/").expect("Failed to connect to Redis");
+///     let con = client.get_multiplexed_tokio_connection().await.expect("Failed to get Redis connection");
+///     let client_id = ClientID::from(1001);
+///
+///     let came_online = heartbeat(&con, client_id, 1, DEFAULT_PRESENCE_TTL).await.unwrap();
+///     assert!(is_online(&con, client_id).await.unwrap());
+///     println!("Came online: {}", came_online);
+/// }
+/// ```
+pub async fn heartbeat(con: &MultiplexedConnection, clt: ClientID, dev: u32, ttl: Duration) -> Result<bool, BtcmDataError> {
+    let mut con = con.clone();
+    let now = now_millis();
+    let result: i32 = redis::Script::new(HEARTBEAT_SCRIPT)
+        .key(get_presence_key(clt.into()))
+        .arg(dev)
+        .arg(now.saturating_add(u64::try_from(ttl.as_millis()).unwrap_or(u64::MAX)))
+        .arg(now)
+        .invoke_async(&mut con)
+        .await?;
+    Ok(result == 1)
+}
+
+/// 异步函数，设备正常断开时立即将其标记为离线，不必等待过期。
+///
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `clt`: 客户端ID。
+/// - `dev`: 设备ID。
+///
+/// # 返回值
+/// 返回设备是否有在线记录并被删除，记录已过期但尚未清理时也返回true。
+pub async fn set_offline(con: &MultiplexedConnection, clt: ClientID, dev: u32) -> Result<bool, BtcmDataError> {
+    let mut con = con.clone();
+    let removed: usize = con.zrem(get_presence_key(clt.into()), dev).await?;
+    Ok(removed > 0)
+}
+
+/// 异步函数，判断客户端是否有任何设备在线。
+///
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `clt`: 客户端ID。
+///
+/// # 返回值
+/// 至少一个设备在线时返回true。
+pub async fn is_online(con: &MultiplexedConnection, clt: ClientID) -> Result<bool, BtcmDataError> {
+    let mut con = con.clone();
+    let count: usize = con.zcount(get_presence_key(clt.into()), online_min(), "+inf").await?;
+    Ok(count > 0)
+}
+
+/// 异步函数，获取客户端所有在线的设备。
+///
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `clt`: 客户端ID。
+///
+/// # 返回值
+/// 返回一个包含在线设备ID的HashSet<u32>。
+pub async fn get_online_devices(con: &MultiplexedConnection, clt: ClientID) -> Result<HashSet<u32>, BtcmDataError> {
+    let mut con = con.clone();
+    let result: HashSet<u32> = con.zrangebyscore(get_presence_key(clt.into()), online_min(), "+inf").await?;
+    Ok(result)
+}
+
+/// 异步函数，批量查询一组客户端(一般是用户的联系人)的在线设备。
+///
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `clts`: 客户端ID集合，例如`users::get_user_contacts`的结果。
+///
+/// # 返回值
+/// 返回在线客户端ID到其在线设备的HashMap，不包含离线的客户端。
+///
+/// # 示例
+/// ```rust,no_run
+/// use btcmbase::client::ClientID;
+/// use btcmdata::redis::{presence::get_online_contacts, users::get_user_contacts};
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let client = redis::Client::open("redis://127.0.0.1/").expect("Failed to connect to Redis");
+///     let con = client.get_multiplexed_tokio_connection().await.expect("Failed to get Redis connection");
+///
+///     let contacts = get_user_contacts(&con, ClientID::from(1001)).await.unwrap();
+///     let online = get_online_contacts(&con, &contacts).await.unwrap();
+///     println!("Online contacts: {:?}", online.keys());
+/// }
+/// ```
+pub async fn get_online_contacts(con: &MultiplexedConnection, clts: &HashSet<u64>) -> Result<HashMap<u64, HashSet<u32>>, BtcmDataError> {
+    if clts.is_empty() {
+        return Ok(HashMap::new());
+    }
+    let mut con = con.clone();
+    let clts: Vec<u64> = clts.iter().copied().collect();
+    let min = online_min();
+    let mut pipe = redis::pipe();
+    for clt in &clts {
+        pipe.zrangebyscore(get_presence_key(*clt), &min, "+inf");
+    }
+    let replies: Vec<HashSet<u32>> = pipe.query_async(&mut con).await?;
+    let result = clts
+        .into_iter()
+        .zip(replies)
+        .filter(|(_, devices)| !devices.is_empty())
+        .collect();
+    Ok(result)
+}
+
+/// 异步函数，删除客户端的在线状态，所有设备立即离线。
+///
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `clt`: 客户端ID。
+///
+/// # 返回值
+/// 返回一个Result<bool, BtcmDataError>，表示在线状态是否存在并被删除。
+pub async fn remove_presence(con: &MultiplexedConnection, clt: ClientID) -> Result<bool, BtcmDataError> {
+    let mut con = con.clone();
+    let result: bool = con.del(get_presence_key(clt.into())).await?;
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::redis::test_connection;
+
+    #[tokio::test]
+    #[ignore = "requires BTCMDATA_REDIS_URL"]
+    async fn heartbeats_expire() {
+        let mut con = test_connection().await;
+        let (clt, other) = (ClientID::from(923_001), ClientID::from(923_002));
+        remove_presence(&con, clt).await.unwrap();
+        remove_presence(&con, other).await.unwrap();
+
+        assert!(heartbeat(&con, clt, 1, DEFAULT_PRESENCE_TTL).await.unwrap());
+        assert!(!heartbeat(&con, clt, 1, DEFAULT_PRESENCE_TTL).await.unwrap());
+        assert!(heartbeat(&con, clt, 2, DEFAULT_PRESENCE_TTL).await.unwrap());
+        assert_eq!(get_online_devices(&con, clt).await.unwrap(), HashSet::from([1, 2]));
+
+        // 直接写入已经过去的过期时间，不依赖睡眠时长。过期的设备不再在线，再次心跳时视为重新上线
+        let _: () = con.zadd(get_presence_key(923_001), 1, now_millis() - 1).await.unwrap();
+        assert_eq!(get_online_devices(&con, clt).await.unwrap(), HashSet::from([2]));
+        let online = get_online_contacts(&con, &HashSet::from([923_001, 923_002])).await.unwrap();
+        assert_eq!(online, HashMap::from([(923_001, HashSet::from([2]))]));
+        assert!(heartbeat(&con, clt, 1, DEFAULT_PRESENCE_TTL).await.unwrap());
+
+        assert!(set_offline(&con, clt, 1).await.unwrap());
+        assert!(set_offline(&con, clt, 2).await.unwrap());
+        assert!(!is_online(&con, clt).await.unwrap());
+        assert!(!is_online(&con, other).await.unwrap());
+    }
+}
@@ -0,0 +1,293 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use btcmbase::client::ClientID;
+use redis::{aio::MultiplexedConnection, AsyncCommands};
+use crate::error::BtcmDataError;
+use crate::model::ContactRemark;
+use super::users;
+
+// 用户对联系人的私有备注，只有备注的所有者可见
+// conts_remark:1001:1002         -> Hash  nickname/notes
+// conts_remark_labels:1001:1002  -> Set   1001给1002设置的标签
+// conts_label:1001:<label>       -> Set   1001设置了该标签的联系人
+// conts_labels:1001              -> Set   1001使用中的全部标签
+static REMARK_PREFIX: &str = "conts_remark:";
+static REMARK_LABELS_PREFIX: &str = "conts_remark_labels:";
+static LABEL_PREFIX: &str = "conts_label:";
+static LABELS_PREFIX: &str = "conts_labels:";
+
+/// 获取联系人备注哈希键的函数
+fn get_remark_key(owner: u64, contact: u64) -> String {
+    format!("{}{}:{}", REMARK_PREFIX, owner, contact)
+}
+
+/// 获取联系人标签集合键的函数
+fn get_remark_labels_key(owner: u64, contact: u64) -> String {
+    format!("{}{}:{}", REMARK_LABELS_PREFIX, owner, contact)
+}
+
+/// 获取标签索引键的函数
+fn get_label_key(owner: u64, label: &str) -> String {
+    format!("{}{}:{}", LABEL_PREFIX, owner, label)
+}
+
+/// 获取用户全部标签键的函数
+fn get_labels_key(owner: u64) -> String {
+    format!("{}{}", LABELS_PREFIX, owner)
+}
+
+/// 替换联系人备注并同步标签索引，标签不再被任何联系人使用时从用户的标签集合中删除。
+/// 原有标签由调用方读取后传入，与conts_remark_labels不一致时返回-1，调用方重新读取后重试。
+/// KEYS[1] = conts_remark, KEYS[2] = conts_remark_labels, KEYS[3] = conts_labels,
+/// KEYS[3 + i] = 第i个原有标签的conts_label, 其余KEYS = 新标签的conts_label(与新标签一一对应)
+/// ARGV[1] = 联系人, ARGV[2] = 原有标签数m, ARGV[3] = 字段数n, ARGV[4..m+3] = 原有标签,
+/// ARGV[m+4..m+2n+3] = 字段和值, 其余ARGV = 新标签
+/// 返回 0 成功, -1 原有标签已变化
+static SET_REMARK_SCRIPT: &str = r"
+local m = tonumber(ARGV[2])
+local n = tonumber(ARGV[3])
+if redis.call('SCARD', KEYS[2]) ~= m then
+    return -1
+end
+for i = 1, m do
+    if redis.call('SISMEMBER', KEYS[2], ARGV[3 + i]) == 0 then
+        return -1
+    end
+end
+for i = 1, m do
+    redis.call('SREM', KEYS[3 + i], ARGV[1])
+    if redis.call('SCARD', KEYS[3 + i]) == 0 then
+        redis.call('SREM', KEYS[3], ARGV[3 + i])
+    end
+end
+redis.call('DEL', KEYS[1], KEYS[2])
+if n > 0 then
+    redis.call('HSET', KEYS[1], unpack(ARGV, 4 + m, 3 + m + n * 2))
+end
+local first = 4 + m + n * 2
+for i = first, #ARGV do
+    redis.call('SADD', KEYS[2], ARGV[i])
+    redis.call('SADD', KEYS[4 + m + i - first], ARGV[1])
+    redis.call('SADD', KEYS[3], ARGV[i])
+end
+return 0
+";
+
+/// 异步函数，设置用户对联系人的备注，整体替换原有的备注名、备注说明和标签。
+/// 备注为空时等同于`remove_contact_remark`。
+///
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `owner`: 备注的所有者。
+/// - `contact`: 被备注的联系人。
+/// - `remark`: 备注内容。
+///
+/// # 示例
+/// ```rust,no_run
+/// use btcmbase::client::ClientID;
+/// use btcmdata::model::ContactRemark;
+/// use btcmdata::redis::remarks::{get_contacts_by_label, set_contact_remark};
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let client = redis::Client::open("redis://127.0.0.1/").expect("Failed to connect to Redis");
+///     let con = client.get_multiplexed_tokio_connection().await.expect("Failed to get Redis connection");
+///     let owner = ClientID::from(1001);
+///
+///     let mut remark = ContactRemark::default();
+///     remark.set_nickname(Some("Mom".to_string()));
+///     remark.add_label("family");
+///     set_contact_remark(&con, owner, ClientID::from(1002), &remark).await.unwrap();
+///
+///     let family = get_contacts_by_label(&con, owner, "family").await.unwrap();
+///     println!("Family: {:?}", family);
+/// }
+/// ```
+pub async fn set_contact_remark(con: &MultiplexedConnection, owner: ClientID, contact: ClientID, remark: &ContactRemark) -> Result<(), BtcmDataError> {
+    let mut con = con.clone();
+    let (owner, contact): (u64, u64) = (owner.into(), contact.into());
+    let fields = remark.to_fields();
+
+    let script = redis::Script::new(SET_REMARK_SCRIPT);
+    loop {
+        // 标签索引的键由标签名决定，先读取原有标签，脚本中确认没有变化
+        let previous: Vec<String> = con.smembers(get_remark_labels_key(owner, contact)).await?;
+        let mut invocation = script.prepare_invoke();
+        invocation
+            .key(get_remark_key(owner, contact))
+            .key(get_remark_labels_key(owner, contact))
+            .key(get_labels_key(owner))
+            .arg(contact)
+            .arg(previous.len())
+            .arg(fields.len());
+        for label in &previous {
+            invocation.key(get_label_key(owner, label)).arg(label);
+        }
+        for (field, value) in &fields {
+            invocation.arg(*field).arg(value);
+        }
+        for label in remark.labels() {
+            invocation.key(get_label_key(owner, label)).arg(label);
+        }
+        let result: i32 = invocation.invoke_async(&mut con).await?;
+        if result == 0 {
+            return Ok(());
+        }
+    }
+}
+
+/// 异步函数，获取用户对联系人的备注。
+///
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `owner`: 备注的所有者。
+/// - `contact`: 被备注的联系人。
+///
+/// # 返回值
+/// 返回备注，没有设置过备注时返回None。
+pub async fn get_contact_remark(con: &MultiplexedConnection, owner: ClientID, contact: ClientID) -> Result<Option<ContactRemark>, BtcmDataError> {
+    let mut con = con.clone();
+    let (owner, contact): (u64, u64) = (owner.into(), contact.into());
+    let (hm, labels): (HashMap<String, String>, BTreeSet<String>) = redis::pipe()
+        .hgetall(get_remark_key(owner, contact))
+        .smembers(get_remark_labels_key(owner, contact))
+        .query_async(&mut con)
+        .await?;
+    let remark = ContactRemark::from_fields(hm, labels);
+    Ok((!remark.is_empty()).then_some(remark))
+}
+
+/// 异步函数，获取用户对所有联系人的备注。
+///
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `owner`: 备注的所有者。
+///
+/// # 返回值
+/// 返回联系人ID到备注的HashMap，只包含`conts_user:`中设置过备注的联系人。
+pub async fn get_contact_remarks(con: &MultiplexedConnection, owner: ClientID) -> Result<HashMap<u64, ContactRemark>, BtcmDataError> {
+    let contacts: Vec<u64> = users::get_user_contacts(con, owner).await?.into_iter().collect();
+    if contacts.is_empty() {
+        return Ok(HashMap::new());
+    }
+    let mut rcon = con.clone();
+    let owner: u64 = owner.into();
+    let mut pipe = redis::pipe();
+    for contact in &contacts {
+        pipe.hgetall(get_remark_key(owner, *contact)).smembers(get_remark_labels_key(owner, *contact));
+    }
+    let replies: Vec<(HashMap<String, String>, BTreeSet<String>)> = pipe.query_async(&mut rcon).await?;
+    let result = contacts
+        .into_iter()
+        .zip(replies)
+        .map(|(contact, (hm, labels))| (contact, ContactRemark::from_fields(hm, labels)))
+        .filter(|(_, remark)| !remark.is_empty())
+        .collect();
+    Ok(result)
+}
+
+/// 异步函数，删除用户对联系人的备注及其标签。
+///
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `owner`: 备注的所有者。
+/// - `contact`: 被备注的联系人。
+pub async fn remove_contact_remark(con: &MultiplexedConnection, owner: ClientID, contact: ClientID) -> Result<(), BtcmDataError> {
+    set_contact_remark(con, owner, contact, &ContactRemark::default()).await
+}
+
+/// 异步函数，获取用户设置了指定标签的所有联系人。
+///
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `owner`: 标签的所有者。
+/// - `label`: 标签。
+///
+/// # 返回值
+/// 返回一个包含联系人ID的HashSet<u64>。
+pub async fn get_contacts_by_label(con: &MultiplexedConnection, owner: ClientID, label: &str) -> Result<HashSet<u64>, BtcmDataError> {
+    let mut con = con.clone();
+    let owner: u64 = owner.into();
+    let result: HashSet<u64> = con.smembers(get_label_key(owner, label)).await?;
+    Ok(result)
+}
+
+/// 异步函数，获取用户使用中的全部标签。
+///
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `owner`: 标签的所有者。
+///
+/// # 返回值
+/// 返回一个按字典序排列的BTreeSet<String>。
+pub async fn get_labels(con: &MultiplexedConnection, owner: ClientID) -> Result<BTreeSet<String>, BtcmDataError> {
+    let mut con = con.clone();
+    let result: BTreeSet<String> = con.smembers(get_labels_key(owner.into())).await?;
+    Ok(result)
+}
+
+/// 异步函数，删除用户设置的全部备注和标签，用于注销账号。
+/// 备注只能从联系人和标签索引中找到，`contacts`为用户的联系人。
+pub(crate) async fn remove_contact_remarks(con: &MultiplexedConnection, owner: ClientID, contacts: &HashSet<u64>) -> Result<(), BtcmDataError> {
+    let mut con = con.clone();
+    let owner: u64 = owner.into();
+    let labels: Vec<String> = con.smembers(get_labels_key(owner)).await?;
+    let mut remarked = contacts.clone();
+    if !labels.is_empty() {
+        let mut pipe = redis::pipe();
+        for label in &labels {
+            pipe.smembers(get_label_key(owner, label));
+        }
+        let members: Vec<HashSet<u64>> = pipe.query_async(&mut con).await?;
+        remarked.extend(members.into_iter().flatten());
+    }
+    let mut pipe = redis::pipe();
+    pipe.atomic();
+    for contact in &remarked {
+        pipe.del(get_remark_key(owner, *contact)).ignore();
+        pipe.del(get_remark_labels_key(owner, *contact)).ignore();
+    }
+    for label in &labels {
+        pipe.del(get_label_key(owner, label)).ignore();
+    }
+    pipe.del(get_labels_key(owner)).ignore();
+    let _: () = pipe.query_async(&mut con).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::redis::test_connection;
+
+    #[tokio::test]
+    #[ignore = "requires BTCMDATA_REDIS_URL"]
+    async fn labels_follow_the_remarks() {
+        let con = test_connection().await;
+        let (owner, mom, dad) = (ClientID::from(915_001), ClientID::from(915_002), ClientID::from(915_003));
+        remove_contact_remarks(&con, owner, &HashSet::from([915_002, 915_003])).await.unwrap();
+
+        let mut remark = ContactRemark::default();
+        remark.set_nickname(Some("Mom".to_string()));
+        remark.add_label("family");
+        remark.add_label("close");
+        set_contact_remark(&con, owner, mom, &remark).await.unwrap();
+        let mut other = ContactRemark::default();
+        other.add_label("family");
+        set_contact_remark(&con, owner, dad, &other).await.unwrap();
+        assert_eq!(get_contact_remark(&con, owner, mom).await.unwrap(), Some(remark.clone()));
+        assert_eq!(get_contacts_by_label(&con, owner, "family").await.unwrap(), HashSet::from([915_002, 915_003]));
+
+        // 替换备注时从旧标签的索引中移除，不再使用的标签从标签集合中删除
+        remark.remove_label("close");
+        remark.add_label("parents");
+        set_contact_remark(&con, owner, mom, &remark).await.unwrap();
+        assert!(get_contacts_by_label(&con, owner, "close").await.unwrap().is_empty());
+        assert_eq!(get_labels(&con, owner).await.unwrap(), BTreeSet::from(["family".to_string(), "parents".to_string()]));
+
+        remove_contact_remark(&con, owner, mom).await.unwrap();
+        assert!(get_contact_remark(&con, owner, mom).await.unwrap().is_none());
+        assert_eq!(get_contacts_by_label(&con, owner, "family").await.unwrap(), HashSet::from([915_003]));
+        assert_eq!(get_labels(&con, owner).await.unwrap(), BTreeSet::from(["family".to_string()]));
+        remove_contact_remarks(&con, owner, &HashSet::from([915_002, 915_003])).await.unwrap();
+    }
+}
@@ -0,0 +1,445 @@
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+use btcmbase::client::ClientID;
+use redis::{aio::MultiplexedConnection, AsyncCommands};
+use crate::error::BtcmDataError;
+use crate::now_millis;
+
+// 路由表记录设备的长连接在哪个网关节点上，网关持有一个租约并定期续约
+// route:1001         -> Hash  设备ID -> 节点ID
+// route_lease:1001   -> ZSet  设备ID -> 租约到期时间(毫秒)
+// node_routes:gw-01  -> ZSet  节点上的设备"客户端ID:设备ID" -> 租约到期时间(毫秒)，用于节点宕机后的清理
+//
+// 租约到期的设备视为没有路由，绑定和续约时顺带清理；各键的过期时间跟随最晚到期的设备
+static ROUTE_PREFIX: &str = "route:";
+static ROUTE_LEASE_PREFIX: &str = "route_lease:";
+static NODE_ROUTES_PREFIX: &str = "node_routes:";
+
+/// 路由默认的租约时长，网关应以明显短于该值的间隔续约
+pub const DEFAULT_ROUTE_LEASE: Duration = Duration::from_secs(60);
+
+/// 每次清理的节点路由数上限
+const REMOVE_BATCH: isize = 500;
+
+/// 获取客户端路由哈希键的函数
+fn get_route_key(clt: u64) -> String {
+    format!("{}{}", ROUTE_PREFIX, clt)
+}
+
+/// 获取客户端路由租约键的函数
+fn get_route_lease_key(clt: u64) -> String {
+    format!("{}{}", ROUTE_LEASE_PREFIX, clt)
+}
+
+/// 获取节点设备集合键的函数
+fn get_node_routes_key(node: &str) -> String {
+    format!("{}{}", NODE_ROUTES_PREFIX, node)
+}
+
+/// `get_routes`的管道返回值: (设备ID到节点ID, 租约未到期的设备)
+type RouteReply = (HashMap<u32, String>, HashSet<u32>);
+
+/// 将设备绑定到节点，从原节点的设备集合中移除，清理租约已到期的设备并刷新键的过期时间。
+/// 路由中已有的节点由调用方读取后传入，出现未传入的节点时返回{0, ''}，调用方重新读取后重试。
+/// KEYS[1] = route, KEYS[2] = route_lease, KEYS[3] = 该节点的node_routes, KEYS[3 + i] = 第i个已有节点的node_routes
+/// ARGV[1] = 设备ID, ARGV[2] = 节点ID, ARGV[3] = 租约到期时间, ARGV[4] = 当前时间, ARGV[5] = 客户端ID,
+/// ARGV[5 + i] = 第i个已有节点
+/// 返回{1, 租约未到期的原节点}，原节点就是该节点或没有原节点时为空串
+static BIND_ROUTE_SCRIPT: &str = r"
+local known = {[ARGV[2]] = KEYS[3]}
+for i = 6, #ARGV do
+    known[ARGV[i]] = KEYS[i - 2]
+end
+for _, node in ipairs(redis.call('HVALS', KEYS[1])) do
+    if not known[node] then
+        return {0, ''}
+    end
+end
+local current = redis.call('HGET', KEYS[1], ARGV[1])
+local lease = redis.call('ZSCORE', KEYS[2], ARGV[1])
+local prev = ''
+if current and current ~= ARGV[2] then
+    if lease and tonumber(lease) > tonumber(ARGV[4]) then
+        prev = current
+    end
+    redis.call('ZREM', known[current], ARGV[5] .. ':' .. ARGV[1])
+end
+redis.call('HSET', KEYS[1], ARGV[1], ARGV[2])
+redis.call('ZADD', KEYS[2], ARGV[3], ARGV[1])
+redis.call('ZADD', KEYS[3], ARGV[3], ARGV[5] .. ':' .. ARGV[1])
+for _, dev in ipairs(redis.call('ZRANGEBYSCORE', KEYS[2], '-inf', ARGV[4])) do
+    local node = redis.call('HGET', KEYS[1], dev)
+    if node then
+        redis.call('ZREM', known[node], ARGV[5] .. ':' .. dev)
+        redis.call('HDEL', KEYS[1], dev)
+    end
+    redis.call('ZREM', KEYS[2], dev)
+end
+redis.call('ZREMRANGEBYSCORE', KEYS[3], '-inf', ARGV[4])
+local last = redis.call('ZRANGE', KEYS[2], -1, -1, 'WITHSCORES')
+if last[2] then
+    redis.call('PEXPIREAT', KEYS[1], last[2])
+    redis.call('PEXPIREAT', KEYS[2], last[2])
+end
+last = redis.call('ZRANGE', KEYS[3], -1, -1, 'WITHSCORES')
+if last[2] then
+    redis.call('PEXPIREAT', KEYS[3], last[2])
+end
+return {1, prev}
+";
+
+/// 延长设备的租约，只有设备仍绑定在该节点且租约未到期时才续约，同时清理节点上租约已到期的设备。
+/// KEYS[1] = route, KEYS[2] = route_lease, KEYS[3] = node_routes
+/// ARGV[1] = 设备ID, ARGV[2] = 节点ID, ARGV[3] = 租约到期时间, ARGV[4] = 当前时间, ARGV[5] = 客户端ID
+/// 续约成功返回1，否则返回0
+static RENEW_ROUTE_SCRIPT: &str = r"
+local lease = redis.call('ZSCORE', KEYS[2], ARGV[1])
+if redis.call('HGET', KEYS[1], ARGV[1]) ~= ARGV[2] or not lease or tonumber(lease) <= tonumber(ARGV[4]) then
+    return 0
+end
+redis.call('ZADD', KEYS[2], ARGV[3], ARGV[1])
+redis.call('ZADD', KEYS[3], ARGV[3], ARGV[5] .. ':' .. ARGV[1])
+redis.call('ZREMRANGEBYSCORE', KEYS[3], '-inf', ARGV[4])
+local last = redis.call('ZRANGE', KEYS[2], -1, -1, 'WITHSCORES')
+redis.call('PEXPIREAT', KEYS[1], last[2])
+redis.call('PEXPIREAT', KEYS[2], last[2])
+last = redis.call('ZRANGE', KEYS[3], -1, -1, 'WITHSCORES')
+if last[2] then
+    redis.call('PEXPIREAT', KEYS[3], last[2])
+end
+return 1
+";
+
+/// 解除设备与节点的绑定，设备已绑定到其他节点时不做修改。
+/// KEYS[1] = route, KEYS[2] = route_lease, KEYS[3] = node_routes, ARGV[1] = 设备ID, ARGV[2] = 节点ID, ARGV[3] = 客户端ID
+/// 解除绑定返回1，否则返回0
+static UNBIND_ROUTE_SCRIPT: &str = r"
+if redis.call('HGET', KEYS[1], ARGV[1]) ~= ARGV[2] then
+    return 0
+end
+redis.call('HDEL', KEYS[1], ARGV[1])
+redis.call('ZREM', KEYS[2], ARGV[1])
+redis.call('ZREM', KEYS[3], ARGV[3] .. ':' .. ARGV[1])
+return 1
+";
+
+/// 删除节点上的一批路由，已绑定到其他节点的设备保持不变。
+/// KEYS[1] = node_routes, 第i个设备: KEYS[2i] = route, KEYS[2i + 1] = route_lease
+/// ARGV[1] = 节点ID, ARGV[1 + i] = 第i个设备"客户端ID:设备ID"
+/// 返回删除的路由数
+static REMOVE_NODE_ROUTES_SCRIPT: &str = r"
+local count = 0
+for i = 1, #ARGV - 1 do
+    local member = ARGV[1 + i]
+    if redis.call('ZREM', KEYS[1], member) == 1 then
+        local dev = string.sub(member, string.find(member, ':', 1, true) + 1)
+        if redis.call('HGET', KEYS[2 * i], dev) == ARGV[1] then
+            redis.call('HDEL', KEYS[2 * i], dev)
+            redis.call('ZREM', KEYS[2 * i + 1], dev)
+            count = count + 1
+        end
+    end
+end
+return count
+";
+
+/// 删除客户端的全部路由，并从各节点的设备集合中移除。
+/// 路由中的节点由调用方读取后传入，出现未传入的节点时返回-1，调用方重新读取后重试。
+/// KEYS[1] = route, KEYS[2] = route_lease, KEYS[2 + i] = 第i个节点的node_routes
+/// ARGV[1] = 客户端ID, ARGV[1 + i] = 第i个节点
+/// 返回删除的路由数
+static REMOVE_ROUTES_SCRIPT: &str = r"
+local known = {}
+for i = 2, #ARGV do
+    known[ARGV[i]] = KEYS[i + 1]
+end
+local routes = redis.call('HGETALL', KEYS[1])
+for i = 2, #routes, 2 do
+    if not known[routes[i]] then
+        return -1
+    end
+end
+for i = 1, #routes, 2 do
+    redis.call('ZREM', known[routes[i + 1]], ARGV[1] .. ':' .. routes[i])
+end
+redis.call('DEL', KEYS[1], KEYS[2])
+return #routes / 2
+";
+
+/// 异步函数，设备连接到网关节点后绑定路由，设备原来的路由被覆盖。
+///
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `clt`: 客户端ID。
+/// - `dev`: 设备ID。
+/// - `node`: 网关节点ID。
+/// - `lease`: 租约时长，一般为`DEFAULT_ROUTE_LEASE`。
+///
+/// # 返回值
+/// 设备原来连接在另一个节点上且租约未到期时返回该节点，调用方可通知其关闭旧连接。
+///
+/// # 示例
+/// ```rust,no_run
+/// use btcmbase::client::ClientID;
+/// use btcmdata::redis::route::{bind_route, get_route, DEFAULT_ROUTE_LEASE};
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let client = redis::Client::open("redis://127.0.0.1/").expect("Failed to connect to Redis");
+///     let con = client.get_multiplexed_tokio_connection().await.expect("Failed to get Redis connection");
+///     let client_id = ClientID::from(1001);
+///
+///     if let Some(old) = bind_route(&con, client_id, 1, "gw-01", DEFAULT_ROUTE_LEASE).await.unwrap() {
+///         println!("Close the stale connection on {}", old);
+///     }
+///     assert_eq!(get_route(&con, client_id, 1).await.unwrap().as_deref(), Some("gw-01"));
+/// }
+/// ```
+pub async fn bind_route(con: &MultiplexedConnection, clt: ClientID, dev: u32, node: &str, lease: Duration) -> Result<Option<String>, BtcmDataError> {
+    let mut con = con.clone();
+    let user_id: u64 = clt.into();
+    let script = redis::Script::new(BIND_ROUTE_SCRIPT);
+    loop {
+        // 节点设备集合的键由节点ID决定，先读取路由中已有的节点，脚本中确认没有新的节点
+        let nodes: HashSet<String> = con.hvals(get_route_key(user_id)).await?;
+        let now = now_millis();
+        let mut invocation = script.prepare_invoke();
+        invocation
+            .key(get_route_key(user_id))
+            .key(get_route_lease_key(user_id))
+            .key(get_node_routes_key(node))
+            .arg(dev)
+            .arg(node)
+            .arg(now.saturating_add(u64::try_from(lease.as_millis()).unwrap_or(u64::MAX)))
+            .arg(now)
+            .arg(user_id);
+        for known in &nodes {
+            invocation.key(get_node_routes_key(known)).arg(known);
+        }
+        let (bound, prev): (i32, String) = invocation.invoke_async(&mut con).await?;
+        if bound == 1 {
+            return Ok((!prev.is_empty()).then_some(prev));
+        }
+    }
+}
+
+/// 异步函数，网关节点为设备续约。
+///
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `clt`: 客户端ID。
+/// - `dev`: 设备ID。
+/// - `node`: 网关节点ID。
+/// - `lease`: 从现在起的租约时长。
+///
+/// # 返回值
+/// 设备已绑定到其他节点或租约已到期时返回false，此时节点应关闭连接或重新`bind_route`。
+pub async fn renew_route(con: &MultiplexedConnection, clt: ClientID, dev: u32, node: &str, lease: Duration) -> Result<bool, BtcmDataError> {
+    let mut con = con.clone();
+    let user_id: u64 = clt.into();
+    let now = now_millis();
+    let renewed: i32 = redis::Script::new(RENEW_ROUTE_SCRIPT)
+        .key(get_route_key(user_id))
+        .key(get_route_lease_key(user_id))
+        .key(get_node_routes_key(node))
+        .arg(dev)
+        .arg(node)
+        .arg(now.saturating_add(u64::try_from(lease.as_millis()).unwrap_or(u64::MAX)))
+        .arg(now)
+        .arg(user_id)
+        .invoke_async(&mut con)
+        .await?;
+    Ok(renewed == 1)
+}
+
+/// 异步函数，设备断开后网关节点解除路由。
+///
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `clt`: 客户端ID。
+/// - `dev`: 设备ID。
+/// - `node`: 网关节点ID。
+///
+/// # 返回值
+/// 返回路由是否属于该节点并被删除，设备已重新连接到其他节点时返回false。
+pub async fn unbind_route(con: &MultiplexedConnection, clt: ClientID, dev: u32, node: &str) -> Result<bool, BtcmDataError> {
+    let mut con = con.clone();
+    let user_id: u64 = clt.into();
+    let removed: i32 = redis::Script::new(UNBIND_ROUTE_SCRIPT)
+        .key(get_route_key(user_id))
+        .key(get_route_lease_key(user_id))
+        .key(get_node_routes_key(node))
+        .arg(dev)
+        .arg(node)
+        .arg(user_id)
+        .invoke_async(&mut con)
+        .await?;
+    Ok(removed == 1)
+}
+
+/// 异步函数，获取设备所在的网关节点。
+///
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `clt`: 客户端ID。
+/// - `dev`: 设备ID。
+///
+/// # 返回值
+/// 返回节点ID，设备没有路由或租约已到期时返回None。
+pub async fn get_route(con: &MultiplexedConnection, clt: ClientID, dev: u32) -> Result<Option<String>, BtcmDataError> {
+    let mut con = con.clone();
+    let user_id: u64 = clt.into();
+    let (node, lease): (Option<String>, Option<u64>) = redis::pipe()
+        .hget(get_route_key(user_id), dev)
+        .zscore(get_route_lease_key(user_id), dev)
+        .query_async(&mut con)
+        .await?;
+    Ok(node.filter(|_| lease.is_some_and(|lease| lease > now_millis())))
+}
+
+/// 异步函数，批量获取一组客户端所有设备的路由，用于向多个接收者投递消息。
+///
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `clts`: 客户端ID集合。
+///
+/// # 返回值
+/// 返回客户端ID到其设备路由(设备ID到节点ID)的HashMap，不包含没有任何路由的客户端。
+///
+/// # 示例
+/// ```rust,no_run
+/// use btcmdata::redis::route::get_routes;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let client = redis::Client::open("redis://127.0.0.1/").expect("Failed to connect to Redis");
+///     let con = client.get_multiplexed_tokio_connection().await.expect("Failed to get Redis connection");
+///
+///     let routes = get_routes(&con, &[1001, 1002].into_iter().collect()).await.unwrap();
+///     for (clt, devices) in routes {
+///         for (dev, node) in devices {
+///             println!("{}:{} -> {}", clt, dev, node);
+///         }
+///     }
+/// }
+/// ```
+pub async fn get_routes(con: &MultiplexedConnection, clts: &HashSet<u64>) -> Result<HashMap<u64, HashMap<u32, String>>, BtcmDataError> {
+    if clts.is_empty() {
+        return Ok(HashMap::new());
+    }
+    let mut con = con.clone();
+    let clts: Vec<u64> = clts.iter().copied().collect();
+    let min = format!("({}", now_millis());
+    let mut pipe = redis::pipe();
+    for clt in &clts {
+        pipe.hgetall(get_route_key(*clt)).zrangebyscore(get_route_lease_key(*clt), &min, "+inf");
+    }
+    let replies: Vec<RouteReply> = pipe.query_async(&mut con).await?;
+    let result = clts
+        .into_iter()
+        .zip(replies)
+        .filter_map(|(clt, (mut routes, alive))| {
+            routes.retain(|dev, _| alive.contains(dev));
+            (!routes.is_empty()).then_some((clt, routes))
+        })
+        .collect();
+    Ok(result)
+}
+
+/// 异步函数，网关节点宕机或下线后删除该节点上的所有路由。
+/// 路由分批删除，每批一次脚本调用，应在节点确认下线后调用。
+///
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `node`: 网关节点ID。
+///
+/// # 返回值
+/// 返回删除的路由数，已重新连接到其他节点的设备不计算在内。
+pub async fn remove_node_routes(con: &MultiplexedConnection, node: &str) -> Result<usize, BtcmDataError> {
+    let mut con = con.clone();
+    let key = get_node_routes_key(node);
+    let script = redis::Script::new(REMOVE_NODE_ROUTES_SCRIPT);
+    let mut removed = 0;
+    loop {
+        // 路由的键由客户端ID决定，先分批读取节点上的设备，脚本中逐个移除
+        let members: Vec<String> = con.zrange(&key, 0, REMOVE_BATCH - 1).await?;
+        if members.is_empty() {
+            return Ok(removed);
+        }
+        let mut invocation = script.prepare_invoke();
+        invocation.key(&key).arg(node);
+        for member in &members {
+            let clt: u64 = member
+                .split_once(':')
+                .and_then(|(clt, _)| clt.parse().ok())
+                .ok_or_else(|| BtcmDataError::TypeMismatch(format!("invalid member {} in {}", member, key)))?;
+            invocation.key(get_route_key(clt)).key(get_route_lease_key(clt)).arg(member);
+        }
+        let count: usize = invocation.invoke_async(&mut con).await?;
+        removed += count;
+    }
+}
+
+/// 异步函数，删除客户端的全部路由，用于注销账号。
+pub(crate) async fn remove_routes(con: &MultiplexedConnection, clt: ClientID) -> Result<usize, BtcmDataError> {
+    let mut con = con.clone();
+    let user_id: u64 = clt.into();
+    let script = redis::Script::new(REMOVE_ROUTES_SCRIPT);
+    loop {
+        let nodes: HashSet<String> = con.hvals(get_route_key(user_id)).await?;
+        let mut invocation = script.prepare_invoke();
+        invocation.key(get_route_key(user_id)).key(get_route_lease_key(user_id)).arg(user_id);
+        for node in &nodes {
+            invocation.key(get_node_routes_key(node)).arg(node);
+        }
+        let removed: i64 = invocation.invoke_async(&mut con).await?;
+        if removed >= 0 {
+            return Ok(removed as usize);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::redis::test_connection;
+
+    #[tokio::test]
+    #[ignore = "requires BTCMDATA_REDIS_URL"]
+    async fn bind_renew_and_expire() {
+        let con = test_connection().await;
+        let (clt, other) = (ClientID::from(924_001), ClientID::from(924_002));
+        let (gw_a, gw_b) = ("test-gw-924a", "test-gw-924b");
+        for node in [gw_a, gw_b] {
+            remove_node_routes(&con, node).await.unwrap();
+        }
+        for user in [clt, other] {
+            remove_routes(&con, user).await.unwrap();
+        }
+        let mut rcon = con.clone();
+
+        // 重新绑定到另一个节点时返回原节点，并从原节点的设备集合中移除
+        assert_eq!(bind_route(&con, clt, 1, gw_a, DEFAULT_ROUTE_LEASE).await.unwrap(), None);
+        assert_eq!(bind_route(&con, clt, 1, gw_b, DEFAULT_ROUTE_LEASE).await.unwrap().as_deref(), Some(gw_a));
+        assert_eq!(rcon.zcard::<_, usize>(get_node_routes_key(gw_a)).await.unwrap(), 0);
+        assert!(!renew_route(&con, clt, 1, gw_a, DEFAULT_ROUTE_LEASE).await.unwrap());
+        assert!(renew_route(&con, clt, 1, gw_b, DEFAULT_ROUTE_LEASE).await.unwrap());
+
+        // 租约到期后没有路由，下一次绑定时从路由和节点的设备集合中清理
+        bind_route(&con, clt, 2, gw_a, Duration::ZERO).await.unwrap();
+        assert_eq!(get_route(&con, clt, 2).await.unwrap(), None);
+        bind_route(&con, other, 1, gw_a, DEFAULT_ROUTE_LEASE).await.unwrap();
+        let members: Vec<String> = rcon.zrange(get_node_routes_key(gw_a), 0, -1).await.unwrap();
+        assert_eq!(members, vec!["924002:1".to_string()]);
+        bind_route(&con, clt, 3, gw_b, DEFAULT_ROUTE_LEASE).await.unwrap();
+        let devices: HashSet<u32> = rcon.hkeys(get_route_key(924_001)).await.unwrap();
+        assert_eq!(devices, HashSet::from([1, 3]));
+
+        let routes = get_routes(&con, &HashSet::from([924_001, 924_002])).await.unwrap();
+        assert_eq!(routes[&924_001], HashMap::from([(1, gw_b.to_string()), (3, gw_b.to_string())]));
+        assert_eq!(remove_node_routes(&con, gw_b).await.unwrap(), 2);
+        assert_eq!(get_route(&con, clt, 1).await.unwrap(), None);
+        assert_eq!(remove_routes(&con, other).await.unwrap(), 1);
+        assert_eq!(rcon.zcard::<_, usize>(get_node_routes_key(gw_a)).await.unwrap(), 0);
+    }
+}
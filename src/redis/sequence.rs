@@ -0,0 +1,135 @@
+use std::ops::RangeInclusive;
+use redis::{aio::MultiplexedConnection, AsyncCommands};
+use crate::error::BtcmDataError;
+use crate::model::Conversation;
+
+// 每个会话一个计数器，由Redis原子递增分配消息序号，不依赖网关时钟
+// seq:user:1001:1002 -> 单聊会话的最大序号
+// seq:group:3001     -> 群聊会话的最大序号
+static SEQ_PREFIX: &str = "seq:";
+
+/// 获取会话序号键的函数
+fn get_seq_key(conv: Conversation) -> String {
+    format!("{}{}", SEQ_PREFIX, conv)
+}
+
+/// 异步函数，为会话分配下一个消息序号，序号从1开始单调递增。
+///
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `conv`: 会话。
+///
+/// # 返回值
+/// 返回分配的序号。
+///
+/// # 示例
+/// ```rust,no_run
+/// use btcmbase::client::ClientID;
+/// use btcmdata::model::Conversation;
+/// use btcmdata::redis::sequence::next_seq;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let client = redis::Client::open("redis://127.0.0.1/").expect("Failed to connect to Redis");
+///     let con = client.get_multiplexed_tokio_connection().await.expect("Failed to get Redis connection");
+///     let conv = Conversation::user(ClientID::from(1001), ClientID::from(1002));
+///
+///     let seq = next_seq(&con, conv).await.unwrap();
+///     println!("Sequence: {}", seq);
+/// }
+/// ```
+pub async fn next_seq(con: &MultiplexedConnection, conv: Conversation) -> Result<u64, BtcmDataError> {
+    let mut con = con.clone();
+    let seq: u64 = con.incr(get_seq_key(conv), 1u64).await?;
+    Ok(seq)
+}
+
+/// 异步函数，为会话批量分配`count`个连续的消息序号，供高吞吐的发送端使用。
+///
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `conv`: 会话。
+/// - `count`: 分配的序号个数，为0时返回空区间。
+///
+/// # 返回值
+/// 返回分配的序号区间(闭区间)。
+///
+/// # 示例
+/// ```rust,no_run
+/// use btcmbase::client::ClientID;
+/// use btcmdata::model::Conversation;
+/// use btcmdata::redis::sequence::alloc_seq;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let client = redis::Client::open("redis://127.0.0.1/").expect("Failed to connect to Redis");
+///     let con = client.get_multiplexed_tokio_connection().await.expect("Failed to get Redis connection");
+///     let conv = Conversation::group(ClientID::from(3001));
+///
+///     for seq in alloc_seq(&con, conv, 100).await.unwrap() {
+///         println!("Sequence: {}", seq);
+///     }
+/// }
+/// ```
+pub async fn alloc_seq(con: &MultiplexedConnection, conv: Conversation, count: u64) -> Result<RangeInclusive<u64>, BtcmDataError> {
+    let mut con = con.clone();
+    let end: u64 = con.incr(get_seq_key(conv), count).await?;
+    Ok((end + 1 - count)..=end)
+}
+
+/// 异步函数，获取会话当前已分配的最大序号，客户端据此检测消息缺口。
+///
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `conv`: 会话。
+///
+/// # 返回值
+/// 返回最大序号，会话尚未分配过序号时返回0。
+pub async fn get_max_seq(con: &MultiplexedConnection, conv: Conversation) -> Result<u64, BtcmDataError> {
+    let mut con = con.clone();
+    let seq: Option<u64> = con.get(get_seq_key(conv)).await?;
+    Ok(seq.unwrap_or(0))
+}
+
+/// 异步函数，批量获取多个会话当前已分配的最大序号。
+///
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `convs`: 会话列表。
+///
+/// # 返回值
+/// 返回与`convs`一一对应的最大序号，尚未分配过序号的会话为0。
+pub async fn get_max_seqs(con: &MultiplexedConnection, convs: &[Conversation]) -> Result<Vec<u64>, BtcmDataError> {
+    if convs.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut con = con.clone();
+    let keys: Vec<String> = convs.iter().map(|conv| get_seq_key(*conv)).collect();
+    // 使用MGET命令一次获取全部计数器，单个键时也返回数组
+    let seqs: Vec<Option<u64>> = redis::cmd("MGET").arg(keys).query_async(&mut con).await?;
+    Ok(seqs.into_iter().map(|seq| seq.unwrap_or(0)).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use btcmbase::client::ClientID;
+    use super::*;
+    use crate::redis::test_connection;
+
+    #[tokio::test]
+    #[ignore = "requires BTCMDATA_REDIS_URL"]
+    async fn allocates_contiguous_ranges() {
+        let con = test_connection().await;
+        let mut rcon = con.clone();
+        let (conv, unused) = (Conversation::group(ClientID::from(907_001)), Conversation::group(ClientID::from(907_002)));
+        let _: () = rcon.del(&[get_seq_key(conv), get_seq_key(unused)]).await.unwrap();
+
+        assert_eq!(next_seq(&con, conv).await.unwrap(), 1);
+        assert_eq!(alloc_seq(&con, conv, 3).await.unwrap(), 2..=4);
+        // 分配0个序号时返回空区间，计数器不变
+        assert!(alloc_seq(&con, conv, 0).await.unwrap().is_empty());
+        assert_eq!(next_seq(&con, conv).await.unwrap(), 5);
+        assert_eq!(get_max_seqs(&con, &[conv, unused]).await.unwrap(), vec![5, 0]);
+        assert_eq!(get_max_seq(&con, unused).await.unwrap(), 0);
+    }
+}
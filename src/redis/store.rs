@@ -0,0 +1,282 @@
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+use async_trait::async_trait;
+use btcmbase::client::ClientID;
+use redis::aio::MultiplexedConnection;
+use crate::error::BtcmDataError;
+use crate::model::{AccountDeletionReport, DeletedUser, Device, GroupInfo, GroupRole, UniqueField, User};
+use crate::store::{AccountStore, BlockStore, ContactStore, DeviceStore, GroupStore, UserStore};
+use super::{account, blocklist, device, groups, users};
+
+/// # Redis Store
+///
+/// The `RedisStore` struct implements the storage traits of `crate::store` on top of
+/// the free functions of `redis::users`, `redis::groups` and `redis::device`.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use btcmdata::redis::init_redis_database;
+/// use btcmdata::redis::store::RedisStore;
+/// use btcmdata::store::UserStore;
+/// use btcmbase::client::ClientID;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     init_redis_database("redis://127.0.0.1/").await.expect("Failed to initialize Redis database.");
+///     let store = RedisStore::from_singleton().expect("RedisDBManager singleton instance does not exist.");
+///     let user = store.get_user(ClientID::from(123)).await.unwrap();
+///     println!("User info: {:?}", user);
+/// }
+/// ```
+#[derive(Clone)]
+pub struct RedisStore {
+    connect: MultiplexedConnection,
+}
+
+impl RedisStore {
+    /// Creates a store on top of an existing multiplexed connection.
+    pub fn new(connect: MultiplexedConnection) -> Self {
+        RedisStore { connect }
+    }
+
+    /// Creates a store from the connection of the `RedisDBManager` singleton,
+    /// `None` if `init_redis_database` has not been called yet.
+    pub fn from_singleton() -> Option<Self> {
+        super::get_redis_connect().map(RedisStore::new)
+    }
+}
+
+#[async_trait]
+impl UserStore for RedisStore {
+    async fn add_user(&self, clt: ClientID, user: &User) -> Result<(), BtcmDataError> {
+        users::add_user(&self.connect, clt, user).await
+    }
+
+    async fn update_user(&self, clt: ClientID, user: &User) -> Result<u64, BtcmDataError> {
+        users::update_user(&self.connect, clt, user).await
+    }
+
+    async fn get_user(&self, clt: ClientID) -> Result<Option<User>, BtcmDataError> {
+        users::get_user(&self.connect, clt).await
+    }
+
+    async fn get_user_fields(&self, clt: ClientID, fields: &[&str]) -> Result<HashMap<String, String>, BtcmDataError> {
+        users::get_user_fields(&self.connect, clt, fields).await
+    }
+
+    async fn set_user_fields(&self, clt: ClientID, fields: &HashMap<String, String>) -> Result<(), BtcmDataError> {
+        users::set_user_fields(&self.connect, clt, fields).await
+    }
+
+    async fn delete_user_fields(&self, clt: ClientID, fields: &[&str]) -> Result<usize, BtcmDataError> {
+        users::delete_user_fields(&self.connect, clt, fields).await
+    }
+
+    async fn find_user_by(&self, field: UniqueField, value: &str) -> Result<Option<u64>, BtcmDataError> {
+        users::find_user_by(&self.connect, field, value).await
+    }
+
+    async fn search_users_by_nickname(&self, prefix: &str, offset: usize, count: usize) -> Result<Vec<u64>, BtcmDataError> {
+        users::search_users_by_nickname(&self.connect, prefix, offset, count).await
+    }
+
+    async fn exists_user(&self, clt: ClientID) -> Result<bool, BtcmDataError> {
+        users::exists_user(&mut self.connect.clone(), clt).await
+    }
+
+    async fn remove_user(&self, clt: ClientID, reason: Option<&str>) -> Result<bool, BtcmDataError> {
+        users::remove_user(&mut self.connect.clone(), clt, reason).await
+    }
+
+    async fn restore_user(&self, clt: ClientID) -> Result<(), BtcmDataError> {
+        users::restore_user(&self.connect, clt).await
+    }
+
+    async fn list_deleted_users(&self, offset: usize, count: usize) -> Result<Vec<DeletedUser>, BtcmDataError> {
+        users::list_deleted_users(&self.connect, offset, count).await
+    }
+
+    async fn purge_deleted_users(&self, retention: Duration) -> Result<usize, BtcmDataError> {
+        users::purge_deleted_users(&self.connect, retention).await
+    }
+}
+
+#[async_trait]
+impl ContactStore for RedisStore {
+    async fn add_user_contacts(&self, clt: ClientID, hs: &HashSet<u64>) -> Result<(), BtcmDataError> {
+        users::add_user_contacts(&self.connect, clt, hs).await
+    }
+
+    async fn del_user_contacts(&self, clt: ClientID, hs: &HashSet<u64>) -> Result<(), BtcmDataError> {
+        users::del_user_contacts(&self.connect, clt, hs).await
+    }
+
+    async fn get_user_contacts(&self, clt: ClientID) -> Result<HashSet<u64>, BtcmDataError> {
+        users::get_user_contacts(&self.connect, clt).await
+    }
+
+    async fn add_contact(&self, a: ClientID, b: ClientID) -> Result<(), BtcmDataError> {
+        users::add_contact(&self.connect, a, b).await
+    }
+
+    async fn remove_contact(&self, a: ClientID, b: ClientID) -> Result<(), BtcmDataError> {
+        users::remove_contact(&self.connect, a, b).await
+    }
+
+    async fn follow(&self, follower: ClientID, followee: ClientID) -> Result<(), BtcmDataError> {
+        users::follow(&self.connect, follower, followee).await
+    }
+
+    async fn unfollow(&self, follower: ClientID, followee: ClientID) -> Result<(), BtcmDataError> {
+        users::unfollow(&self.connect, follower, followee).await
+    }
+
+    async fn add_group_contacts(&self, clt: ClientID, hs: &HashSet<u64>) -> Result<(), BtcmDataError> {
+        users::add_group_contacts(&self.connect, clt, hs).await
+    }
+
+    async fn del_group_contacts(&self, clt: ClientID, hs: &HashSet<u64>) -> Result<(), BtcmDataError> {
+        users::del_group_contacts(&self.connect, clt, hs).await
+    }
+
+    async fn get_group_contacts(&self, clt: ClientID) -> Result<HashSet<u64>, BtcmDataError> {
+        users::get_group_contacts(&self.connect, clt).await
+    }
+}
+
+#[async_trait]
+impl BlockStore for RedisStore {
+    async fn block_user(&self, clt: ClientID, blocked: ClientID) -> Result<(), BtcmDataError> {
+        blocklist::block_user(&self.connect, clt, blocked).await
+    }
+
+    async fn unblock_user(&self, clt: ClientID, blocked: ClientID) -> Result<bool, BtcmDataError> {
+        blocklist::unblock_user(&self.connect, clt, blocked).await
+    }
+
+    async fn get_blocklist(&self, clt: ClientID) -> Result<HashSet<u64>, BtcmDataError> {
+        blocklist::get_blocklist(&self.connect, clt).await
+    }
+
+    async fn is_blocked(&self, clt: ClientID, other: ClientID) -> Result<bool, BtcmDataError> {
+        blocklist::is_blocked(&self.connect, clt, other).await
+    }
+}
+
+#[async_trait]
+impl GroupStore for RedisStore {
+    async fn add_group(&self, clt: ClientID, hs: &HashSet<u64>) -> Result<(), BtcmDataError> {
+        groups::add_group(&self.connect, clt, hs).await
+    }
+
+    async fn del_group(&self, clt: ClientID, hs: &HashSet<u64>) -> Result<(), BtcmDataError> {
+        groups::del_group(&self.connect, clt, hs).await
+    }
+
+    async fn create_group(&self, clt: ClientID, info: &GroupInfo, hs: &HashSet<u64>) -> Result<(), BtcmDataError> {
+        groups::create_group(&self.connect, clt, info, hs).await
+    }
+
+    async fn get_group_info(&self, clt: ClientID) -> Result<Option<GroupInfo>, BtcmDataError> {
+        groups::get_group_info(&self.connect, clt).await
+    }
+
+    async fn transfer_owner(&self, clt: ClientID, new_owner: ClientID) -> Result<(), BtcmDataError> {
+        groups::transfer_owner(&self.connect, clt, new_owner).await
+    }
+
+    async fn promote_admin(&self, clt: ClientID, member: ClientID) -> Result<(), BtcmDataError> {
+        groups::promote_admin(&self.connect, clt, member).await
+    }
+
+    async fn demote_admin(&self, clt: ClientID, member: ClientID) -> Result<bool, BtcmDataError> {
+        groups::demote_admin(&self.connect, clt, member).await
+    }
+
+    async fn get_member_role(&self, clt: ClientID, member: ClientID) -> Result<Option<GroupRole>, BtcmDataError> {
+        groups::get_member_role(&self.connect, clt, member).await
+    }
+
+    async fn get_group_admins(&self, clt: ClientID) -> Result<HashSet<u64>, BtcmDataError> {
+        groups::get_group_admins(&self.connect, clt).await
+    }
+
+    async fn get_group(&self, clt: ClientID) -> Result<HashSet<u64>, BtcmDataError> {
+        groups::get_group(&self.connect, clt).await
+    }
+
+    async fn exists_group(&self, clt: ClientID) -> Result<bool, BtcmDataError> {
+        groups::exists_group(&mut self.connect.clone(), clt).await
+    }
+
+    async fn remove_group(&self, clt: ClientID) -> Result<bool, BtcmDataError> {
+        groups::remove_group(&mut self.connect.clone(), clt).await
+    }
+
+    async fn groups_of_user(&self, clt: ClientID) -> Result<HashSet<u64>, BtcmDataError> {
+        groups::groups_of_user(&self.connect, clt).await
+    }
+
+    async fn common_groups(&self, a: ClientID, b: ClientID) -> Result<HashSet<u64>, BtcmDataError> {
+        groups::common_groups(&self.connect, a, b).await
+    }
+}
+
+#[async_trait]
+impl DeviceStore for RedisStore {
+    async fn add_dev2clt(&self, clt: ClientID, devs: &HashSet<u64>) -> Result<(), BtcmDataError> {
+        device::add_dev2clt(&self.connect, clt, devs).await
+    }
+
+    async fn del_dev4clt(&self, clt: ClientID, devs: &HashSet<u64>) -> Result<(), BtcmDataError> {
+        device::del_dev4clt(&self.connect, clt, devs).await
+    }
+
+    async fn get_devclt_set(&self, clt: ClientID) -> Result<HashSet<u64>, BtcmDataError> {
+        device::get_devclt_set(&self.connect, clt).await
+    }
+
+    async fn exists_devclt(&self, clt: ClientID) -> Result<bool, BtcmDataError> {
+        device::exists_devclt(&mut self.connect.clone(), clt).await
+    }
+
+    async fn remove_devclt_set(&self, clt: ClientID) -> Result<bool, BtcmDataError> {
+        device::remove_devclt_set(&mut self.connect.clone(), clt).await
+    }
+
+    async fn add_dev2clt_hash(&self, clt: ClientID, dev: u32, device: &Device) -> Result<(), BtcmDataError> {
+        device::add_dev2clt_hash(&self.connect, clt, dev, device).await
+    }
+
+    async fn get_device(&self, clt: ClientID, dev: u32) -> Result<Option<Device>, BtcmDataError> {
+        device::get_device(&self.connect, clt, dev).await
+    }
+
+    async fn get_device_fields(&self, clt: ClientID, dev: u32, fields: &[&str]) -> Result<HashMap<String, String>, BtcmDataError> {
+        device::get_device_fields(&self.connect, clt, dev, fields).await
+    }
+
+    async fn set_device_fields(&self, clt: ClientID, dev: u32, fields: &HashMap<String, String>) -> Result<(), BtcmDataError> {
+        device::set_device_fields(&self.connect, clt, dev, fields).await
+    }
+
+    async fn delete_device_fields(&self, clt: ClientID, dev: u32, fields: &[&str]) -> Result<usize, BtcmDataError> {
+        device::delete_device_fields(&self.connect, clt, dev, fields).await
+    }
+
+    async fn exists_device(&self, clt: ClientID, dev: u32) -> Result<bool, BtcmDataError> {
+        device::exists_device(&mut self.connect.clone(), clt, dev).await
+    }
+
+    async fn remove_device(&self, clt: ClientID, dev: u32) -> Result<bool, BtcmDataError> {
+        device::remove_device(&mut self.connect.clone(), clt, dev).await
+    }
+}
+
+#[async_trait]
+impl AccountStore for RedisStore {
+    async fn delete_account(&self, clt: ClientID, reason: Option<&str>) -> Result<AccountDeletionReport, BtcmDataError> {
+        account::delete_account(&self.connect, clt, reason).await
+    }
+}
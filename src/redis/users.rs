@@ -1,36 +1,225 @@
 use std::collections::{HashMap, HashSet};
+use std::time::Duration;
 use btcmbase::client::ClientID;
-use redis::{aio::MultiplexedConnection, AsyncCommands, RedisResult};
+use redis::{aio::MultiplexedConnection, AsyncCommands};
+use crate::error::BtcmDataError;
+use crate::model::{DeletedUser, UniqueField, User};
+use crate::now_millis;
+use super::blocklist;
 
-/// 异步函数，将用户信息添加到Redis中。
+/// 异步函数，将用户信息写入Redis中，整体替换原有的用户信息，版本号加1。
+/// `user`中的版本号被忽略，需要检查版本号时使用`update_user`。
+/// 用户名、手机号或邮箱已被其他用户使用时返回`BtcmDataError::Duplicate`，不做任何修改。
 /// 
 /// # 参数
 /// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
 /// - `clt`: 指定的用户ID。
-/// - `hm`: HashMap<String, String>，包含用户信息的哈希表。
+/// - `user`: 用户信息。
 /// 
 /// # 示例
-/// ```rust
-/// use std::collections::HashMap;
+/// ```rust,no_run
 /// use btcmbase::client::ClientID;
-/// use redis::{aio::MultiplexedConnection, AsyncCommands, RedisResult};
+/// use btcmdata::model::User;
+/// use btcmdata::redis::users::add_user;
 /// 
 /// #[tokio::main]
 /// async fn main() {
 ///     let client = redis::Client::open("redis://127.0.0.1/").expect("Failed to connect to Redis");
-///     let mut con = client.get_async_connection().await.expect("Failed to get Redis connection");
+///     let con = client.get_multiplexed_tokio_connection().await.expect("Failed to get Redis connection");
 ///     let user_id = ClientID::from(123);
-///     let user_info: HashMap<String, String> = [("name", "John"), ("age", "30")].iter().cloned().collect();
+///     let mut user = User::new("John");
+///     user.extra_mut().insert("age".to_string(), "30".to_string());
 ///     
-///     add_user(&mut con, user_id, &user_info).await;
+///     add_user(&con, user_id, &user).await.unwrap();
 /// }
 /// ```
-pub async fn add_user(con: &MultiplexedConnection, clt: ClientID, hm: &HashMap<String, String>) {
+pub async fn add_user(con: &MultiplexedConnection, clt: ClientID, user: &User) -> Result<(), BtcmDataError> {
     let mut con = con.clone();
-    let user_key = get_user_key(clt);
     
-    // 使用cmd函数构建一个hset命令，将HashMap中的数据写入Redis的hash结构中，假设hash的名字是"user"
-    let _: () = redis::cmd("HSET").arg(user_key).arg(hm).query_async(&mut con).await.unwrap();
+    // 在脚本中删除旧哈希、写入新字段并递增版本号，避免残留已不存在的字段
+    let fields = user.to_fields();
+    let mut args: Vec<&str> = Vec::with_capacity(fields.len() * 2);
+    for (field, value) in &fields {
+        args.push(field);
+        args.push(value);
+    }
+    write_user(&mut con, clt, "", "replace", &args).await?;
+    Ok(())
+}
+
+/// 用户脚本共用的KEYS和ARGV前缀:
+/// KEYS[1] = users, KEYS[2] = del_users, KEYS[3] = 已删除用户索引, KEYS[4] = 删除原因,
+/// KEYS[5] = 昵称索引, KEYS[6..n+5] = 唯一字段索引; ARGV[1] = 用户ID, ARGV[2..n+1] = 与索引对应的唯一字段名
+fn user_script_invocation(script: &redis::Script, clt: ClientID) -> redis::ScriptInvocation<'_> {
+    let user_id: u64 = clt.into();
+    let mut invocation = script.prepare_invoke();
+    invocation
+        .key(get_user_key(clt))
+        .key(get_del_user_key(clt))
+        .key(DEL_USER_INDEX)
+        .key(DEL_USER_REASONS)
+        .key(USER_NICKNAME_INDEX);
+    for field in UniqueField::ALL {
+        invocation.key(get_user_index_key(field));
+    }
+    invocation.arg(user_id);
+    for field in UniqueField::ALL {
+        invocation.arg(field.as_str());
+    }
+    invocation
+}
+
+/// 唯一字段重复时的错误，`index`为脚本返回的字段序号(从1开始)
+fn duplicate_error(index: usize) -> BtcmDataError {
+    let field = UniqueField::ALL[index - 1];
+    BtcmDataError::Duplicate(format!("{} is already used by another user", field))
+}
+
+/// 写入用户信息并维护版本号、昵称索引和唯一字段索引:整体替换、合并字段或删除字段，每次写入版本号加1。
+/// 先检查全部唯一字段，任一字段的新值已被其他用户使用时不做任何修改。
+/// 整体替换不存在的用户(即注册)时丢弃该ID已删除的用户信息。
+/// KEYS和ARGV[1..n+1]见`user_script_invocation`, ARGV[n+2] = 期望的版本号(空串表示不检查),
+/// ARGV[n+3] = replace/merge/delete, 其余ARGV = 字段和值(replace/merge)或字段名(delete)
+/// 返回 {新版本号, 删除的字段数}，新版本号为 -1 表示用户不存在, -2 表示版本号不一致,
+/// -3 表示唯一字段重复，此时第二个值为重复字段的序号(从1开始)
+static WRITE_USER_SCRIPT: &str = r"
+local n = #KEYS - 5
+local expected, mode, first = ARGV[n + 2], ARGV[n + 3], n + 4
+local exists = redis.call('EXISTS', KEYS[1]) == 1
+local cur = tonumber(redis.call('HGET', KEYS[1], 'version') or '0')
+if not exists and (expected ~= '' or mode ~= 'replace') then
+    return {-1, 0}
+end
+if expected ~= '' and tonumber(expected) ~= cur then
+    return {-2, 0}
+end
+local old_nickname = redis.call('HGET', KEYS[1], 'nickname')
+local new_nickname = mode ~= 'replace' and old_nickname
+local old, new = {}, {}
+for i = 1, n do
+    local value = redis.call('HGET', KEYS[1], ARGV[1 + i])
+    if value == '' then
+        value = false
+    end
+    old[i] = value
+    new[i] = mode ~= 'replace' and value
+end
+local step = mode == 'delete' and 1 or 2
+for j = first, #ARGV, step do
+    if ARGV[j] == 'nickname' then
+        new_nickname = step == 2 and ARGV[j + 1]
+    end
+    for i = 1, n do
+        if ARGV[j] == ARGV[1 + i] then
+            new[i] = step == 2 and ARGV[j + 1] ~= '' and ARGV[j + 1]
+        end
+    end
+end
+for i = 1, n do
+    if new[i] and new[i] ~= old[i] then
+        local owner = redis.call('HGET', KEYS[5 + i], new[i])
+        if owner and owner ~= ARGV[1] then
+            return {-3, i}
+        end
+    end
+end
+local removed = 0
+if mode == 'delete' then
+    for j = first, #ARGV do
+        removed = removed + redis.call('HDEL', KEYS[1], ARGV[j])
+    end
+    if removed == 0 then
+        return {cur, 0}
+    end
+else
+    if mode == 'replace' then
+        redis.call('DEL', KEYS[1])
+    end
+    if #ARGV >= first then
+        redis.call('HSET', KEYS[1], unpack(ARGV, first))
+    end
+end
+if not exists then
+    redis.call('DEL', KEYS[2])
+    redis.call('ZREM', KEYS[3], ARGV[1])
+    redis.call('HDEL', KEYS[4], ARGV[1])
+end
+for i = 1, n do
+    if new[i] ~= old[i] then
+        if old[i] and redis.call('HGET', KEYS[5 + i], old[i]) == ARGV[1] then
+            redis.call('HDEL', KEYS[5 + i], old[i])
+        end
+        if new[i] then
+            redis.call('HSET', KEYS[5 + i], new[i], ARGV[1])
+        end
+    end
+end
+if old_nickname then
+    redis.call('ZREM', KEYS[5], string.lower(old_nickname) .. '\0' .. ARGV[1])
+end
+if new_nickname and new_nickname ~= '' then
+    redis.call('ZADD', KEYS[5], 0, string.lower(new_nickname) .. '\0' .. ARGV[1])
+end
+redis.call('HSET', KEYS[1], 'version', cur + 1)
+return {cur + 1, removed}
+";
+
+/// 执行WRITE_USER_SCRIPT并将返回码转换为错误，返回 (新版本号, 删除的字段数)
+async fn write_user(con: &mut MultiplexedConnection, clt: ClientID, expected: &str, mode: &str, args: &[&str]) -> Result<(u64, usize), BtcmDataError> {
+    let user_id: u64 = clt.into();
+    let script = redis::Script::new(WRITE_USER_SCRIPT);
+    let mut invocation = user_script_invocation(&script, clt);
+    let (version, removed): (i64, usize) = invocation.arg(expected).arg(mode).arg(args).invoke_async(con).await?;
+    match version {
+        -1 => Err(BtcmDataError::NotFound(format!("user {}", user_id))),
+        -2 => Err(BtcmDataError::Conflict(format!("user {} was modified concurrently", user_id))),
+        -3 => Err(duplicate_error(removed)),
+        _ => Ok((version as u64, removed)),
+    }
+}
+
+/// 异步函数，以比较并设置(CAS)的方式整体更新用户信息。
+/// 
+/// 只有存储中的版本号仍等于`user.version()`(即读取时的版本号)时才写入，
+/// 否则说明其他网关已修改过该用户，返回`BtcmDataError::Conflict`，调用方应重新读取后再更新。
+/// 
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `clt`: 指定的用户ID。
+/// - `user`: 新的用户信息，版本号为读取时的版本号。
+/// 
+/// # 返回值
+/// 返回写入后的版本号；用户不存在时返回`BtcmDataError::NotFound`，
+/// 唯一字段已被其他用户使用时返回`BtcmDataError::Duplicate`。
+/// 
+/// # 示例
+/// ```rust,no_run
+/// use btcmbase::client::ClientID;
+/// use btcmdata::redis::users::{get_user, update_user};
+/// 
+/// #[tokio::main]
+/// async fn main() {
+///     let client = redis::Client::open("redis://127.0.0.1/").expect("Failed to connect to Redis");
+///     let con = client.get_multiplexed_tokio_connection().await.expect("Failed to get Redis connection");
+///     let user_id = ClientID::from(123);
+///     
+///     let mut user = get_user(&con, user_id).await.unwrap().expect("user does not exist");
+///     user.set_nickname("Johnny".to_string());
+///     let version = update_user(&con, user_id, &user).await.unwrap();
+///     println!("Version: {}", version);
+/// }
+/// ```
+pub async fn update_user(con: &MultiplexedConnection, clt: ClientID, user: &User) -> Result<u64, BtcmDataError> {
+    let mut con = con.clone();
+    let expected = user.version().to_string();
+    let fields = user.to_fields();
+    let mut args: Vec<&str> = Vec::with_capacity(fields.len() * 2);
+    for (field, value) in &fields {
+        args.push(field);
+        args.push(value);
+    }
+    let (version, _) = write_user(&mut con, clt, &expected, "replace", &args).await?;
+    Ok(version)
 }
 
 /// 异步函数，获取指定用户的信息。
@@ -40,25 +229,24 @@ pub async fn add_user(con: &MultiplexedConnection, clt: ClientID, hm: &HashMap<S
 /// - `clt`: 指定的用户ID。
 /// 
 /// # 返回值
-/// 返回一个HashMap<String, String>，包含用户的信息。
+/// 返回用户信息，用户不存在时返回None。
 /// 
 /// # 示例
-/// ```rust
-/// use std::collections::HashMap;
+/// ```rust,no_run
 /// use btcmbase::client::ClientID;
-/// use redis::{aio::MultiplexedConnection, AsyncCommands, RedisResult};
+/// use btcmdata::redis::users::get_user;
 /// 
 /// #[tokio::main]
 /// async fn main() {
 ///     let client = redis::Client::open("redis://127.0.0.1/").expect("Failed to connect to Redis");
-///     let mut con = client.get_async_connection().await.expect("Failed to get Redis connection");
+///     let con = client.get_multiplexed_tokio_connection().await.expect("Failed to get Redis connection");
 ///     let user_id = ClientID::from(123);
 ///     
-///     let user_info = get_user(&mut con, user_id).await;
-///     println!("User info: {:?}", user_info);
+///     let user = get_user(&con, user_id).await.unwrap();
+///     println!("User info: {:?}", user);
 /// }
 /// ```
-pub async fn get_user(con: &MultiplexedConnection, clt: ClientID) -> HashMap<String, String> {
+pub async fn get_user(con: &MultiplexedConnection, clt: ClientID) -> Result<Option<User>, BtcmDataError> {
     let mut con = con.clone();
     let user_key = get_user_key(clt);
     
@@ -67,10 +255,104 @@ pub async fn get_user(con: &MultiplexedConnection, clt: ClientID) -> HashMap<Str
         ::cmd("HGETALL")
         .arg(user_key)
         .query_async(&mut con)
-        .await
-        .unwrap();
+        .await?;
     
-    return result;
+    if result.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(User::from_fields(result)?))
+}
+
+/// 异步函数，只读取用户信息中的指定字段。
+/// 
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `clt`: 指定的用户ID。
+/// - `fields`: 要读取的字段名。
+/// 
+/// # 返回值
+/// 返回字段名到值的HashMap，不存在的字段不包含在结果中。
+/// 
+/// # 示例
+/// ```rust,no_run
+/// use std::collections::HashMap;
+/// use btcmbase::client::ClientID;
+/// use btcmdata::redis::users::{get_user_fields, set_user_fields};
+/// 
+/// #[tokio::main]
+/// async fn main() {
+///     let client = redis::Client::open("redis://127.0.0.1/").expect("Failed to connect to Redis");
+///     let con = client.get_multiplexed_tokio_connection().await.expect("Failed to get Redis connection");
+///     let user_id = ClientID::from(123);
+///     
+///     let fields: HashMap<String, String> = [("avatar".to_string(), "a.png".to_string())].into_iter().collect();
+///     set_user_fields(&con, user_id, &fields).await.unwrap();
+///     let avatar = get_user_fields(&con, user_id, &["avatar"]).await.unwrap();
+///     println!("Avatar: {:?}", avatar.get("avatar"));
+/// }
+/// ```
+pub async fn get_user_fields(con: &MultiplexedConnection, clt: ClientID, fields: &[&str]) -> Result<HashMap<String, String>, BtcmDataError> {
+    get_hash_fields(con, get_user_key(clt), fields).await
+}
+
+/// 异步函数，只更新用户信息中的指定字段，其余字段保持不变，版本号加1。
+/// 
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `clt`: 指定的用户ID。
+/// - `fields`: 要写入的字段名和值。
+/// 
+/// # 返回值
+/// 用户不存在时返回`BtcmDataError::NotFound`，字段值不符合`User`的字段类型时返回`BtcmDataError::TypeMismatch`，
+/// 写入版本号字段时返回`BtcmDataError::Conflict`，唯一字段已被其他用户使用时返回`BtcmDataError::Duplicate`。
+pub async fn set_user_fields(con: &MultiplexedConnection, clt: ClientID, fields: &HashMap<String, String>) -> Result<(), BtcmDataError> {
+    User::check_fields(fields)?;
+    if fields.is_empty() {
+        return Ok(());
+    }
+    let mut con = con.clone();
+    let mut args: Vec<&str> = Vec::with_capacity(fields.len() * 2);
+    for (field, value) in fields {
+        args.push(field);
+        args.push(value);
+    }
+    write_user(&mut con, clt, "", "merge", &args).await?;
+    Ok(())
+}
+
+/// 异步函数，删除用户信息中的指定字段，有字段被删除时版本号加1。
+/// 
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `clt`: 指定的用户ID。
+/// - `fields`: 要删除的字段名。
+/// 
+/// # 返回值
+/// 返回实际删除的字段数；用户不存在时返回`BtcmDataError::NotFound`，删除版本号字段时返回`BtcmDataError::Conflict`。
+pub async fn delete_user_fields(con: &MultiplexedConnection, clt: ClientID, fields: &[&str]) -> Result<usize, BtcmDataError> {
+    User::check_field_names(fields.iter().copied())?;
+    if fields.is_empty() {
+        return Ok(0);
+    }
+    let mut con = con.clone();
+    let (_, removed) = write_user(&mut con, clt, "", "delete", fields).await?;
+    Ok(removed)
+}
+
+/// 使用HMGET读取哈希中的指定字段
+pub(crate) async fn get_hash_fields(con: &MultiplexedConnection, key: String, fields: &[&str]) -> Result<HashMap<String, String>, BtcmDataError> {
+    if fields.is_empty() {
+        return Ok(HashMap::new());
+    }
+    let mut con = con.clone();
+    // 使用HMGET命令，单个字段时也返回数组
+    let values: Vec<Option<String>> = redis::cmd("HMGET").arg(key).arg(fields).query_async(&mut con).await?;
+    let result = fields
+        .iter()
+        .zip(values)
+        .filter_map(|(field, value)| value.map(|value| (field.to_string(), value)))
+        .collect();
+    Ok(result)
 }
 
 /// 异步函数，检查指定用户是否存在。
@@ -80,63 +362,73 @@ pub async fn get_user(con: &MultiplexedConnection, clt: ClientID) -> HashMap<Str
 /// - `clt`: 指定的用户ID。
 /// 
 /// # 返回值
-/// 返回一个RedisResult<bool>，表示用户是否存在。
+/// 返回一个Result<bool, BtcmDataError>，表示用户是否存在。
 /// 
 /// # 示例
-/// ```rust
+/// ```rust,no_run
 /// use btcmbase::client::ClientID;
-/// use redis::{aio::MultiplexedConnection, AsyncCommands, RedisResult};
+/// use btcmdata::redis::users::exists_user;
 /// 
 /// #[tokio::main]
 /// async fn main() {
 ///     let client = redis::Client::open("redis://127.0.0.1/").expect("Failed to connect to Redis");
-///     let mut con = client.get_async_connection().await.expect("Failed to get Redis connection");
+///     let mut con = client.get_multiplexed_tokio_connection().await.expect("Failed to get Redis connection");
 ///     let user_id = ClientID::from(123);
 ///     
-///     let user_exists = exists_user(&mut con, user_id).await;
+///     let user_exists = exists_user(&mut con, user_id).await.unwrap();
 ///     println!("User exists: {:?}", user_exists);
 /// }
 /// ```
-pub async fn exists_user(con: &mut MultiplexedConnection, clt: ClientID) -> RedisResult<bool> {
+pub async fn exists_user(con: &mut MultiplexedConnection, clt: ClientID) -> Result<bool, BtcmDataError> {
     let mut con = con.clone();
     let user_key = get_user_key(clt);
     
     // 调用redis-rs提供的exists方法，返回一个布尔值
-    let result: bool = con.exists(user_key).await.unwrap();
+    let result: bool = con.exists(user_key).await?;
     Ok(result)
 }
 
-/// 异步函数，从Redis中删除指定用户。
+/// 异步函数，从Redis中软删除指定用户，记录删除时间和原因。
+/// 用户信息被移到`del_users:<id>`，在`purge_deleted_users`清除之前可以用`restore_user`恢复；
+/// 用户的用户名、手机号和邮箱同时被释放，可以被其他用户使用。
 /// 
 /// # 参数
 /// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
 /// - `clt`: 指定的用户ID。
+/// - `reason`: 删除原因。
 /// 
 /// # 返回值
-/// 返回一个RedisResult<bool>，表示用户是否成功删除。
+/// 返回一个Result<bool, BtcmDataError>，表示用户是否被本次调用删除，用户已被删除时返回false。
+/// 用户不存在也没有被删除过时返回`BtcmDataError::NotFound`。
 /// 
 /// # 示例
-/// ```rust
+/// ```rust,no_run
 /// use btcmbase::client::ClientID;
-/// use redis::{aio::MultiplexedConnection, AsyncCommands, RedisResult};
+/// use btcmdata::redis::users::remove_user;
 /// 
 /// #[tokio::main]
 /// async fn main() {
 ///     let client = redis::Client::open("redis://127.0.0.1/").expect("Failed to connect to Redis");
-///     let mut con = client.get_async_connection().await.expect("Failed to get Redis connection");
+///     let mut con = client.get_multiplexed_tokio_connection().await.expect("Failed to get Redis connection");
 ///     let user_id = ClientID::from(123);
 ///     
-///     let user_removed = remove_user(&mut con, user_id).await;
+///     let user_removed = remove_user(&mut con, user_id, Some("requested by user")).await.unwrap();
 ///     println!("User removed: {:?}", user_removed);
 /// }
 /// ```
-pub async fn remove_user(con: &mut MultiplexedConnection, clt: ClientID) -> RedisResult<bool> {
+pub async fn remove_user(con: &mut MultiplexedConnection, clt: ClientID, reason: Option<&str>) -> Result<bool, BtcmDataError> {
     let mut con = con.clone();
-    let user_key = get_user_key(clt);
-    let del_user_key = get_del_user_key(clt);
     
-    // 将用户键重命名为删除用户键
-    con.rename(user_key, del_user_key).await
+    // 在脚本中释放用户的索引，并将用户键重命名为删除用户键
+    let script = redis::Script::new(REMOVE_USER_SCRIPT);
+    let mut invocation = user_script_invocation(&script, clt);
+    let result: i64 = invocation.arg(now_millis()).arg(reason.unwrap_or("")).invoke_async(&mut con).await?;
+    if result < 0 {
+        // 用户不存在时RENAME会返回错误，脚本提前返回-1，这里转换为NotFound
+        let user_id: u64 = clt.into();
+        return Err(BtcmDataError::NotFound(format!("user {}", user_id)));
+    }
+    Ok(result == 1)
     
     // 这里注释了原来的删除用户的代码，因为在rename时已经将用户移动到了删除用户键
     // let result: bool = con.del(user_key).await.unwrap();
@@ -147,6 +439,313 @@ pub async fn remove_user(con: &mut MultiplexedConnection, clt: ClientID) -> Redi
     // Ok(result)
 }
 
+/// 软删除用户:将其移出昵称索引、释放其唯一字段索引，将用户键重命名为删除用户键并记录删除时间和原因。
+/// KEYS和ARGV[1..n+1]见`user_script_invocation`, ARGV[n+2] = 删除时间, ARGV[n+3] = 删除原因(空串表示没有)
+/// 返回1，用户已被删除时返回0，用户不存在时返回-1
+static REMOVE_USER_SCRIPT: &str = r"
+local n = #KEYS - 5
+if redis.call('EXISTS', KEYS[1]) == 0 then
+    return redis.call('EXISTS', KEYS[2]) == 1 and 0 or -1
+end
+local nickname = redis.call('HGET', KEYS[1], 'nickname')
+if nickname then
+    redis.call('ZREM', KEYS[5], string.lower(nickname) .. '\0' .. ARGV[1])
+end
+for i = 1, n do
+    local value = redis.call('HGET', KEYS[1], ARGV[1 + i])
+    if value and redis.call('HGET', KEYS[5 + i], value) == ARGV[1] then
+        redis.call('HDEL', KEYS[5 + i], value)
+    end
+end
+redis.call('RENAME', KEYS[1], KEYS[2])
+redis.call('ZADD', KEYS[3], ARGV[n + 2], ARGV[1])
+if ARGV[n + 3] ~= '' then
+    redis.call('HSET', KEYS[4], ARGV[1], ARGV[n + 3])
+else
+    redis.call('HDEL', KEYS[4], ARGV[1])
+end
+return 1
+";
+
+/// 异步函数，恢复已软删除的用户，版本号加1。
+/// 
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `clt`: 指定的用户ID。
+/// 
+/// # 返回值
+/// 用户没有被删除时返回`BtcmDataError::NotFound`，该ID已重新注册时返回`BtcmDataError::Conflict`，
+/// 用户的用户名、手机号或邮箱在删除期间已被其他用户使用时返回`BtcmDataError::Duplicate`。
+/// 
+/// # 示例
+/// ```rust,no_run
+/// use btcmbase::client::ClientID;
+/// use btcmdata::redis::users::{list_deleted_users, restore_user};
+/// 
+/// #[tokio::main]
+/// async fn main() {
+///     let client = redis::Client::open("redis://127.0.0.1/").expect("Failed to connect to Redis");
+///     let con = client.get_multiplexed_tokio_connection().await.expect("Failed to get Redis connection");
+///     
+///     for deleted in list_deleted_users(&con, 0, 20).await.unwrap() {
+///         println!("{} deleted at {}: {:?}", deleted.client_id(), deleted.deleted_at(), deleted.reason());
+///     }
+///     restore_user(&con, ClientID::from(123)).await.unwrap();
+/// }
+/// ```
+pub async fn restore_user(con: &MultiplexedConnection, clt: ClientID) -> Result<(), BtcmDataError> {
+    let mut con = con.clone();
+    let user_id: u64 = clt.into();
+    let script = redis::Script::new(RESTORE_USER_SCRIPT);
+    let (code, index): (i64, usize) = user_script_invocation(&script, clt).invoke_async(&mut con).await?;
+    match code {
+        -1 => Err(BtcmDataError::NotFound(format!("deleted user {}", user_id))),
+        -2 => Err(BtcmDataError::Conflict(format!("user {} exists", user_id))),
+        -3 => Err(duplicate_error(index)),
+        _ => Ok(()),
+    }
+}
+
+/// 恢复已删除的用户:检查唯一字段没有被其他用户使用，将删除用户键重命名回用户键并重建索引。
+/// KEYS和ARGV见`user_script_invocation`
+/// 返回 {新版本号, 0}，新版本号为 -1 表示用户没有被删除, -2 表示用户已存在,
+/// -3 表示唯一字段重复，此时第二个值为重复字段的序号(从1开始)
+static RESTORE_USER_SCRIPT: &str = r"
+local n = #KEYS - 5
+if redis.call('EXISTS', KEYS[1]) == 1 then
+    return {-2, 0}
+end
+if redis.call('EXISTS', KEYS[2]) == 0 then
+    return {-1, 0}
+end
+local values = {}
+for i = 1, n do
+    local value = redis.call('HGET', KEYS[2], ARGV[1 + i])
+    if value and value ~= '' then
+        local owner = redis.call('HGET', KEYS[5 + i], value)
+        if owner and owner ~= ARGV[1] then
+            return {-3, i}
+        end
+        values[i] = value
+    end
+end
+redis.call('RENAME', KEYS[2], KEYS[1])
+redis.call('ZREM', KEYS[3], ARGV[1])
+redis.call('HDEL', KEYS[4], ARGV[1])
+for i = 1, n do
+    if values[i] then
+        redis.call('HSET', KEYS[5 + i], values[i], ARGV[1])
+    end
+end
+local nickname = redis.call('HGET', KEYS[1], 'nickname')
+if nickname and nickname ~= '' then
+    redis.call('ZADD', KEYS[5], 0, string.lower(nickname) .. '\0' .. ARGV[1])
+end
+return {redis.call('HINCRBY', KEYS[1], 'version', 1), 0}
+";
+
+/// 异步函数，按删除时间从早到晚列出已软删除的用户。
+/// 
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `offset`: 跳过的结果数。
+/// - `count`: 最多返回的结果数。
+/// 
+/// # 返回值
+/// 返回已删除的用户，包含删除前的用户信息、删除时间和删除原因。
+pub async fn list_deleted_users(con: &MultiplexedConnection, offset: usize, count: usize) -> Result<Vec<DeletedUser>, BtcmDataError> {
+    if count == 0 {
+        return Ok(Vec::new());
+    }
+    let mut con = con.clone();
+    let deleted: Vec<(u64, u64)> = con
+        .zrange_withscores(DEL_USER_INDEX, offset as isize, (offset + count - 1) as isize)
+        .await?;
+    if deleted.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut pipe = redis::pipe();
+    for (user_id, _) in &deleted {
+        pipe.hgetall(get_del_user_key(ClientID::from(*user_id))).hget(DEL_USER_REASONS, *user_id);
+    }
+    let replies: Vec<(HashMap<String, String>, Option<String>)> = pipe.query_async(&mut con).await?;
+    deleted
+        .into_iter()
+        .zip(replies)
+        .map(|((user_id, deleted_at), (hm, reason))| Ok(DeletedUser::new(user_id, User::from_fields(hm)?, deleted_at, reason)))
+        .collect()
+}
+
+/// 异步函数，清除删除时间早于保留期限的已删除用户，清除后不能再恢复。
+/// 
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `retention`: 已删除用户的保留期限。
+/// 
+/// # 返回值
+/// 返回清除的用户数。
+/// 
+/// # 示例
+/// ```rust,no_run
+/// use std::time::Duration;
+/// use btcmdata::redis::users::purge_deleted_users;
+/// 
+/// #[tokio::main]
+/// async fn main() {
+///     let client = redis::Client::open("redis://127.0.0.1/").expect("Failed to connect to Redis");
+///     let con = client.get_multiplexed_tokio_connection().await.expect("Failed to get Redis connection");
+///     
+///     // 已删除的用户保留30天
+///     let purged = purge_deleted_users(&con, Duration::from_secs(30 * 24 * 3600)).await.unwrap();
+///     println!("Purged: {}", purged);
+/// }
+/// ```
+pub async fn purge_deleted_users(con: &MultiplexedConnection, retention: Duration) -> Result<usize, BtcmDataError> {
+    let mut con = con.clone();
+    let cutoff = now_millis().saturating_sub(retention.as_millis() as u64);
+    let script = redis::Script::new(PURGE_DELETED_USERS_SCRIPT);
+    let mut purged = 0;
+    loop {
+        // 已删除用户的键由用户ID决定，先分批读取到期的用户，脚本中再确认删除时间
+        let ids: Vec<u64> = con.zrangebyscore_limit(DEL_USER_INDEX, "-inf", cutoff, 0, PURGE_BATCH).await?;
+        if ids.is_empty() {
+            return Ok(purged);
+        }
+        let mut invocation = script.prepare_invoke();
+        invocation.key(DEL_USER_INDEX).key(DEL_USER_REASONS).arg(cutoff);
+        for id in &ids {
+            invocation.key(get_del_user_key(ClientID::from(*id))).arg(*id);
+        }
+        let count: usize = invocation.invoke_async(&mut con).await?;
+        purged += count;
+        if ids.len() < PURGE_BATCH as usize {
+            return Ok(purged);
+        }
+    }
+}
+
+/// 每次清除的已删除用户数上限
+const PURGE_BATCH: isize = 500;
+
+/// 清除删除时间不晚于截止时间的已删除用户，删除时间已变化或已恢复的用户跳过。
+/// KEYS[1] = 已删除用户索引, KEYS[2] = 删除原因, KEYS[2 + i] = 第i个用户的del_users
+/// ARGV[1] = 截止时间, ARGV[1 + i] = 第i个用户ID
+/// 返回清除的用户数
+static PURGE_DELETED_USERS_SCRIPT: &str = r"
+local purged = 0
+for i = 2, #ARGV do
+    local deleted_at = redis.call('ZSCORE', KEYS[1], ARGV[i])
+    if deleted_at and tonumber(deleted_at) <= tonumber(ARGV[1]) then
+        redis.call('DEL', KEYS[1 + i])
+        redis.call('HDEL', KEYS[2], ARGV[i])
+        redis.call('ZREM', KEYS[1], ARGV[i])
+        purged = purged + 1
+    end
+end
+return purged
+";
+
+/// 异步函数，通过唯一字段的值查找用户。
+/// 
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `field`: 唯一字段。
+/// - `value`: 字段的值。
+/// 
+/// # 返回值
+/// 返回使用该值的用户ID，没有用户使用该值时返回None。
+/// 
+/// # 示例
+/// ```rust,no_run
+/// use btcmdata::model::UniqueField;
+/// use btcmdata::redis::users::find_user_by;
+/// 
+/// #[tokio::main]
+/// async fn main() {
+///     let client = redis::Client::open("redis://127.0.0.1/").expect("Failed to connect to Redis");
+///     let con = client.get_multiplexed_tokio_connection().await.expect("Failed to get Redis connection");
+///     
+///     let user_id = find_user_by(&con, UniqueField::Phone, "+8613800000000").await.unwrap();
+///     println!("User: {:?}", user_id);
+/// }
+/// ```
+pub async fn find_user_by(con: &MultiplexedConnection, field: UniqueField, value: &str) -> Result<Option<u64>, BtcmDataError> {
+    let mut con = con.clone();
+    let result: Option<u64> = con.hget(get_user_index_key(field), value).await?;
+    Ok(result)
+}
+
+/// 异步函数，通过用户名查找用户，见`find_user_by`。
+pub async fn find_user_by_username(con: &MultiplexedConnection, username: &str) -> Result<Option<u64>, BtcmDataError> {
+    find_user_by(con, UniqueField::Username, username).await
+}
+
+/// 异步函数，通过手机号查找用户，见`find_user_by`。
+pub async fn find_user_by_phone(con: &MultiplexedConnection, phone: &str) -> Result<Option<u64>, BtcmDataError> {
+    find_user_by(con, UniqueField::Phone, phone).await
+}
+
+/// 异步函数，通过邮箱查找用户，见`find_user_by`。
+pub async fn find_user_by_email(con: &MultiplexedConnection, email: &str) -> Result<Option<u64>, BtcmDataError> {
+    find_user_by(con, UniqueField::Email, email).await
+}
+
+/// 异步函数，按昵称前缀搜索用户，用于添加联系人时的输入提示。
+/// 
+/// 昵称和前缀中的ASCII字母不区分大小写，结果按昵称的字典序排列，昵称相同时按用户ID排列。
+/// 
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `prefix`: 昵称前缀。
+/// - `offset`: 跳过的结果数。
+/// - `count`: 最多返回的结果数。
+/// 
+/// # 返回值
+/// 返回按顺序排列的用户ID。
+/// 
+/// # 示例
+/// ```rust,no_run
+/// use btcmdata::redis::users::search_users_by_nickname;
+/// 
+/// #[tokio::main]
+/// async fn main() {
+///     let client = redis::Client::open("redis://127.0.0.1/").expect("Failed to connect to Redis");
+///     let con = client.get_multiplexed_tokio_connection().await.expect("Failed to get Redis connection");
+///     
+///     // 第一页和第二页，每页20个
+///     let first = search_users_by_nickname(&con, "jo", 0, 20).await.unwrap();
+///     let second = search_users_by_nickname(&con, "jo", 20, 20).await.unwrap();
+///     println!("Users: {:?} {:?}", first, second);
+/// }
+/// ```
+pub async fn search_users_by_nickname(con: &MultiplexedConnection, prefix: &str, offset: usize, count: usize) -> Result<Vec<u64>, BtcmDataError> {
+    if count == 0 {
+        return Ok(Vec::new());
+    }
+    let mut con = con.clone();
+    // 0xff不会出现在UTF-8中，[prefix 到 [prefix\xff 覆盖了以prefix开头的全部成员
+    let min = [b"[", prefix.to_ascii_lowercase().as_bytes()].concat();
+    let max = [min.as_slice(), &[0xff]].concat();
+    let members: Vec<String> = redis::cmd("ZRANGEBYLEX")
+        .arg(USER_NICKNAME_INDEX)
+        .arg(min)
+        .arg(max)
+        .arg("LIMIT")
+        .arg(offset)
+        .arg(count)
+        .query_async(&mut con)
+        .await?;
+    members
+        .iter()
+        .map(|member| {
+            member
+                .rsplit_once('\0')
+                .and_then(|(_, id)| id.parse().ok())
+                .ok_or_else(|| BtcmDataError::TypeMismatch(format!("invalid nickname index member {:?}", member)))
+        })
+        .collect()
+}
+
 /// 用户键的前缀
 static USER_PREFIX: &str = "users:";
 
@@ -157,9 +756,30 @@ fn get_user_key(clt: ClientID) -> String {
     format!("{}{}", USER_PREFIX, user_id)
 }
 
+/// 唯一字段索引键的前缀，每个唯一字段一个哈希，保存字段值到用户ID的映射
+/// user_index:phone -> Hash  +8613800000000 -> 1001
+static USER_INDEX_PREFIX: &str = "user_index:";
+
+/// 获取唯一字段索引键的函数
+fn get_user_index_key(field: UniqueField) -> String {
+    format!("{}{}", USER_INDEX_PREFIX, field.as_str())
+}
+
+/// 昵称索引，分值都为0的有序集合，成员为 小写的昵称 + "\0" + 用户ID，按字典序范围查询昵称前缀
+/// user_nickname_index -> ZSET  "john\01001"
+static USER_NICKNAME_INDEX: &str = "user_nickname_index";
+
 /// 删除用户键的前缀
 static DEL_USER_PREFIX: &str = "del_users:";
 
+/// 已删除用户的索引，按删除时间排序的有序集合
+/// del_users_index -> ZSET  1001 -> 删除时间
+static DEL_USER_INDEX: &str = "del_users_index";
+
+/// 已删除用户的删除原因
+/// del_users_reason -> Hash  1001 -> 删除原因
+static DEL_USER_REASONS: &str = "del_users_reason";
+
 /// 获取删除用户键的函数
 fn get_del_user_key(clt: ClientID) -> String {
     let user_id: u64 = clt.into();
@@ -171,41 +791,72 @@ fn get_del_user_key(clt: ClientID) -> String {
 static USER_CONTS_PREFIX: &str = "conts_user:";
 
 /// 获取用户联系人键的函数
-fn get_user_conts_key(clt: ClientID) -> String {
+pub(crate) fn get_user_conts_key(clt: ClientID) -> String {
     let user_id: u64 = clt.into();
     // 使用format!宏将两个变量连接成一个字符串变量
     format!("{}{}", USER_CONTS_PREFIX, user_id)
 }
 
+/// 批量添加联系人:任意一个联系人与用户之间存在拉黑时全部拒绝。
+/// KEYS[1] = 用户的blocklist, KEYS[2] = 用户的conts_user, KEYS[2 + i] = 第i个联系人的blocklist
+/// ARGV[1] = 用户ID, ARGV[1 + i] = 第i个联系人
+/// 返回 0 成功, 否则返回第一个被拉黑的联系人的序号i
+static ADD_CONTACTS_SCRIPT: &str = r"
+for i = 2, #ARGV do
+    if redis.call('SISMEMBER', KEYS[1], ARGV[i]) == 1 or redis.call('SISMEMBER', KEYS[i + 1], ARGV[1]) == 1 then
+        return i - 1
+    end
+end
+for i = 2, #ARGV do
+    redis.call('SADD', KEYS[2], ARGV[i])
+end
+return 0
+";
+
 /// 异步函数，将用户联系人添加到Redis中。
 /// 
+/// 只写入用户自己的集合；任意一个联系人与用户之间存在拉黑(任一方向)时返回`BtcmDataError::Blocked`，
+/// 不写入任何联系人。
+/// 
 /// # 参数
 /// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
 /// - `clt`: 指定的用户ID。
 /// - `hs`: HashSet<u64>，包含用户联系人的用户ID集合。
 /// 
 /// # 示例
-/// ```rust
+/// ```rust,no_run
 /// use std::collections::HashSet;
 /// use btcmbase::client::ClientID;
-/// use redis::{aio::MultiplexedConnection, AsyncCommands, RedisResult};
+/// use btcmdata::redis::users::add_user_contacts;
 /// 
 /// #[tokio::main]
 /// async fn main() {
 ///     let client = redis::Client::open("redis://127.0.0.1/").expect("Failed to connect to Redis");
-///     let mut con = client.get_async_connection().await.expect("Failed to get Redis connection");
+///     let con = client.get_multiplexed_tokio_connection().await.expect("Failed to get Redis connection");
 ///     let user_id = ClientID::from(123);
 ///     let contacts: HashSet<u64> = [456, 789].iter().cloned().collect();
 ///     
-///     add_user_contacts(&mut con, user_id, &contacts).await;
+///     add_user_contacts(&con, user_id, &contacts).await.unwrap();
 /// }
 /// ```
-pub async fn add_user_contacts(con: &MultiplexedConnection, clt: ClientID, hs: &HashSet<u64>) {
+pub async fn add_user_contacts(con: &MultiplexedConnection, clt: ClientID, hs: &HashSet<u64>) -> Result<(), BtcmDataError> {
+    if hs.is_empty() {
+        return Ok(());
+    }
     let mut con = con.clone();
-    let key = get_user_conts_key(clt);
-    
-    // 使用cmd函数构建一个sadd命令，将HashSet中的数据写入Redis的set结构中
-    let _: () = redis::cmd("SADD").arg(key).arg(hs).query_async(&mut con).await.unwrap();
+    let user_id: u64 = clt.into();
+    let contacts: Vec<u64> = hs.iter().copied().collect();
+    let script = redis::Script::new(ADD_CONTACTS_SCRIPT);
+    let mut invocation = script.prepare_invoke();
+    invocation.key(blocklist::get_blocklist_key(clt)).key(get_user_conts_key(clt)).arg(user_id);
+    for contact in &contacts {
+        invocation.key(blocklist::get_blocklist_key(ClientID::from(*contact))).arg(*contact);
+    }
+    let blocked: usize = invocation.invoke_async(&mut con).await?;
+    if blocked > 0 {
+        return Err(BtcmDataError::Blocked(format!("contact between {} and {} is blocked", user_id, contacts[blocked - 1])));
+    }
+    Ok(())
 }
 
 /// 异步函数，从Redis中删除指定用户的联系人。
@@ -216,27 +867,28 @@ pub async fn add_user_contacts(con: &MultiplexedConnection, clt: ClientID, hs: &
 /// - `hs`: HashSet<u64>，包含要删除的用户联系人的用户ID集合。
 /// 
 /// # 示例
-/// ```rust
+/// ```rust,no_run
 /// use std::collections::HashSet;
 /// use btcmbase::client::ClientID;
-/// use redis::{aio::MultiplexedConnection, AsyncCommands, RedisResult};
+/// use btcmdata::redis::users::del_user_contacts;
 /// 
 /// #[tokio::main]
 /// async fn main() {
 ///     let client = redis::Client::open("redis://127.0.0.1/").expect("Failed to connect to Redis");
-///     let mut con = client.get_async_connection().await.expect("Failed to get Redis connection");
+///     let con = client.get_multiplexed_tokio_connection().await.expect("Failed to get Redis connection");
 ///     let user_id = ClientID::from(123);
 ///     let contacts_to_remove: HashSet<u64> = [456, 789].iter().cloned().collect();
 ///     
-///     del_user_contacts(&mut con, user_id, &contacts_to_remove).await;
+///     del_user_contacts(&con, user_id, &contacts_to_remove).await.unwrap();
 /// }
 /// ```
-pub async fn del_user_contacts(con: &MultiplexedConnection, clt: ClientID, hs: &HashSet<u64>) {
+pub async fn del_user_contacts(con: &MultiplexedConnection, clt: ClientID, hs: &HashSet<u64>) -> Result<(), BtcmDataError> {
     let mut con = con.clone();
     let key = get_user_conts_key(clt);
     
     // 使用cmd函数构建一个srem命令，将HashSet中的数据从Redis的set结构中删除
-    let _: () = redis::cmd("SREM").arg(key).arg(hs).query_async(&mut con).await.unwrap();
+    let _: () = redis::cmd("SREM").arg(key).arg(hs).query_async(&mut con).await?;
+    Ok(())
 }
 
 /// 异步函数，获取指定用户的所有联系人。
@@ -249,35 +901,161 @@ pub async fn del_user_contacts(con: &MultiplexedConnection, clt: ClientID, hs: &
 /// 返回一个HashSet<u64>，包含用户的所有联系人的用户ID。
 /// 
 /// # 示例
-/// ```rust
+/// ```rust,no_run
 /// use std::collections::HashSet;
 /// use btcmbase::client::ClientID;
-/// use redis::{aio::MultiplexedConnection, AsyncCommands, RedisResult};
+/// use btcmdata::redis::users::get_user_contacts;
 /// 
 /// #[tokio::main]
 /// async fn main() {
 ///     let client = redis::Client::open("redis://127.0.0.1/").expect("Failed to connect to Redis");
-///     let mut con = client.get_async_connection().await.expect("Failed to get Redis connection");
+///     let con = client.get_multiplexed_tokio_connection().await.expect("Failed to get Redis connection");
 ///     let user_id = ClientID::from(123);
 ///     
-///     let user_contacts = get_user_contacts(&mut con, user_id).await;
+///     let user_contacts = get_user_contacts(&con, user_id).await.unwrap();
 ///     println!("User contacts: {:?}", user_contacts);
 /// }
 /// ```
-pub async fn get_user_contacts(con: &MultiplexedConnection, clt: ClientID) -> HashSet<u64> {
+pub async fn get_user_contacts(con: &MultiplexedConnection, clt: ClientID) -> Result<HashSet<u64>, BtcmDataError> {
     let mut con = con.clone();
     let key = get_user_conts_key(clt);
     
     // 使用cmd函数构建一个smembers命令，获取Redis中的set结构中的数据，返回一个HashSet<u64>
-    let result: HashSet<u64> = redis::cmd("SMEMBERS").arg(key).query_async(&mut con).await.unwrap();
-    return result;
+    let result: HashSet<u64> = redis::cmd("SMEMBERS").arg(key).query_async(&mut con).await?;
+    Ok(result)
+}
+
+/// 建立联系人关系:任意一方拉黑了另一方时拒绝。
+/// KEYS[1] = a的blocklist, KEYS[2] = b的blocklist, KEYS[3] = a的conts_user, KEYS[4] = b的conts_user
+/// ARGV[1] = a, ARGV[2] = b, ARGV[3] = 1表示双向关系, 0表示a单向关注b
+/// 返回 1 成功, 0 被拉黑
+static ADD_CONTACT_SCRIPT: &str = r"
+if redis.call('SISMEMBER', KEYS[1], ARGV[2]) == 1 or redis.call('SISMEMBER', KEYS[2], ARGV[1]) == 1 then
+    return 0
+end
+redis.call('SADD', KEYS[3], ARGV[2])
+if ARGV[3] == '1' then
+    redis.call('SADD', KEYS[4], ARGV[1])
+end
+return 1
+";
+
+/// 在脚本中检查黑名单后建立联系人关系
+async fn add_contact_checked(con: &MultiplexedConnection, a: ClientID, b: ClientID, mutual: bool) -> Result<(), BtcmDataError> {
+    let mut con = con.clone();
+    let (a_id, b_id): (u64, u64) = (a.into(), b.into());
+    let added: bool = redis::Script::new(ADD_CONTACT_SCRIPT)
+        .key(blocklist::get_blocklist_key(a))
+        .key(blocklist::get_blocklist_key(b))
+        .key(get_user_conts_key(a))
+        .key(get_user_conts_key(b))
+        .arg(a_id)
+        .arg(b_id)
+        .arg(if mutual { 1 } else { 0 })
+        .invoke_async(&mut con)
+        .await?;
+    if !added {
+        return Err(BtcmDataError::Blocked(format!("contact between {} and {} is blocked", a_id, b_id)));
+    }
+    Ok(())
+}
+
+/// 异步函数，在两个用户之间建立双向联系人关系。
+/// 
+/// 两个方向在同一个脚本中写入，不会出现A有B而B没有A的情况；
+/// 任意一方拉黑了另一方时返回`BtcmDataError::Blocked`。
+/// 
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `a`: 第一个用户ID。
+/// - `b`: 第二个用户ID。
+/// 
+/// # 示例
+/// ```rust,no_run
+/// use btcmbase::client::ClientID;
+/// use btcmdata::redis::users::add_contact;
+/// 
+/// #[tokio::main]
+/// async fn main() {
+///     let client = redis::Client::open("redis://127.0.0.1/").expect("Failed to connect to Redis");
+///     let con = client.get_multiplexed_tokio_connection().await.expect("Failed to get Redis connection");
+///     
+///     add_contact(&con, ClientID::from(123), ClientID::from(456)).await.unwrap();
+/// }
+/// ```
+pub async fn add_contact(con: &MultiplexedConnection, a: ClientID, b: ClientID) -> Result<(), BtcmDataError> {
+    add_contact_checked(con, a, b, true).await
+}
+
+/// 异步函数，解除两个用户之间的联系人关系，两个方向在同一个事务中删除。
+/// 
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `a`: 第一个用户ID。
+/// - `b`: 第二个用户ID。
+pub async fn remove_contact(con: &MultiplexedConnection, a: ClientID, b: ClientID) -> Result<(), BtcmDataError> {
+    let mut con = con.clone();
+    let (a_id, b_id): (u64, u64) = (a.into(), b.into());
+    let _: () = redis::pipe()
+        .atomic()
+        .srem(get_user_conts_key(a), b_id)
+        .ignore()
+        .srem(get_user_conts_key(b), a_id)
+        .ignore()
+        .query_async(&mut con)
+        .await?;
+    Ok(())
+}
+
+/// 异步函数，单向关注:只将`followee`加入`follower`的联系人集合，适用于有意不对称的关系。
+/// 任意一方拉黑了另一方时返回`BtcmDataError::Blocked`。
+/// 
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `follower`: 关注者的用户ID。
+/// - `followee`: 被关注者的用户ID。
+pub async fn follow(con: &MultiplexedConnection, follower: ClientID, followee: ClientID) -> Result<(), BtcmDataError> {
+    add_contact_checked(con, follower, followee, false).await
+}
+
+/// 异步函数，取消单向关注，只从`follower`的联系人集合中删除`followee`。
+/// 
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `follower`: 关注者的用户ID。
+/// - `followee`: 被关注者的用户ID。
+pub async fn unfollow(con: &MultiplexedConnection, follower: ClientID, followee: ClientID) -> Result<(), BtcmDataError> {
+    let mut con = con.clone();
+    let followee: u64 = followee.into();
+    let _: () = con.srem(get_user_conts_key(follower), followee).await?;
+    Ok(())
+}
+
+/// 异步函数，判断两个用户是否互为联系人。
+/// 
+/// # 参数
+/// - `con`: Redis的MultiplexedConnection，用于与Redis进行异步通信。
+/// - `a`: 第一个用户ID。
+/// - `b`: 第二个用户ID。
+/// 
+/// # 返回值
+/// 双方的联系人集合都包含对方时返回true。
+pub async fn is_mutual_contact(con: &MultiplexedConnection, a: ClientID, b: ClientID) -> Result<bool, BtcmDataError> {
+    let mut con = con.clone();
+    let (a_id, b_id): (u64, u64) = (a.into(), b.into());
+    let (ab, ba): (bool, bool) = redis::pipe()
+        .sismember(get_user_conts_key(a), b_id)
+        .sismember(get_user_conts_key(b), a_id)
+        .query_async(&mut con)
+        .await?;
+    Ok(ab && ba)
 }
 
 /// 组联系人键的前缀
 static GROUP_CONTS_PREFIX: &str = "conts_group:";
 
 /// 获取组联系人键的函数
-fn get_group_conts_key(clt: ClientID) -> String {
+pub(crate) fn get_group_conts_key(clt: ClientID) -> String {
     let user_id: u64 = clt.into();
     // 使用format!宏将两个变量连接成一个字符串变量
     format!("{}{}", GROUP_CONTS_PREFIX, user_id)
@@ -291,27 +1069,28 @@ fn get_group_conts_key(clt: ClientID) -> String {
 /// - `hs`: HashSet<u64>，包含组联系人的用户ID集合。
 /// 
 /// # 示例
-/// ```rust
+/// ```rust,no_run
 /// use std::collections::HashSet;
 /// use btcmbase::client::ClientID;
-/// use redis::{aio::MultiplexedConnection, AsyncCommands, RedisResult};
+/// use btcmdata::redis::users::add_group_contacts;
 /// 
 /// #[tokio::main]
 /// async fn main() {
 ///     let client = redis::Client::open("redis://127.0.0.1/").expect("Failed to connect to Redis");
-///     let mut con = client.get_async_connection().await.expect("Failed to get Redis connection");
+///     let con = client.get_multiplexed_tokio_connection().await.expect("Failed to get Redis connection");
 ///     let user_id = ClientID::from(123);
 ///     let group_contacts: HashSet<u64> = [456, 789].iter().cloned().collect();
 ///     
-///     add_group_contacts(&mut con, user_id, &group_contacts).await;
+///     add_group_contacts(&con, user_id, &group_contacts).await.unwrap();
 /// }
 /// ```
-pub async fn add_group_contacts(con: &MultiplexedConnection, clt: ClientID, hs: &HashSet<u64>) {
+pub async fn add_group_contacts(con: &MultiplexedConnection, clt: ClientID, hs: &HashSet<u64>) -> Result<(), BtcmDataError> {
     let mut con = con.clone();
     let key = get_group_conts_key(clt);
     
     // 使用cmd函数构建一个sadd命令，将HashSet中的数据写入Redis的set结构中
-    let _: () = redis::cmd("SADD").arg(key).arg(hs).query_async(&mut con).await.unwrap();
+    let _: () = redis::cmd("SADD").arg(key).arg(hs).query_async(&mut con).await?;
+    Ok(())
 }
 
 /// 异步函数，从Redis中删除指定用户组的联系人。
@@ -322,27 +1101,28 @@ pub async fn add_group_contacts(con: &MultiplexedConnection, clt: ClientID, hs:
 /// - `hs`: HashSet<u64>，包含要删除的用户组联系人的用户ID集合。
 /// 
 /// # 示例
-/// ```rust
+/// ```rust,no_run
 /// use std::collections::HashSet;
 /// use btcmbase::client::ClientID;
-/// use redis::{aio::MultiplexedConnection, AsyncCommands, RedisResult};
+/// use btcmdata::redis::users::del_group_contacts;
 /// 
 /// #[tokio::main]
 /// async fn main() {
 ///     let client = redis::Client::open("redis://127.0.0.1/").expect("Failed to connect to Redis");
-///     let mut con = client.get_async_connection().await.expect("Failed to get Redis connection");
+///     let con = client.get_multiplexed_tokio_connection().await.expect("Failed to get Redis connection");
 ///     let user_id = ClientID::from(123);
 ///     let group_contacts_to_remove: HashSet<u64> = [456, 789].iter().cloned().collect();
 ///     
-///     del_group_contacts(&mut con, user_id, &group_contacts_to_remove).await;
+///     del_group_contacts(&con, user_id, &group_contacts_to_remove).await.unwrap();
 /// }
 /// ```
-pub async fn del_group_contacts(con: &MultiplexedConnection, clt: ClientID, hs: &HashSet<u64>) {
+pub async fn del_group_contacts(con: &MultiplexedConnection, clt: ClientID, hs: &HashSet<u64>) -> Result<(), BtcmDataError> {
     let mut con = con.clone();
     let key = get_group_conts_key(clt);
     
     // 使用cmd函数构建一个srem命令，将HashSet中的数据从Redis的set结构中删除
-    let _: () = redis::cmd("SREM").arg(key).arg(hs).query_async(&mut con).await.unwrap();
+    let _: () = redis::cmd("SREM").arg(key).arg(hs).query_async(&mut con).await?;
+    Ok(())
 }
 
 /// 异步函数，获取指定用户组的所有联系人。
@@ -355,28 +1135,28 @@ pub async fn del_group_contacts(con: &MultiplexedConnection, clt: ClientID, hs:
 /// 返回一个HashSet<u64>，包含用户组的所有联系人的用户ID。
 /// 
 /// # 示例
-/// ```rust
+/// ```rust,no_run
 /// use std::collections::HashSet;
 /// use btcmbase::client::ClientID;
-/// use redis::{aio::MultiplexedConnection, AsyncCommands, RedisResult};
+/// use btcmdata::redis::users::get_group_contacts;
 /// 
 /// #[tokio::main]
 /// async fn main() {
 ///     let client = redis::Client::open("redis://127.0.0.1/").expect("Failed to connect to Redis");
-///     let mut con = client.get_async_connection().await.expect("Failed to get Redis connection");
+///     let con = client.get_multiplexed_tokio_connection().await.expect("Failed to get Redis connection");
 ///     let user_id = ClientID::from(123);
 ///     
-///     let group_contacts = get_group_contacts(&mut con, user_id).await;
+///     let group_contacts = get_group_contacts(&con, user_id).await.unwrap();
 ///     println!("Group contacts: {:?}", group_contacts);
 /// }
 /// ```
-pub async fn get_group_contacts(con: &MultiplexedConnection, clt: ClientID) -> HashSet<u64> {
+pub async fn get_group_contacts(con: &MultiplexedConnection, clt: ClientID) -> Result<HashSet<u64>, BtcmDataError> {
     let mut con = con.clone();
     let key = get_group_conts_key(clt);
     
     // 使用cmd函数构建一个smembers命令，获取Redis中的set结构中的数据，返回一个HashSet<u64>
-    let result: HashSet<u64> = redis::cmd("SMEMBERS").arg(key).query_async(&mut con).await.unwrap();
-    return result;
+    let result: HashSet<u64> = redis::cmd("SMEMBERS").arg(key).query_async(&mut con).await?;
+    Ok(result)
 }
 
 
@@ -532,4 +1312,170 @@ pub async fn get_group_contacts(con: &MultiplexedConnection, clt: ClientID) -> H
 //     // 使用cmd函数构建一个smembers命令，获取redis中的set结构中的数据，返回一个hashset
 //     let result: HashSet<u64> = redis::cmd("SMEMBERS").arg(key).query_async(&mut con).await.unwrap();
 //     return result;
-// }
\ No newline at end of file
+// }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::redis::test_connection;
+
+    /// 删除测试用户的全部痕迹，包括已删除的用户信息
+    async fn reset_user(con: &MultiplexedConnection, clt: ClientID) {
+        let mut con = con.clone();
+        if exists_user(&mut con, clt).await.unwrap() {
+            remove_user(&mut con, clt, None).await.unwrap();
+        }
+        let user_id: u64 = clt.into();
+        let _: () = redis::pipe()
+            .del(get_del_user_key(clt))
+            .zrem(DEL_USER_INDEX, user_id)
+            .hdel(DEL_USER_REASONS, user_id)
+            .query_async(&mut con)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore = "requires BTCMDATA_REDIS_URL"]
+    async fn soft_delete_restore_and_purge() {
+        let con = test_connection().await;
+        let mut rcon = con.clone();
+        let clt = ClientID::from(921_001);
+        reset_user(&con, clt).await;
+        add_user(&con, clt, &User::new("Purge Me")).await.unwrap();
+
+        assert!(remove_user(&mut rcon, clt, Some("spam")).await.unwrap());
+        assert!(!remove_user(&mut rcon, clt, None).await.unwrap());
+        assert!(!exists_user(&mut rcon, clt).await.unwrap());
+        let deleted = list_deleted_users(&con, 0, 1000).await.unwrap();
+        let deleted = deleted.iter().find(|deleted| *deleted.client_id() == 921_001).unwrap();
+        assert_eq!(deleted.reason().as_deref(), Some("spam"));
+        assert_eq!(deleted.user().nickname(), "Purge Me");
+
+        restore_user(&con, clt).await.unwrap();
+        assert_eq!(get_user(&con, clt).await.unwrap().unwrap().nickname(), "Purge Me");
+
+        // 清除后不能再恢复
+        remove_user(&mut rcon, clt, None).await.unwrap();
+        assert!(purge_deleted_users(&con, Duration::ZERO).await.unwrap() >= 1);
+        assert!(matches!(restore_user(&con, clt).await, Err(BtcmDataError::NotFound(_))));
+        assert!(!rcon.exists::<_, bool>(get_del_user_key(clt)).await.unwrap());
+        assert!(!rcon.hexists::<_, _, bool>(DEL_USER_REASONS, 921_001).await.unwrap());
+    }
+
+    #[tokio::test]
+    #[ignore = "requires BTCMDATA_REDIS_URL"]
+    async fn contacts_are_written_in_both_directions() {
+        let con = test_connection().await;
+        let mut rcon = con.clone();
+        let (a, b, c) = (ClientID::from(912_001), ClientID::from(912_002), ClientID::from(912_003));
+        for user in [a, b, c] {
+            let _: () = rcon.del(&[get_user_conts_key(user), blocklist::get_blocklist_key(user)]).await.unwrap();
+        }
+
+        add_contact(&con, a, b).await.unwrap();
+        assert!(is_mutual_contact(&con, a, b).await.unwrap());
+        assert!(is_mutual_contact(&con, b, a).await.unwrap());
+        remove_contact(&con, b, a).await.unwrap();
+        assert!(get_user_contacts(&con, a).await.unwrap().is_empty());
+        assert!(get_user_contacts(&con, b).await.unwrap().is_empty());
+
+        // 单向关注只写入关注者的集合
+        follow(&con, a, c).await.unwrap();
+        assert_eq!(get_user_contacts(&con, a).await.unwrap(), HashSet::from([912_003]));
+        assert!(get_user_contacts(&con, c).await.unwrap().is_empty());
+        assert!(!is_mutual_contact(&con, a, c).await.unwrap());
+        unfollow(&con, a, c).await.unwrap();
+
+        // 任意一方拉黑另一方时两个方向都不写入
+        blocklist::block_user(&con, b, a).await.unwrap();
+        assert!(matches!(add_contact(&con, a, b).await, Err(BtcmDataError::Blocked(_))));
+        assert!(matches!(follow(&con, b, a).await, Err(BtcmDataError::Blocked(_))));
+        assert!(get_user_contacts(&con, a).await.unwrap().is_empty());
+        assert!(get_user_contacts(&con, b).await.unwrap().is_empty());
+        blocklist::unblock_user(&con, b, a).await.unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore = "requires BTCMDATA_REDIS_URL"]
+    async fn stale_versions_are_refused() {
+        let con = test_connection().await;
+        let clt = ClientID::from(918_001);
+        reset_user(&con, clt).await;
+        add_user(&con, clt, &User::new("Versioned")).await.unwrap();
+        let stale = get_user(&con, clt).await.unwrap().unwrap();
+
+        // 部分字段更新同样递增版本号
+        let fields: HashMap<String, String> = [("avatar".to_string(), "a.png".to_string())].into_iter().collect();
+        set_user_fields(&con, clt, &fields).await.unwrap();
+        let mut fresh = get_user(&con, clt).await.unwrap().unwrap();
+        assert_eq!(*fresh.version(), stale.version() + 1);
+        assert!(matches!(update_user(&con, clt, &stale).await, Err(BtcmDataError::Conflict(_))));
+
+        fresh.set_nickname("Renamed".to_string());
+        assert_eq!(update_user(&con, clt, &fresh).await.unwrap(), stale.version() + 2);
+        let version: HashMap<String, String> = [("version".to_string(), "1".to_string())].into_iter().collect();
+        assert!(matches!(set_user_fields(&con, clt, &version).await, Err(BtcmDataError::Conflict(_))));
+        let user = get_user(&con, clt).await.unwrap().unwrap();
+        assert_eq!((user.nickname().as_str(), user.avatar().as_deref()), ("Renamed", Some("a.png")));
+        reset_user(&con, clt).await;
+    }
+
+    #[tokio::test]
+    #[ignore = "requires BTCMDATA_REDIS_URL"]
+    async fn unique_fields_are_released_on_delete() {
+        let con = test_connection().await;
+        let mut rcon = con.clone();
+        let (a, b) = (ClientID::from(919_001), ClientID::from(919_002));
+        reset_user(&con, a).await;
+        reset_user(&con, b).await;
+
+        let mut user = User::new("Unique");
+        user.set_username(Some("unique_919".to_string()));
+        add_user(&con, a, &user).await.unwrap();
+        assert_eq!(find_user_by_username(&con, "unique_919").await.unwrap(), Some(919_001));
+        assert!(matches!(add_user(&con, b, &user).await, Err(BtcmDataError::Duplicate(_))));
+        assert!(!exists_user(&mut rcon, b).await.unwrap());
+
+        // 软删除释放唯一字段，被其他用户占用后原用户无法恢复
+        remove_user(&mut rcon, a, None).await.unwrap();
+        assert_eq!(find_user_by_username(&con, "unique_919").await.unwrap(), None);
+        add_user(&con, b, &user).await.unwrap();
+        assert_eq!(find_user_by_username(&con, "unique_919").await.unwrap(), Some(919_002));
+        assert!(matches!(restore_user(&con, a).await, Err(BtcmDataError::Duplicate(_))));
+
+        let fields: HashMap<String, String> = [("username".to_string(), "renamed_919".to_string())].into_iter().collect();
+        set_user_fields(&con, b, &fields).await.unwrap();
+        assert_eq!(find_user_by_username(&con, "unique_919").await.unwrap(), None);
+        restore_user(&con, a).await.unwrap();
+        assert_eq!(find_user_by_username(&con, "unique_919").await.unwrap(), Some(919_001));
+        reset_user(&con, a).await;
+        reset_user(&con, b).await;
+    }
+
+    #[tokio::test]
+    #[ignore = "requires BTCMDATA_REDIS_URL"]
+    async fn nickname_prefix_bounds() {
+        let con = test_connection().await;
+        let users = [(920_001, "Zz920 Alpha"), (920_002, "zz920 beta"), (920_003, "Zz920Ä"), (920_004, "Zz92"), (920_005, "zz921")];
+        for (id, nickname) in users {
+            reset_user(&con, ClientID::from(id)).await;
+            add_user(&con, ClientID::from(id), &User::new(nickname)).await.unwrap();
+        }
+
+        // 只有ASCII字母不区分大小写，非ASCII字符的成员排在最后
+        assert_eq!(search_users_by_nickname(&con, "ZZ920", 0, 10).await.unwrap(), vec![920_001, 920_002, 920_003]);
+        assert_eq!(search_users_by_nickname(&con, "zz920", 1, 1).await.unwrap(), vec![920_002]);
+        assert_eq!(search_users_by_nickname(&con, "zz920Ä", 0, 10).await.unwrap(), vec![920_003]);
+        assert!(search_users_by_nickname(&con, "zz920ä", 0, 10).await.unwrap().is_empty());
+        assert_eq!(search_users_by_nickname(&con, "zz92", 0, 10).await.unwrap(), vec![920_004, 920_001, 920_002, 920_003, 920_005]);
+
+        // 修改或删除用户后索引随之更新
+        let fields: HashMap<String, String> = [("nickname".to_string(), "Other".to_string())].into_iter().collect();
+        set_user_fields(&con, ClientID::from(920_001), &fields).await.unwrap();
+        reset_user(&con, ClientID::from(920_002)).await;
+        assert_eq!(search_users_by_nickname(&con, "zz920", 0, 10).await.unwrap(), vec![920_003]);
+        for (id, _) in users {
+            reset_user(&con, ClientID::from(id)).await;
+        }
+    }
+}
@@ -0,0 +1,1163 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+use async_trait::async_trait;
+use btcmbase::client::ClientID;
+use once_cell::sync::OnceCell;
+use rusqlite::{params, Connection, OptionalExtension};
+use tokio::sync::{Mutex, MutexGuard};
+use crate::error::BtcmDataError;
+use crate::model::{device_id, AccountDeletionReport, DeletedUser, Device, GroupInfo, GroupRole, UniqueField, User};
+use crate::store::{AccountStore, BlockStore, ContactStore, DeviceStore, GroupStore, UserStore};
+
+/// 建表语句，每张表对应Redis中的一类键:
+/// - `users` / `del_users`: `users:<id>` / `del_users:<id>` 用户哈希，
+///   `users_unique`对应 `user_index:<field>` 唯一字段索引，`users_nickname`对应 `user_nickname_index` 昵称索引
+/// - `del_users_index`: `del_users_index` / `del_users_reason` 已删除用户的删除时间和原因
+/// - `conts_user` / `conts_group`: `conts_user:<id>` / `conts_group:<id>` 联系人集合
+/// - `blocklist`: `blocklist:<id>` 黑名单集合
+/// - `group_members` / `group_info`: `group:<id>` 群组成员集合和 `group_info:<id>` 群组信息哈希
+/// - `group_admins`: `group_admins:<id>` 群组管理员集合(不包括群主)
+/// - `client_device` / `client_device_hash`: `client_device:<id>` 设备集合和 `client_device:<id>:<dev>` 设备哈希
+static SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS users (
+    client_id INTEGER NOT NULL,
+    field     TEXT    NOT NULL,
+    value     TEXT    NOT NULL,
+    PRIMARY KEY (client_id, field)
+);
+CREATE UNIQUE INDEX IF NOT EXISTS users_unique ON users (field, value)
+    WHERE field IN ('username', 'phone', 'email') AND value <> '';
+CREATE INDEX IF NOT EXISTS users_nickname ON users (lower(value), client_id) WHERE field = 'nickname';
+CREATE TABLE IF NOT EXISTS del_users (
+    client_id INTEGER NOT NULL,
+    field     TEXT    NOT NULL,
+    value     TEXT    NOT NULL,
+    PRIMARY KEY (client_id, field)
+);
+CREATE TABLE IF NOT EXISTS del_users_index (
+    client_id  INTEGER PRIMARY KEY,
+    deleted_at INTEGER NOT NULL,
+    reason     TEXT
+);
+CREATE INDEX IF NOT EXISTS del_users_index_deleted_at ON del_users_index (deleted_at);
+CREATE TABLE IF NOT EXISTS conts_user (
+    client_id  INTEGER NOT NULL,
+    contact_id INTEGER NOT NULL,
+    PRIMARY KEY (client_id, contact_id)
+);
+CREATE TABLE IF NOT EXISTS blocklist (
+    client_id  INTEGER NOT NULL,
+    blocked_id INTEGER NOT NULL,
+    PRIMARY KEY (client_id, blocked_id)
+);
+CREATE TABLE IF NOT EXISTS conts_group (
+    client_id INTEGER NOT NULL,
+    group_id  INTEGER NOT NULL,
+    PRIMARY KEY (client_id, group_id)
+);
+CREATE TABLE IF NOT EXISTS group_members (
+    group_id  INTEGER NOT NULL,
+    member_id INTEGER NOT NULL,
+    PRIMARY KEY (group_id, member_id)
+);
+CREATE INDEX IF NOT EXISTS group_members_member ON group_members (member_id);
+CREATE TABLE IF NOT EXISTS group_info (
+    group_id INTEGER NOT NULL,
+    field    TEXT    NOT NULL,
+    value    TEXT    NOT NULL,
+    PRIMARY KEY (group_id, field)
+);
+CREATE TABLE IF NOT EXISTS group_admins (
+    group_id  INTEGER NOT NULL,
+    member_id INTEGER NOT NULL,
+    PRIMARY KEY (group_id, member_id)
+);
+CREATE TABLE IF NOT EXISTS client_device (
+    client_id INTEGER NOT NULL,
+    device_id INTEGER NOT NULL,
+    PRIMARY KEY (client_id, device_id)
+);
+CREATE TABLE IF NOT EXISTS client_device_hash (
+    client_id INTEGER NOT NULL,
+    device_id INTEGER NOT NULL,
+    field     TEXT    NOT NULL,
+    value     TEXT    NOT NULL,
+    PRIMARY KEY (client_id, device_id, field)
+);
+";
+
+/// # SQLite Store
+///
+/// The `SqliteStore` struct implements the storage traits of `crate::store` on top of a
+/// single SQLite connection. It mirrors the Redis key layout table by table, so small
+/// deployments and CI can run without a Redis server.
+///
+/// # Examples
+///
+/// ```rust
+/// use btcmdata::sqlite::SqliteStore;
+/// use btcmdata::store::GroupStore;
+/// use btcmbase::client::ClientID;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let store = SqliteStore::open_in_memory().expect("Failed to open SQLite database.");
+///     let members = [456, 789].iter().cloned().collect();
+///     store.add_group(ClientID::from(123), &members).await.unwrap();
+/// }
+/// ```
+pub struct SqliteStore {
+    connect: Mutex<Connection>,
+}
+
+impl SqliteStore {
+    /// Opens (or creates) the SQLite database at `path` and creates the tables.
+    pub fn open(path: &str) -> Result<Self, BtcmDataError> {
+        SqliteStore::from_connection(Connection::open(path)?)
+    }
+
+    /// Opens a private in-memory SQLite database and creates the tables.
+    pub fn open_in_memory() -> Result<Self, BtcmDataError> {
+        SqliteStore::from_connection(Connection::open_in_memory()?)
+    }
+
+    fn from_connection(con: Connection) -> Result<Self, BtcmDataError> {
+        con.execute_batch(SCHEMA)?;
+        Ok(SqliteStore { connect: Mutex::new(con) })
+    }
+
+    /// 异步获取连接锁，等待期间不阻塞Tokio工作线程
+    async fn lock(&self) -> MutexGuard<'_, Connection> {
+        self.connect.lock().await
+    }
+}
+
+// 使用 OnceCell 包装 Singleton，确保只初始化一次
+static SINGLETON_SQLITE_STORE: OnceCell<Arc<SqliteStore>> = OnceCell::new();
+
+/// Initializes the SQLite database and returns a Result containing an Arc-wrapped `SqliteStore`.
+///
+/// # Arguments
+///
+/// * `path` - The path of the SQLite database file, `":memory:"` for an in-memory database.
+///
+/// # Returns
+///
+/// Returns a `Result` containing an `Arc<SqliteStore>` on success, or a `BtcmDataError` on failure.
+pub fn init_sqlite_database(path: &str) -> Result<Arc<SqliteStore>, BtcmDataError> {
+    let store = SINGLETON_SQLITE_STORE.get_or_try_init(|| SqliteStore::open(path).map(Arc::new))?;
+    Ok(store.clone())
+}
+
+/// Gets the SQLite store from the singleton instance.
+///
+/// # Returns
+///
+/// Returns an `Option<Arc<SqliteStore>>`. If the singleton instance exists, it returns the store;
+/// otherwise, it returns `None`.
+pub fn get_sqlite_store() -> Option<Arc<SqliteStore>> {
+    SINGLETON_SQLITE_STORE.get().cloned()
+}
+
+/// 读取 (field, value) 形式的查询结果为HashMap
+fn query_fields(con: &Connection, sql: &str, params: &[&dyn rusqlite::ToSql]) -> Result<HashMap<String, String>, BtcmDataError> {
+    let mut stmt = con.prepare_cached(sql)?;
+    let rows = stmt.query_map(params, |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?;
+    let mut result = HashMap::new();
+    for row in rows {
+        let (field, value) = row?;
+        result.insert(field, value);
+    }
+    Ok(result)
+}
+
+/// 读取单列id的查询结果为HashSet
+fn query_ids(con: &Connection, sql: &str, id: i64) -> Result<HashSet<u64>, BtcmDataError> {
+    let mut stmt = con.prepare_cached(sql)?;
+    let rows = stmt.query_map(params![id], |row| row.get::<_, i64>(0))?;
+    let mut result = HashSet::new();
+    for row in rows {
+        result.insert(row? as u64);
+    }
+    Ok(result)
+}
+
+/// 在一个事务中向 (owner, id) 形式的集合表插入或删除一批id
+fn update_ids(con: &mut Connection, sql: &str, owner: i64, ids: &HashSet<u64>) -> Result<(), BtcmDataError> {
+    let tx = con.transaction()?;
+    execute_ids(&tx, sql, owner, ids)?;
+    tx.commit()?;
+    Ok(())
+}
+
+fn execute_ids(con: &Connection, sql: &str, owner: i64, ids: &HashSet<u64>) -> Result<(), BtcmDataError> {
+    let mut stmt = con.prepare_cached(sql)?;
+    for id in ids {
+        stmt.execute(params![owner, *id as i64])?;
+    }
+    Ok(())
+}
+
+/// 读取群组信息中的群主，群组没有群组信息时返回None
+fn group_owner(con: &Connection, clt: ClientID) -> Result<Option<u64>, BtcmDataError> {
+    let owner: Option<String> = con
+        .query_row("SELECT value FROM group_info WHERE group_id = ?1 AND field = 'owner'", params![id_of(clt)], |row| row.get(0))
+        .optional()?;
+    owner
+        .map(|owner| owner.parse().map_err(|_| BtcmDataError::TypeMismatch(format!("field owner has invalid value {}", owner))))
+        .transpose()
+}
+
+/// 删除群组成员、管理员和群组信息，返回群组是否存在
+fn remove_group(con: &Connection, clt: ClientID) -> Result<bool, BtcmDataError> {
+    let removed = con.execute("DELETE FROM group_members WHERE group_id = ?1", params![id_of(clt)])?;
+    let info = con.execute("DELETE FROM group_info WHERE group_id = ?1", params![id_of(clt)])?;
+    con.execute("DELETE FROM group_admins WHERE group_id = ?1", params![id_of(clt)])?;
+    Ok(removed + info > 0)
+}
+
+/// 与Redis的TRANSFER_OWNER_SCRIPT一致:修改群主，新群主不再是管理员
+fn set_group_owner(con: &Connection, clt: ClientID, owner: u64) -> Result<(), BtcmDataError> {
+    con.execute("UPDATE group_info SET value = ?2 WHERE group_id = ?1 AND field = 'owner'", params![id_of(clt), owner.to_string()])?;
+    con.execute("DELETE FROM group_admins WHERE group_id = ?1 AND member_id = ?2", params![id_of(clt), owner as i64])?;
+    Ok(())
+}
+
+/// 在一个事务中对 (a, b) 和 (b, a) 两个方向执行同一条语句
+fn update_pair(con: &mut Connection, sql: &str, a: i64, b: i64) -> Result<(), BtcmDataError> {
+    let tx = con.transaction()?;
+    {
+        let mut stmt = tx.prepare_cached(sql)?;
+        stmt.execute(params![a, b])?;
+        stmt.execute(params![b, a])?;
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+/// 与Redis的REMOVE_USER_SCRIPT一致:将用户移入已删除用户表并记录删除时间和原因，
+/// 用户已被删除时返回false，用户不存在时返回NotFound
+fn soft_delete_user(con: &Connection, clt: ClientID, reason: Option<&str>) -> Result<bool, BtcmDataError> {
+    let id = id_of(clt);
+    if !exists(con, "SELECT 1 FROM users WHERE client_id = ?1 LIMIT 1", &[&id])? {
+        if exists(con, "SELECT 1 FROM del_users_index WHERE client_id = ?1", &[&id])? {
+            return Ok(false);
+        }
+        return Err(BtcmDataError::NotFound(format!("user {}", id as u64)));
+    }
+    con.execute("INSERT INTO del_users (client_id, field, value) SELECT client_id, field, value FROM users WHERE client_id = ?1", params![id])?;
+    con.execute("DELETE FROM users WHERE client_id = ?1", params![id])?;
+    con.execute(
+        "INSERT INTO del_users_index (client_id, deleted_at, reason) VALUES (?1, ?2, ?3)",
+        params![id, crate::now_millis() as i64, reason],
+    )?;
+    Ok(true)
+}
+
+/// 与Redis的WRITE_USER_SCRIPT一致:检查用户是否存在和期望的版本号，并将版本号加1
+fn bump_user_version(con: &Connection, clt: ClientID, expected: Option<u64>, must_exist: bool) -> Result<u64, BtcmDataError> {
+    let id = id_of(clt);
+    if (must_exist || expected.is_some()) && !exists(con, "SELECT 1 FROM users WHERE client_id = ?1 LIMIT 1", &[&id])? {
+        return Err(BtcmDataError::NotFound(format!("user {}", id as u64)));
+    }
+    let current: Option<String> = con
+        .query_row(
+            "SELECT value FROM users WHERE client_id = ?1 AND field = ?2",
+            params![id, User::VERSION_FIELD],
+            |row| row.get(0),
+        )
+        .optional()?;
+    let current: u64 = match current {
+        Some(value) => value
+            .parse()
+            .map_err(|_| BtcmDataError::TypeMismatch(format!("field version has invalid value {}", value)))?,
+        None => 0,
+    };
+    if expected.is_some_and(|expected| expected != current) {
+        return Err(BtcmDataError::Conflict(format!("user {} was modified concurrently", id as u64)));
+    }
+    con.execute(
+        "INSERT INTO users (client_id, field, value) VALUES (?1, ?2, ?3)
+         ON CONFLICT (client_id, field) DO UPDATE SET value = excluded.value",
+        params![id, User::VERSION_FIELD, (current + 1).to_string()],
+    )?;
+    Ok(current + 1)
+}
+
+/// 检查唯一字段的值没有被其他用户使用，否则返回`BtcmDataError::Duplicate`
+fn check_unique_fields<'a>(con: &Connection, clt: ClientID, fields: impl Iterator<Item = (&'a String, &'a String)>) -> Result<(), BtcmDataError> {
+    for (field, value) in fields {
+        if UniqueField::from_name(field).is_none() || value.is_empty() {
+            continue;
+        }
+        if exists(
+            con,
+            "SELECT 1 FROM users WHERE field = ?1 AND value = ?2 AND client_id <> ?3",
+            &[&field, &value, &id_of(clt)],
+        )? {
+            return Err(BtcmDataError::Duplicate(format!("{} is already used by another user", field)));
+        }
+    }
+    Ok(())
+}
+
+/// 删除已删除用户的信息、删除时间和原因
+fn discard_deleted_user(con: &Connection, id: i64) -> Result<(), BtcmDataError> {
+    con.execute("DELETE FROM del_users WHERE client_id = ?1", params![id])?;
+    con.execute("DELETE FROM del_users_index WHERE client_id = ?1", params![id])?;
+    Ok(())
+}
+
+/// 整体替换用户信息的字段，保留版本号
+fn replace_user_fields(con: &Connection, clt: ClientID, user: &User) -> Result<(), BtcmDataError> {
+    con.execute("DELETE FROM users WHERE client_id = ?1 AND field <> ?2", params![id_of(clt), User::VERSION_FIELD])?;
+    let mut stmt = con.prepare_cached("INSERT INTO users (client_id, field, value) VALUES (?1, ?2, ?3)")?;
+    for (field, value) in user.to_fields() {
+        stmt.execute(params![id_of(clt), field, value])?;
+    }
+    Ok(())
+}
+
+/// 任意一方拉黑了另一方时返回`BtcmDataError::Blocked`
+fn check_not_blocked(con: &Connection, a: ClientID, b: ClientID) -> Result<(), BtcmDataError> {
+    let sql = "SELECT 1 FROM blocklist WHERE (client_id = ?1 AND blocked_id = ?2) OR (client_id = ?2 AND blocked_id = ?1)";
+    if exists(con, sql, &[&id_of(a), &id_of(b)])? {
+        let (a, b): (u64, u64) = (a.into(), b.into());
+        return Err(BtcmDataError::Blocked(format!("contact between {} and {} is blocked", a, b)));
+    }
+    Ok(())
+}
+
+/// 与Redis一致:任意一个成员拉黑了群主时拒绝将其加入群组
+fn check_not_blocking_owner(con: &Connection, clt: ClientID, owner: u64, members: &HashSet<u64>) -> Result<(), BtcmDataError> {
+    for member in members {
+        if exists(con, "SELECT 1 FROM blocklist WHERE client_id = ?1 AND blocked_id = ?2", &[&(*member as i64), &(owner as i64)])? {
+            let group_id: u64 = clt.into();
+            return Err(BtcmDataError::Blocked(format!("user {} has blocked the owner of group {}", member, group_id)));
+        }
+    }
+    Ok(())
+}
+
+fn exists(con: &Connection, sql: &str, params: &[&dyn rusqlite::ToSql]) -> Result<bool, BtcmDataError> {
+    let found: Option<i64> = con.query_row(sql, params, |row| row.get(0)).optional()?;
+    Ok(found.is_some())
+}
+
+fn id_of(clt: ClientID) -> i64 {
+    let id: u64 = clt.into();
+    id as i64
+}
+
+#[async_trait]
+impl UserStore for SqliteStore {
+    async fn add_user(&self, clt: ClientID, user: &User) -> Result<(), BtcmDataError> {
+        let mut con = self.lock().await;
+        let tx = con.transaction()?;
+        if !exists(&tx, "SELECT 1 FROM users WHERE client_id = ?1 LIMIT 1", &[&id_of(clt)])? {
+            // 与Redis一致:重新注册已删除的用户时丢弃其已删除的用户信息
+            discard_deleted_user(&tx, id_of(clt))?;
+        }
+        bump_user_version(&tx, clt, None, false)?;
+        check_unique_fields(&tx, clt, user.to_fields().iter().map(|(field, value)| (field, value)))?;
+        replace_user_fields(&tx, clt, user)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    async fn update_user(&self, clt: ClientID, user: &User) -> Result<u64, BtcmDataError> {
+        let mut con = self.lock().await;
+        let tx = con.transaction()?;
+        let version = bump_user_version(&tx, clt, Some(*user.version()), true)?;
+        check_unique_fields(&tx, clt, user.to_fields().iter().map(|(field, value)| (field, value)))?;
+        replace_user_fields(&tx, clt, user)?;
+        tx.commit()?;
+        Ok(version)
+    }
+
+    async fn get_user(&self, clt: ClientID) -> Result<Option<User>, BtcmDataError> {
+        let con = self.lock().await;
+        let hm = query_fields(&con, "SELECT field, value FROM users WHERE client_id = ?1", &[&id_of(clt)])?;
+        if hm.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(User::from_fields(hm)?))
+    }
+
+    async fn get_user_fields(&self, clt: ClientID, fields: &[&str]) -> Result<HashMap<String, String>, BtcmDataError> {
+        let con = self.lock().await;
+        let mut hm = query_fields(&con, "SELECT field, value FROM users WHERE client_id = ?1", &[&id_of(clt)])?;
+        hm.retain(|field, _| fields.contains(&field.as_str()));
+        Ok(hm)
+    }
+
+    async fn set_user_fields(&self, clt: ClientID, fields: &HashMap<String, String>) -> Result<(), BtcmDataError> {
+        User::check_fields(fields)?;
+        let mut con = self.lock().await;
+        let tx = con.transaction()?;
+        bump_user_version(&tx, clt, None, true)?;
+        check_unique_fields(&tx, clt, fields.iter())?;
+        {
+            let mut stmt = tx.prepare_cached(
+                "INSERT INTO users (client_id, field, value) VALUES (?1, ?2, ?3)
+                 ON CONFLICT (client_id, field) DO UPDATE SET value = excluded.value",
+            )?;
+            for (field, value) in fields {
+                stmt.execute(params![id_of(clt), field, value])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    async fn delete_user_fields(&self, clt: ClientID, fields: &[&str]) -> Result<usize, BtcmDataError> {
+        User::check_field_names(fields.iter().copied())?;
+        let mut con = self.lock().await;
+        let tx = con.transaction()?;
+        if !exists(&tx, "SELECT 1 FROM users WHERE client_id = ?1 LIMIT 1", &[&id_of(clt)])? {
+            let user_id: u64 = clt.into();
+            return Err(BtcmDataError::NotFound(format!("user {}", user_id)));
+        }
+        let mut removed = 0;
+        {
+            let mut stmt = tx.prepare_cached("DELETE FROM users WHERE client_id = ?1 AND field = ?2")?;
+            for field in fields {
+                removed += stmt.execute(params![id_of(clt), field])?;
+            }
+        }
+        if removed > 0 {
+            bump_user_version(&tx, clt, None, true)?;
+        }
+        tx.commit()?;
+        Ok(removed)
+    }
+
+    async fn find_user_by(&self, field: UniqueField, value: &str) -> Result<Option<u64>, BtcmDataError> {
+        let con = self.lock().await;
+        let id: Option<i64> = con
+            .query_row(
+                "SELECT client_id FROM users WHERE field = ?1 AND value = ?2
+                 AND field IN ('username', 'phone', 'email') AND value <> ''",
+                params![field.as_str(), value],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(id.map(|id| id as u64))
+    }
+
+    async fn search_users_by_nickname(&self, prefix: &str, offset: usize, count: usize) -> Result<Vec<u64>, BtcmDataError> {
+        let con = self.lock().await;
+        // 与Redis一致:lower只转换ASCII字母，用范围查询代替LIKE以使用users_nickname索引
+        let mut stmt = con.prepare_cached(
+            "SELECT client_id FROM users WHERE field = 'nickname' AND value <> ''
+             AND lower(value) >= lower(?1) AND lower(value) < lower(?1) || char(1114111)
+             ORDER BY lower(value), client_id LIMIT ?2 OFFSET ?3",
+        )?;
+        let ids = stmt
+            .query_map(params![prefix, count as i64, offset as i64], |row| row.get::<_, i64>(0))?
+            .map(|id| id.map(|id| id as u64))
+            .collect::<Result<Vec<u64>, _>>()?;
+        Ok(ids)
+    }
+
+    async fn exists_user(&self, clt: ClientID) -> Result<bool, BtcmDataError> {
+        let con = self.lock().await;
+        exists(&con, "SELECT 1 FROM users WHERE client_id = ?1 LIMIT 1", &[&id_of(clt)])
+    }
+
+    async fn remove_user(&self, clt: ClientID, reason: Option<&str>) -> Result<bool, BtcmDataError> {
+        let mut con = self.lock().await;
+        let tx = con.transaction()?;
+        let removed = soft_delete_user(&tx, clt, reason)?;
+        tx.commit()?;
+        Ok(removed)
+    }
+
+    async fn restore_user(&self, clt: ClientID) -> Result<(), BtcmDataError> {
+        let mut con = self.lock().await;
+        let id = id_of(clt);
+        let tx = con.transaction()?;
+        if exists(&tx, "SELECT 1 FROM users WHERE client_id = ?1 LIMIT 1", &[&id])? {
+            return Err(BtcmDataError::Conflict(format!("user {} exists", id as u64)));
+        }
+        if !exists(&tx, "SELECT 1 FROM del_users_index WHERE client_id = ?1", &[&id])? {
+            return Err(BtcmDataError::NotFound(format!("deleted user {}", id as u64)));
+        }
+        let archived = query_fields(&tx, "SELECT field, value FROM del_users WHERE client_id = ?1", &[&id])?;
+        check_unique_fields(&tx, clt, archived.iter())?;
+        tx.execute("INSERT INTO users (client_id, field, value) SELECT client_id, field, value FROM del_users WHERE client_id = ?1", params![id])?;
+        discard_deleted_user(&tx, id)?;
+        bump_user_version(&tx, clt, None, true)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    async fn list_deleted_users(&self, offset: usize, count: usize) -> Result<Vec<DeletedUser>, BtcmDataError> {
+        let con = self.lock().await;
+        let mut stmt = con.prepare_cached(
+            "SELECT client_id, deleted_at, reason FROM del_users_index ORDER BY deleted_at, client_id LIMIT ?1 OFFSET ?2",
+        )?;
+        let rows = stmt
+            .query_map(params![count as i64, offset as i64], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?, row.get::<_, Option<String>>(2)?))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        rows.into_iter()
+            .map(|(id, deleted_at, reason)| {
+                let archived = query_fields(&con, "SELECT field, value FROM del_users WHERE client_id = ?1", &[&id])?;
+                Ok(DeletedUser::new(id as u64, User::from_fields(archived)?, deleted_at as u64, reason))
+            })
+            .collect()
+    }
+
+    async fn purge_deleted_users(&self, retention: Duration) -> Result<usize, BtcmDataError> {
+        let mut con = self.lock().await;
+        let cutoff = crate::now_millis().saturating_sub(retention.as_millis() as u64) as i64;
+        let tx = con.transaction()?;
+        tx.execute(
+            "DELETE FROM del_users WHERE client_id IN (SELECT client_id FROM del_users_index WHERE deleted_at <= ?1)",
+            params![cutoff],
+        )?;
+        let purged = tx.execute("DELETE FROM del_users_index WHERE deleted_at <= ?1", params![cutoff])?;
+        tx.commit()?;
+        Ok(purged)
+    }
+}
+
+#[async_trait]
+impl ContactStore for SqliteStore {
+    async fn add_user_contacts(&self, clt: ClientID, hs: &HashSet<u64>) -> Result<(), BtcmDataError> {
+        let mut con = self.lock().await;
+        let tx = con.transaction()?;
+        for contact in hs {
+            check_not_blocked(&tx, clt, ClientID::from(*contact))?;
+        }
+        execute_ids(&tx, "INSERT OR IGNORE INTO conts_user (client_id, contact_id) VALUES (?1, ?2)", id_of(clt), hs)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    async fn del_user_contacts(&self, clt: ClientID, hs: &HashSet<u64>) -> Result<(), BtcmDataError> {
+        let mut con = self.lock().await;
+        update_ids(&mut con, "DELETE FROM conts_user WHERE client_id = ?1 AND contact_id = ?2", id_of(clt), hs)
+    }
+
+    async fn get_user_contacts(&self, clt: ClientID) -> Result<HashSet<u64>, BtcmDataError> {
+        let con = self.lock().await;
+        query_ids(&con, "SELECT contact_id FROM conts_user WHERE client_id = ?1", id_of(clt))
+    }
+
+    async fn add_contact(&self, a: ClientID, b: ClientID) -> Result<(), BtcmDataError> {
+        let mut con = self.lock().await;
+        check_not_blocked(&con, a, b)?;
+        update_pair(&mut con, "INSERT OR IGNORE INTO conts_user (client_id, contact_id) VALUES (?1, ?2)", id_of(a), id_of(b))
+    }
+
+    async fn remove_contact(&self, a: ClientID, b: ClientID) -> Result<(), BtcmDataError> {
+        let mut con = self.lock().await;
+        update_pair(&mut con, "DELETE FROM conts_user WHERE client_id = ?1 AND contact_id = ?2", id_of(a), id_of(b))
+    }
+
+    async fn follow(&self, follower: ClientID, followee: ClientID) -> Result<(), BtcmDataError> {
+        let con = self.lock().await;
+        check_not_blocked(&con, follower, followee)?;
+        con.execute("INSERT OR IGNORE INTO conts_user (client_id, contact_id) VALUES (?1, ?2)", params![id_of(follower), id_of(followee)])?;
+        Ok(())
+    }
+
+    async fn unfollow(&self, follower: ClientID, followee: ClientID) -> Result<(), BtcmDataError> {
+        let con = self.lock().await;
+        con.execute("DELETE FROM conts_user WHERE client_id = ?1 AND contact_id = ?2", params![id_of(follower), id_of(followee)])?;
+        Ok(())
+    }
+
+    async fn add_group_contacts(&self, clt: ClientID, hs: &HashSet<u64>) -> Result<(), BtcmDataError> {
+        let mut con = self.lock().await;
+        update_ids(&mut con, "INSERT OR IGNORE INTO conts_group (client_id, group_id) VALUES (?1, ?2)", id_of(clt), hs)
+    }
+
+    async fn del_group_contacts(&self, clt: ClientID, hs: &HashSet<u64>) -> Result<(), BtcmDataError> {
+        let mut con = self.lock().await;
+        update_ids(&mut con, "DELETE FROM conts_group WHERE client_id = ?1 AND group_id = ?2", id_of(clt), hs)
+    }
+
+    async fn get_group_contacts(&self, clt: ClientID) -> Result<HashSet<u64>, BtcmDataError> {
+        let con = self.lock().await;
+        query_ids(&con, "SELECT group_id FROM conts_group WHERE client_id = ?1", id_of(clt))
+    }
+}
+
+#[async_trait]
+impl BlockStore for SqliteStore {
+    async fn block_user(&self, clt: ClientID, blocked: ClientID) -> Result<(), BtcmDataError> {
+        let con = self.lock().await;
+        con.execute("INSERT OR IGNORE INTO blocklist (client_id, blocked_id) VALUES (?1, ?2)", params![id_of(clt), id_of(blocked)])?;
+        Ok(())
+    }
+
+    async fn unblock_user(&self, clt: ClientID, blocked: ClientID) -> Result<bool, BtcmDataError> {
+        let con = self.lock().await;
+        let removed = con.execute("DELETE FROM blocklist WHERE client_id = ?1 AND blocked_id = ?2", params![id_of(clt), id_of(blocked)])?;
+        Ok(removed > 0)
+    }
+
+    async fn get_blocklist(&self, clt: ClientID) -> Result<HashSet<u64>, BtcmDataError> {
+        let con = self.lock().await;
+        query_ids(&con, "SELECT blocked_id FROM blocklist WHERE client_id = ?1", id_of(clt))
+    }
+
+    async fn is_blocked(&self, clt: ClientID, other: ClientID) -> Result<bool, BtcmDataError> {
+        let con = self.lock().await;
+        exists(&con, "SELECT 1 FROM blocklist WHERE client_id = ?1 AND blocked_id = ?2", &[&id_of(clt), &id_of(other)])
+    }
+}
+
+#[async_trait]
+impl GroupStore for SqliteStore {
+    async fn add_group(&self, clt: ClientID, hs: &HashSet<u64>) -> Result<(), BtcmDataError> {
+        let mut con = self.lock().await;
+        let tx = con.transaction()?;
+        if let Some(owner) = group_owner(&tx, clt)? {
+            check_not_blocking_owner(&tx, clt, owner, hs)?;
+        }
+        execute_ids(&tx, "INSERT OR IGNORE INTO group_members (group_id, member_id) VALUES (?1, ?2)", id_of(clt), hs)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    async fn del_group(&self, clt: ClientID, hs: &HashSet<u64>) -> Result<(), BtcmDataError> {
+        let mut con = self.lock().await;
+        let tx = con.transaction()?;
+        // 与Redis一致:成员中包含群主时拒绝删除
+        if group_owner(&tx, clt)?.is_some_and(|owner| hs.contains(&owner)) {
+            let group_id: u64 = clt.into();
+            return Err(BtcmDataError::Conflict(format!("cannot remove the owner of group {}", group_id)));
+        }
+        {
+            let mut stmt = tx.prepare_cached("DELETE FROM group_members WHERE group_id = ?1 AND member_id = ?2")?;
+            let mut admins = tx.prepare_cached("DELETE FROM group_admins WHERE group_id = ?1 AND member_id = ?2")?;
+            for member in hs {
+                stmt.execute(params![id_of(clt), *member as i64])?;
+                admins.execute(params![id_of(clt), *member as i64])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    async fn create_group(&self, clt: ClientID, info: &GroupInfo, hs: &HashSet<u64>) -> Result<(), BtcmDataError> {
+        let mut con = self.lock().await;
+        let tx = con.transaction()?;
+        if exists(&tx, "SELECT 1 FROM group_info WHERE group_id = ?1 LIMIT 1", &[&id_of(clt)])? {
+            let group_id: u64 = clt.into();
+            return Err(BtcmDataError::Conflict(format!("group {} already exists", group_id)));
+        }
+        check_not_blocking_owner(&tx, clt, *info.owner(), hs)?;
+        {
+            let mut stmt = tx.prepare_cached("INSERT INTO group_info (group_id, field, value) VALUES (?1, ?2, ?3)")?;
+            for (field, value) in info.to_fields() {
+                stmt.execute(params![id_of(clt), field, value])?;
+            }
+            let mut stmt = tx.prepare_cached("INSERT OR IGNORE INTO group_members (group_id, member_id) VALUES (?1, ?2)")?;
+            for member in hs.iter().chain([info.owner()]) {
+                stmt.execute(params![id_of(clt), *member as i64])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    async fn get_group_info(&self, clt: ClientID) -> Result<Option<GroupInfo>, BtcmDataError> {
+        let con = self.lock().await;
+        let hm = query_fields(&con, "SELECT field, value FROM group_info WHERE group_id = ?1", &[&id_of(clt)])?;
+        if hm.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(GroupInfo::from_fields(hm)?))
+    }
+
+    async fn transfer_owner(&self, clt: ClientID, new_owner: ClientID) -> Result<(), BtcmDataError> {
+        let mut con = self.lock().await;
+        let tx = con.transaction()?;
+        let group_id: u64 = clt.into();
+        let owner_id: u64 = new_owner.into();
+        if group_owner(&tx, clt)?.is_none() {
+            return Err(BtcmDataError::NotFound(format!("group {}", group_id)));
+        }
+        if !exists(&tx, "SELECT 1 FROM group_members WHERE group_id = ?1 AND member_id = ?2", &[&id_of(clt), &id_of(new_owner)])? {
+            return Err(BtcmDataError::NotFound(format!("member {} of group {}", owner_id, group_id)));
+        }
+        set_group_owner(&tx, clt, owner_id)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    async fn promote_admin(&self, clt: ClientID, member: ClientID) -> Result<(), BtcmDataError> {
+        let mut con = self.lock().await;
+        let tx = con.transaction()?;
+        let group_id: u64 = clt.into();
+        let member_id: u64 = member.into();
+        if !exists(&tx, "SELECT 1 FROM group_members WHERE group_id = ?1 AND member_id = ?2", &[&id_of(clt), &id_of(member)])? {
+            return Err(BtcmDataError::NotFound(format!("member {} of group {}", member_id, group_id)));
+        }
+        if group_owner(&tx, clt)? == Some(member_id) {
+            return Err(BtcmDataError::Conflict(format!("member {} is the owner of group {}", member_id, group_id)));
+        }
+        tx.execute("INSERT OR IGNORE INTO group_admins (group_id, member_id) VALUES (?1, ?2)", params![id_of(clt), id_of(member)])?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    async fn demote_admin(&self, clt: ClientID, member: ClientID) -> Result<bool, BtcmDataError> {
+        let con = self.lock().await;
+        let removed = con.execute("DELETE FROM group_admins WHERE group_id = ?1 AND member_id = ?2", params![id_of(clt), id_of(member)])?;
+        Ok(removed > 0)
+    }
+
+    async fn get_member_role(&self, clt: ClientID, member: ClientID) -> Result<Option<GroupRole>, BtcmDataError> {
+        let con = self.lock().await;
+        let member_id: u64 = member.into();
+        let role = if !exists(&con, "SELECT 1 FROM group_members WHERE group_id = ?1 AND member_id = ?2", &[&id_of(clt), &id_of(member)])? {
+            None
+        } else if group_owner(&con, clt)? == Some(member_id) {
+            Some(GroupRole::Owner)
+        } else if exists(&con, "SELECT 1 FROM group_admins WHERE group_id = ?1 AND member_id = ?2", &[&id_of(clt), &id_of(member)])? {
+            Some(GroupRole::Admin)
+        } else {
+            Some(GroupRole::Member)
+        };
+        Ok(role)
+    }
+
+    async fn get_group_admins(&self, clt: ClientID) -> Result<HashSet<u64>, BtcmDataError> {
+        let con = self.lock().await;
+        query_ids(&con, "SELECT member_id FROM group_admins WHERE group_id = ?1", id_of(clt))
+    }
+
+    async fn get_group(&self, clt: ClientID) -> Result<HashSet<u64>, BtcmDataError> {
+        let con = self.lock().await;
+        query_ids(&con, "SELECT member_id FROM group_members WHERE group_id = ?1", id_of(clt))
+    }
+
+    async fn exists_group(&self, clt: ClientID) -> Result<bool, BtcmDataError> {
+        let con = self.lock().await;
+        exists(&con, "SELECT 1 FROM group_members WHERE group_id = ?1 LIMIT 1", &[&id_of(clt)])
+    }
+
+    async fn remove_group(&self, clt: ClientID) -> Result<bool, BtcmDataError> {
+        let mut con = self.lock().await;
+        let tx = con.transaction()?;
+        let removed = remove_group(&tx, clt)?;
+        tx.commit()?;
+        Ok(removed)
+    }
+
+    async fn groups_of_user(&self, clt: ClientID) -> Result<HashSet<u64>, BtcmDataError> {
+        let con = self.lock().await;
+        query_ids(&con, "SELECT group_id FROM group_members WHERE member_id = ?1", id_of(clt))
+    }
+
+    async fn common_groups(&self, a: ClientID, b: ClientID) -> Result<HashSet<u64>, BtcmDataError> {
+        let con = self.lock().await;
+        let mut stmt = con.prepare_cached(
+            "SELECT a.group_id FROM group_members a JOIN group_members b ON a.group_id = b.group_id \
+             WHERE a.member_id = ?1 AND b.member_id = ?2",
+        )?;
+        let rows = stmt.query_map(params![id_of(a), id_of(b)], |row| row.get::<_, i64>(0))?;
+        let mut result = HashSet::new();
+        for row in rows {
+            result.insert(row? as u64);
+        }
+        Ok(result)
+    }
+}
+
+#[async_trait]
+impl DeviceStore for SqliteStore {
+    async fn add_dev2clt(&self, clt: ClientID, devs: &HashSet<u64>) -> Result<(), BtcmDataError> {
+        let mut con = self.lock().await;
+        update_ids(&mut con, "INSERT OR IGNORE INTO client_device (client_id, device_id) VALUES (?1, ?2)", id_of(clt), devs)
+    }
+
+    async fn del_dev4clt(&self, clt: ClientID, devs: &HashSet<u64>) -> Result<(), BtcmDataError> {
+        let mut con = self.lock().await;
+        update_ids(&mut con, "DELETE FROM client_device WHERE client_id = ?1 AND device_id = ?2", id_of(clt), devs)
+    }
+
+    async fn get_devclt_set(&self, clt: ClientID) -> Result<HashSet<u64>, BtcmDataError> {
+        let con = self.lock().await;
+        query_ids(&con, "SELECT device_id FROM client_device WHERE client_id = ?1", id_of(clt))
+    }
+
+    async fn exists_devclt(&self, clt: ClientID) -> Result<bool, BtcmDataError> {
+        let con = self.lock().await;
+        exists(&con, "SELECT 1 FROM client_device WHERE client_id = ?1 LIMIT 1", &[&id_of(clt)])
+    }
+
+    async fn remove_devclt_set(&self, clt: ClientID) -> Result<bool, BtcmDataError> {
+        let con = self.lock().await;
+        let removed = con.execute("DELETE FROM client_device WHERE client_id = ?1", params![id_of(clt)])?;
+        Ok(removed > 0)
+    }
+
+    async fn add_dev2clt_hash(&self, clt: ClientID, dev: u32, device: &Device) -> Result<(), BtcmDataError> {
+        let mut con = self.lock().await;
+        let tx = con.transaction()?;
+        tx.execute("DELETE FROM client_device_hash WHERE client_id = ?1 AND device_id = ?2", params![id_of(clt), dev])?;
+        {
+            let mut stmt = tx.prepare_cached(
+                "INSERT INTO client_device_hash (client_id, device_id, field, value) VALUES (?1, ?2, ?3, ?4)",
+            )?;
+            for (field, value) in device.to_fields() {
+                stmt.execute(params![id_of(clt), dev, field, value])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    async fn get_device(&self, clt: ClientID, dev: u32) -> Result<Option<Device>, BtcmDataError> {
+        let con = self.lock().await;
+        let hm = query_fields(
+            &con,
+            "SELECT field, value FROM client_device_hash WHERE client_id = ?1 AND device_id = ?2",
+            &[&id_of(clt), &dev],
+        )?;
+        if hm.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(Device::from_fields(hm)?))
+    }
+
+    async fn get_device_fields(&self, clt: ClientID, dev: u32, fields: &[&str]) -> Result<HashMap<String, String>, BtcmDataError> {
+        let con = self.lock().await;
+        let mut hm = query_fields(
+            &con,
+            "SELECT field, value FROM client_device_hash WHERE client_id = ?1 AND device_id = ?2",
+            &[&id_of(clt), &dev],
+        )?;
+        hm.retain(|field, _| fields.contains(&field.as_str()));
+        Ok(hm)
+    }
+
+    async fn set_device_fields(&self, clt: ClientID, dev: u32, fields: &HashMap<String, String>) -> Result<(), BtcmDataError> {
+        Device::check_fields(fields)?;
+        let mut con = self.lock().await;
+        let tx = con.transaction()?;
+        if !exists(&tx, "SELECT 1 FROM client_device_hash WHERE client_id = ?1 AND device_id = ?2 LIMIT 1", &[&id_of(clt), &dev])? {
+            let user_id: u64 = clt.into();
+            return Err(BtcmDataError::NotFound(format!("device {} of client {}", dev, user_id)));
+        }
+        {
+            let mut stmt = tx.prepare_cached(
+                "INSERT INTO client_device_hash (client_id, device_id, field, value) VALUES (?1, ?2, ?3, ?4)
+                 ON CONFLICT (client_id, device_id, field) DO UPDATE SET value = excluded.value",
+            )?;
+            for (field, value) in fields {
+                stmt.execute(params![id_of(clt), dev, field, value])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    async fn delete_device_fields(&self, clt: ClientID, dev: u32, fields: &[&str]) -> Result<usize, BtcmDataError> {
+        let mut con = self.lock().await;
+        let tx = con.transaction()?;
+        let mut removed = 0;
+        {
+            let mut stmt = tx.prepare_cached("DELETE FROM client_device_hash WHERE client_id = ?1 AND device_id = ?2 AND field = ?3")?;
+            for field in fields {
+                removed += stmt.execute(params![id_of(clt), dev, field])?;
+            }
+        }
+        tx.commit()?;
+        Ok(removed)
+    }
+
+    async fn exists_device(&self, clt: ClientID, dev: u32) -> Result<bool, BtcmDataError> {
+        let con = self.lock().await;
+        exists(
+            &con,
+            "SELECT 1 FROM client_device_hash WHERE client_id = ?1 AND device_id = ?2 LIMIT 1",
+            &[&id_of(clt), &dev],
+        )
+    }
+
+    async fn remove_device(&self, clt: ClientID, dev: u32) -> Result<bool, BtcmDataError> {
+        let con = self.lock().await;
+        let removed = con.execute(
+            "DELETE FROM client_device_hash WHERE client_id = ?1 AND device_id = ?2",
+            params![id_of(clt), dev],
+        )?;
+        Ok(removed > 0)
+    }
+}
+
+#[async_trait]
+impl AccountStore for SqliteStore {
+    /// 在一个事务中完成，联系人包括所有把该用户加为联系人的用户。
+    /// 用户拥有的群组转让给ID最小的管理员，没有管理员时转让给ID最小的其他成员，没有其他成员时删除群组。
+    async fn delete_account(&self, clt: ClientID, reason: Option<&str>) -> Result<AccountDeletionReport, BtcmDataError> {
+        let mut con = self.lock().await;
+        let id = id_of(clt);
+        let user_id: u64 = clt.into();
+        let tx = con.transaction()?;
+        let mut report = AccountDeletionReport {
+            user_removed: soft_delete_user(&tx, clt, reason)?,
+            contacts: query_ids(&tx, "SELECT client_id FROM conts_user WHERE contact_id = ?1", id)?,
+            ..Default::default()
+        };
+        for group in query_ids(&tx, "SELECT group_id FROM group_members WHERE member_id = ?1", id)? {
+            let gid = ClientID::from(group);
+            if group_owner(&tx, gid)? == Some(user_id) {
+                let next: Option<i64> = tx.query_row(
+                    "SELECT COALESCE(
+                         (SELECT MIN(member_id) FROM group_admins WHERE group_id = ?1 AND member_id <> ?2),
+                         (SELECT MIN(member_id) FROM group_members WHERE group_id = ?1 AND member_id <> ?2))",
+                    params![id_of(gid), id],
+                    |row| row.get(0),
+                )?;
+                match next {
+                    Some(owner) => {
+                        set_group_owner(&tx, gid, owner as u64)?;
+                        report.transferred_groups.insert(group, owner as u64);
+                    }
+                    None => {
+                        remove_group(&tx, gid)?;
+                        report.removed_groups.insert(group);
+                        continue;
+                    }
+                }
+            }
+            report.groups.insert(group);
+        }
+        let devices = query_ids(
+            &tx,
+            "SELECT device_id FROM client_device WHERE client_id = ?1 \
+             UNION SELECT device_id FROM client_device_hash WHERE client_id = ?1",
+            id,
+        )?;
+        report.devices = devices.into_iter().map(device_id).collect::<Result<_, _>>()?;
+        for sql in [
+            "DELETE FROM conts_user WHERE client_id = ?1 OR contact_id = ?1",
+            "DELETE FROM conts_group WHERE client_id = ?1",
+            "DELETE FROM blocklist WHERE client_id = ?1",
+            "DELETE FROM group_members WHERE member_id = ?1",
+            "DELETE FROM group_admins WHERE member_id = ?1",
+            "DELETE FROM client_device WHERE client_id = ?1",
+            "DELETE FROM client_device_hash WHERE client_id = ?1",
+        ] {
+            tx.execute(sql, params![id])?;
+        }
+        tx.commit()?;
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn user_soft_delete() {
+        let store = SqliteStore::open_in_memory().unwrap();
+        let clt = ClientID::from(1001);
+        let mut user = User::new("John");
+        user.extra_mut().insert("age".to_string(), "30".to_string());
+
+        store.add_user(clt, &user).await.unwrap();
+        assert!(store.exists_user(clt).await.unwrap());
+        let mut stored = store.get_user(clt).await.unwrap().unwrap();
+        assert_eq!(*stored.version(), 1);
+        user.set_version(1);
+        assert_eq!(stored, user);
+
+        // 基于旧版本号的更新失败
+        stored.set_nickname("Johnny".to_string());
+        assert_eq!(store.update_user(clt, &stored).await.unwrap(), 2);
+        assert!(matches!(store.update_user(clt, &stored).await, Err(BtcmDataError::Conflict(_))));
+        user = store.get_user(clt).await.unwrap().unwrap();
+
+        assert!(store.remove_user(clt, Some("spam")).await.unwrap());
+        assert!(!store.exists_user(clt).await.unwrap());
+        // 再次删除不会覆盖已删除的用户信息
+        assert!(!store.remove_user(clt, None).await.unwrap());
+        assert!(matches!(store.remove_user(ClientID::from(1002), None).await, Err(BtcmDataError::NotFound(_))));
+
+        let deleted = store.list_deleted_users(0, 10).await.unwrap();
+        assert_eq!(deleted.len(), 1);
+        assert_eq!((*deleted[0].client_id(), deleted[0].user(), deleted[0].reason().as_deref()), (1001, &user, Some("spam")));
+
+        store.restore_user(clt).await.unwrap();
+        assert_eq!(*store.get_user(clt).await.unwrap().unwrap().version(), 3);
+        assert!(store.list_deleted_users(0, 10).await.unwrap().is_empty());
+        assert!(matches!(store.restore_user(clt).await, Err(BtcmDataError::Conflict(_))));
+
+        // 超过保留期限后清除，不能再恢复
+        store.remove_user(clt, None).await.unwrap();
+        assert_eq!(store.purge_deleted_users(Duration::from_secs(3600)).await.unwrap(), 0);
+        assert_eq!(store.purge_deleted_users(Duration::ZERO).await.unwrap(), 1);
+        assert!(matches!(store.restore_user(clt).await, Err(BtcmDataError::NotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn unique_fields() {
+        let store = SqliteStore::open_in_memory().unwrap();
+        let (alice, bob) = (ClientID::from(1001), ClientID::from(1002));
+        let mut user = User::new("Alice");
+        user.set_username(Some("alice".to_string()));
+        user.set_phone(Some("+8613800000000".to_string()));
+        store.add_user(alice, &user).await.unwrap();
+        // 重写自己的值不算重复
+        store.add_user(alice, &user).await.unwrap();
+
+        user.set_phone(None);
+        assert!(matches!(store.add_user(bob, &user).await, Err(BtcmDataError::Duplicate(_))));
+        assert!(!store.exists_user(bob).await.unwrap());
+        assert_eq!(store.find_user_by(UniqueField::Username, "alice").await.unwrap(), Some(1001));
+        assert_eq!(store.find_user_by(UniqueField::Phone, "+8613800000000").await.unwrap(), Some(1001));
+
+        // 删除用户后其他用户可以使用该用户名
+        store.remove_user(alice, None).await.unwrap();
+        assert_eq!(store.find_user_by(UniqueField::Username, "alice").await.unwrap(), None);
+        store.add_user(bob, &user).await.unwrap();
+        assert_eq!(store.find_user_by(UniqueField::Username, "alice").await.unwrap(), Some(1002));
+    }
+
+    #[tokio::test]
+    async fn nickname_search() {
+        let store = SqliteStore::open_in_memory().unwrap();
+        for (id, nickname) in [(1001, "john"), (1002, "Joan"), (1003, "Bob"), (1004, "JOHN")] {
+            store.add_user(ClientID::from(id), &User::new(nickname)).await.unwrap();
+        }
+        assert_eq!(store.search_users_by_nickname("jo", 0, 10).await.unwrap(), vec![1002, 1001, 1004]);
+        assert_eq!(store.search_users_by_nickname("JOH", 1, 10).await.unwrap(), vec![1004]);
+
+        // 修改昵称和删除用户后索引随之更新
+        let nickname: HashMap<String, String> = [("nickname".to_string(), "Bobby".to_string())].into_iter().collect();
+        store.set_user_fields(ClientID::from(1001), &nickname).await.unwrap();
+        store.remove_user(ClientID::from(1004), None).await.unwrap();
+        assert_eq!(store.search_users_by_nickname("bo", 0, 10).await.unwrap(), vec![1003, 1001]);
+        assert_eq!(store.search_users_by_nickname("jo", 0, 10).await.unwrap(), vec![1002]);
+
+        // 通配符按字面匹配，非ASCII字母不转换大小写
+        store.add_user(ClientID::from(1005), &User::new("J_n%")).await.unwrap();
+        store.add_user(ClientID::from(1006), &User::new("Éva")).await.unwrap();
+        assert_eq!(store.search_users_by_nickname("j_", 0, 10).await.unwrap(), vec![1005]);
+        assert!(store.search_users_by_nickname("j%", 0, 10).await.unwrap().is_empty());
+        assert!(store.search_users_by_nickname("é", 0, 10).await.unwrap().is_empty());
+        assert_eq!(store.search_users_by_nickname("É", 0, 10).await.unwrap(), vec![1006]);
+    }
+
+    #[tokio::test]
+    async fn sets_and_device_hash() {
+        let store = SqliteStore::open_in_memory().unwrap();
+        let clt = ClientID::from(1001);
+        let ids: HashSet<u64> = [1, 2, 3].into_iter().collect();
+
+        store.add_group(clt, &ids).await.unwrap();
+        store.del_group(clt, &[2].into_iter().collect()).await.unwrap();
+        assert_eq!(store.get_group(clt).await.unwrap(), [1, 3].into_iter().collect());
+        store.add_group(ClientID::from(8), &[1, 2].into_iter().collect()).await.unwrap();
+        assert_eq!(store.groups_of_user(ClientID::from(1)).await.unwrap(), [1001, 8].into_iter().collect());
+        assert_eq!(store.common_groups(ClientID::from(1), ClientID::from(2)).await.unwrap(), [8].into_iter().collect());
+        assert!(store.remove_group(clt).await.unwrap());
+        assert!(!store.exists_group(clt).await.unwrap());
+
+        let other = ClientID::from(1002);
+        store.add_contact(clt, other).await.unwrap();
+        assert_eq!(store.get_user_contacts(other).await.unwrap(), [1001].into_iter().collect());
+        store.remove_contact(other, clt).await.unwrap();
+        assert!(store.get_user_contacts(clt).await.unwrap().is_empty());
+        store.follow(clt, other).await.unwrap();
+        assert!(store.get_user_contacts(other).await.unwrap().is_empty());
+        store.block_user(other, clt).await.unwrap();
+        assert!(matches!(store.add_contact(clt, other).await, Err(BtcmDataError::Blocked(_))));
+        assert!(matches!(store.add_user_contacts(clt, &[1002, 1003].into_iter().collect()).await, Err(BtcmDataError::Blocked(_))));
+        assert!(matches!(store.create_group(ClientID::from(9), &GroupInfo::new("g", clt), &[1002].into_iter().collect()).await, Err(BtcmDataError::Blocked(_))));
+        assert!(!store.get_user_contacts(clt).await.unwrap().contains(&1003));
+        assert!(store.unblock_user(other, clt).await.unwrap());
+
+        store.add_dev2clt(clt, &ids).await.unwrap();
+        assert_eq!(store.get_devclt_set(clt).await.unwrap(), ids);
+
+        let mut device = Device::default();
+        device.set_kind(Some("Smartphone".to_string()));
+        store.add_dev2clt_hash(clt, 1, &device).await.unwrap();
+        assert_eq!(store.get_device(clt, 1).await.unwrap(), Some(device));
+        let fields: HashMap<String, String> = [("last_seen".to_string(), "42".to_string())].into_iter().collect();
+        store.set_device_fields(clt, 1, &fields).await.unwrap();
+        assert_eq!(store.get_device_fields(clt, 1, &["last_seen", "os"]).await.unwrap(), fields);
+        assert_eq!(store.delete_device_fields(clt, 1, &["type", "os"]).await.unwrap(), 1);
+        assert!(matches!(store.set_device_fields(clt, 2, &fields).await, Err(BtcmDataError::NotFound(_))));
+        assert!(store.remove_device(clt, 1).await.unwrap());
+        assert!(!store.exists_device(clt, 1).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn group_admins() {
+        let store = SqliteStore::open_in_memory().unwrap();
+        let (gid, owner, admin, member) = (ClientID::from(9), ClientID::from(1001), ClientID::from(1002), ClientID::from(1003));
+        store.create_group(gid, &GroupInfo::new("family", owner), &[1002, 1003].into_iter().collect()).await.unwrap();
+
+        store.promote_admin(gid, admin).await.unwrap();
+        assert!(matches!(store.promote_admin(gid, owner).await, Err(BtcmDataError::Conflict(_))));
+        assert!(matches!(store.promote_admin(gid, ClientID::from(1004)).await, Err(BtcmDataError::NotFound(_))));
+        assert_eq!(store.get_group_admins(gid).await.unwrap(), [1002].into_iter().collect());
+        assert_eq!(store.get_member_role(gid, owner).await.unwrap(), Some(GroupRole::Owner));
+        assert_eq!(store.get_member_role(gid, admin).await.unwrap(), Some(GroupRole::Admin));
+        assert_eq!(store.get_member_role(gid, member).await.unwrap(), Some(GroupRole::Member));
+        assert_eq!(store.get_member_role(gid, ClientID::from(1004)).await.unwrap(), None);
+        assert!(store.demote_admin(gid, admin).await.unwrap());
+        assert!(!store.demote_admin(gid, admin).await.unwrap());
+
+        // 新群主不再是管理员，移出群组的成员同时失去管理员身份
+        store.promote_admin(gid, admin).await.unwrap();
+        store.promote_admin(gid, member).await.unwrap();
+        store.transfer_owner(gid, admin).await.unwrap();
+        assert_eq!(store.get_member_role(gid, owner).await.unwrap(), Some(GroupRole::Member));
+        assert_eq!(store.get_group_admins(gid).await.unwrap(), [1003].into_iter().collect());
+        store.del_group(gid, &[1003].into_iter().collect()).await.unwrap();
+        assert!(store.get_group_admins(gid).await.unwrap().is_empty());
+        store.add_group(gid, &[1003].into_iter().collect()).await.unwrap();
+        assert_eq!(store.get_member_role(gid, member).await.unwrap(), Some(GroupRole::Member));
+    }
+
+    #[tokio::test]
+    async fn account_deletion() {
+        let store = SqliteStore::open_in_memory().unwrap();
+        let (clt, friend, fan) = (ClientID::from(1001), ClientID::from(1002), ClientID::from(1003));
+        store.add_user(clt, &User::new("John")).await.unwrap();
+        store.add_contact(clt, friend).await.unwrap();
+        store.follow(fan, clt).await.unwrap();
+        store.add_group(ClientID::from(8), &[1001, 1002].into_iter().collect()).await.unwrap();
+        store.create_group(ClientID::from(9), &GroupInfo::new("family", clt), &[1002, 1003].into_iter().collect()).await.unwrap();
+        store.create_group(ClientID::from(10), &GroupInfo::new("alone", clt), &HashSet::new()).await.unwrap();
+        let owner: HashSet<u64> = [1001].into_iter().collect();
+        assert!(matches!(store.del_group(ClientID::from(9), &owner).await, Err(BtcmDataError::Conflict(_))));
+        // 管理员优先于ID更小的普通成员成为新群主
+        store.promote_admin(ClientID::from(9), fan).await.unwrap();
+        store.add_dev2clt(clt, &[1].into_iter().collect()).await.unwrap();
+        store.add_dev2clt_hash(clt, 2, &Device::default()).await.unwrap();
+
+        let report = store.delete_account(clt, Some("closed")).await.unwrap();
+        assert!(*report.user_removed());
+        assert_eq!(*report.contacts(), [1002, 1003].into_iter().collect());
+        assert_eq!(*report.groups(), [8, 9].into_iter().collect());
+        assert_eq!(*report.transferred_groups(), [(9, 1003)].into_iter().collect());
+        assert_eq!(*report.removed_groups(), [10].into_iter().collect());
+        assert_eq!(*report.devices(), [1, 2].into_iter().collect());
+        assert!(store.get_user_contacts(fan).await.unwrap().is_empty());
+        assert_eq!(store.get_group(ClientID::from(8)).await.unwrap(), [1002].into_iter().collect());
+        assert_eq!(*store.get_group_info(ClientID::from(9)).await.unwrap().unwrap().owner(), 1003);
+        assert!(store.get_group_admins(ClientID::from(9)).await.unwrap().is_empty());
+        assert!(store.get_group_info(ClientID::from(10)).await.unwrap().is_none());
+        assert!(!store.exists_devclt(clt).await.unwrap());
+
+        // 再次注销只清理剩余数据
+        assert_eq!(store.delete_account(clt, None).await.unwrap(), AccountDeletionReport::default());
+        assert!(matches!(store.delete_account(friend, None).await, Err(BtcmDataError::NotFound(_))));
+    }
+}
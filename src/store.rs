@@ -0,0 +1,205 @@
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+use async_trait::async_trait;
+use btcmbase::client::ClientID;
+use crate::error::BtcmDataError;
+use crate::model::{AccountDeletionReport, DeletedUser, Device, GroupInfo, GroupRole, UniqueField, User};
+
+/// # User Store
+///
+/// Storage of the user profile (`User`), keyed by `ClientID`. Backends store the
+/// profile as field/value pairs, see `User::to_fields`.
+///
+/// `remove_user` is a soft delete: the profile is moved aside (e.g. to `del_users:<id>`
+/// in Redis) instead of being dropped, together with the deletion time and reason. A
+/// deleted user can be restored with `restore_user` until `purge_deleted_users` drops
+/// it; `add_user` on a deleted user's id discards the archived profile.
+///
+/// The values of the `UniqueField`s are indexed: every write refuses a value already used
+/// by another user with `BtcmDataError::Duplicate`, leaving the profile unchanged, and
+/// `remove_user` releases the user's values.
+#[async_trait]
+pub trait UserStore: Send + Sync {
+    /// Writes the user's profile, replacing the stored one and incrementing its version.
+    async fn add_user(&self, clt: ClientID, user: &User) -> Result<(), BtcmDataError>;
+    /// Replaces the user's profile only if the stored version still equals `user.version()`,
+    /// returns the new version. `BtcmDataError::Conflict` if the version changed,
+    /// `BtcmDataError::NotFound` if the user does not exist.
+    async fn update_user(&self, clt: ClientID, user: &User) -> Result<u64, BtcmDataError>;
+    /// Returns the user's profile, `None` if the user does not exist.
+    async fn get_user(&self, clt: ClientID) -> Result<Option<User>, BtcmDataError>;
+    /// Returns only the given fields of the user's profile; missing fields are left out.
+    async fn get_user_fields(&self, clt: ClientID, fields: &[&str]) -> Result<HashMap<String, String>, BtcmDataError>;
+    /// Writes only the given fields, leaving the others untouched, and increments the version.
+    /// `BtcmDataError::NotFound` if the user does not exist, `BtcmDataError::TypeMismatch`
+    /// if a value does not fit `User`, `BtcmDataError::Conflict` for the `version` field.
+    async fn set_user_fields(&self, clt: ClientID, fields: &HashMap<String, String>) -> Result<(), BtcmDataError>;
+    /// Deletes the given fields, returns how many existed. The version is incremented if
+    /// any field was deleted. `BtcmDataError::NotFound` if the user does not exist.
+    async fn delete_user_fields(&self, clt: ClientID, fields: &[&str]) -> Result<usize, BtcmDataError>;
+    /// Returns the user whose `field` has the given value, `None` if no user uses it.
+    async fn find_user_by(&self, field: UniqueField, value: &str) -> Result<Option<u64>, BtcmDataError>;
+    /// Returns up to `count` users whose nickname starts with `prefix`, skipping the first
+    /// `offset`. Matching ignores ASCII case; results are ordered by nickname, then by id.
+    async fn search_users_by_nickname(&self, prefix: &str, offset: usize, count: usize) -> Result<Vec<u64>, BtcmDataError>;
+    /// Returns whether the user's profile exists.
+    async fn exists_user(&self, clt: ClientID) -> Result<bool, BtcmDataError>;
+    /// Soft deletes the user's profile, recording the deletion time and `reason`.
+    /// Returns false if the user is already deleted, `BtcmDataError::NotFound` if it
+    /// never existed.
+    async fn remove_user(&self, clt: ClientID, reason: Option<&str>) -> Result<bool, BtcmDataError>;
+    /// Restores a deleted user's profile, incrementing its version. `BtcmDataError::NotFound`
+    /// if the user is not deleted, `BtcmDataError::Conflict` if the user exists again and
+    /// `BtcmDataError::Duplicate` if one of its unique values was taken in the meantime.
+    async fn restore_user(&self, clt: ClientID) -> Result<(), BtcmDataError>;
+    /// Returns up to `count` deleted users, oldest deletion first, skipping the first `offset`.
+    async fn list_deleted_users(&self, offset: usize, count: usize) -> Result<Vec<DeletedUser>, BtcmDataError>;
+    /// Drops the deleted users whose deletion is older than `retention`, returns how many.
+    async fn purge_deleted_users(&self, retention: Duration) -> Result<usize, BtcmDataError>;
+}
+
+/// # Contact Store
+///
+/// Storage of a user's contact sets: the user contacts (`conts_user:`) and the
+/// group contacts (`conts_group:`).
+///
+/// `add_user_contacts` writes one user's set only. Use `add_contact` for a mutual
+/// relationship, which writes both directions atomically, and `follow` where the
+/// asymmetry is intended.
+#[async_trait]
+pub trait ContactStore: Send + Sync {
+    /// Adds user ids to the user's contact set. `BtcmDataError::Blocked`, and nothing
+    /// is added, if the user and any of them have blocked each other in either direction.
+    async fn add_user_contacts(&self, clt: ClientID, hs: &HashSet<u64>) -> Result<(), BtcmDataError>;
+    /// Removes user ids from the user's contact set.
+    async fn del_user_contacts(&self, clt: ClientID, hs: &HashSet<u64>) -> Result<(), BtcmDataError>;
+    /// Returns the user's contact set.
+    async fn get_user_contacts(&self, clt: ClientID) -> Result<HashSet<u64>, BtcmDataError>;
+    /// Makes the two users contacts of each other, both directions in one transaction.
+    /// `BtcmDataError::Blocked` if either user has blocked the other.
+    async fn add_contact(&self, a: ClientID, b: ClientID) -> Result<(), BtcmDataError>;
+    /// Removes both directions of the relationship in one transaction.
+    async fn remove_contact(&self, a: ClientID, b: ClientID) -> Result<(), BtcmDataError>;
+    /// Adds `followee` to `follower`'s contact set only.
+    /// `BtcmDataError::Blocked` if either user has blocked the other.
+    async fn follow(&self, follower: ClientID, followee: ClientID) -> Result<(), BtcmDataError>;
+    /// Removes `followee` from `follower`'s contact set only.
+    async fn unfollow(&self, follower: ClientID, followee: ClientID) -> Result<(), BtcmDataError>;
+    /// Adds group ids to the user's group contact set.
+    async fn add_group_contacts(&self, clt: ClientID, hs: &HashSet<u64>) -> Result<(), BtcmDataError>;
+    /// Removes group ids from the user's group contact set.
+    async fn del_group_contacts(&self, clt: ClientID, hs: &HashSet<u64>) -> Result<(), BtcmDataError>;
+    /// Returns the user's group contact set.
+    async fn get_group_contacts(&self, clt: ClientID) -> Result<HashSet<u64>, BtcmDataError>;
+}
+
+/// # Block Store
+///
+/// Storage of a user's blocklist. A block in either direction makes `add_contact`,
+/// `add_user_contacts`, `follow` and friend requests between the two users fail with
+/// `BtcmDataError::Blocked`. A user who has blocked a group's owner cannot be added to
+/// the group.
+#[async_trait]
+pub trait BlockStore: Send + Sync {
+    /// Adds `blocked` to the user's blocklist.
+    async fn block_user(&self, clt: ClientID, blocked: ClientID) -> Result<(), BtcmDataError>;
+    /// Removes `blocked` from the user's blocklist, returns whether it was blocked.
+    async fn unblock_user(&self, clt: ClientID, blocked: ClientID) -> Result<bool, BtcmDataError>;
+    /// Returns the user's blocklist.
+    async fn get_blocklist(&self, clt: ClientID) -> Result<HashSet<u64>, BtcmDataError>;
+    /// Returns whether `clt` has blocked `other` (one direction only).
+    async fn is_blocked(&self, clt: ClientID, other: ClientID) -> Result<bool, BtcmDataError>;
+}
+
+/// # Group Store
+///
+/// Storage of group membership and of the group profile (`GroupInfo`), keyed by the
+/// group's `ClientID`.
+#[async_trait]
+pub trait GroupStore: Send + Sync {
+    /// Adds user ids to the group. `BtcmDataError::Blocked`, and nobody is added, if any
+    /// of them has blocked the group owner; groups without a profile have no owner to check.
+    async fn add_group(&self, clt: ClientID, hs: &HashSet<u64>) -> Result<(), BtcmDataError>;
+    /// Removes user ids from the group, together with their admin role. Refuses to remove
+    /// the group owner with `BtcmDataError::Conflict`; transfer the ownership first.
+    async fn del_group(&self, clt: ClientID, hs: &HashSet<u64>) -> Result<(), BtcmDataError>;
+    /// Creates the group with its profile and members, the owner joins the members.
+    /// `BtcmDataError::Conflict` if the group profile already exists, `BtcmDataError::Blocked`
+    /// if any member has blocked the owner.
+    async fn create_group(&self, clt: ClientID, info: &GroupInfo, hs: &HashSet<u64>) -> Result<(), BtcmDataError>;
+    /// Returns the group profile, `None` if the group has none.
+    async fn get_group_info(&self, clt: ClientID) -> Result<Option<GroupInfo>, BtcmDataError>;
+    /// Hands the group to another member, who stops being an admin; the old owner becomes
+    /// an ordinary member. `BtcmDataError::NotFound` if the group has no profile or
+    /// `new_owner` is not a member.
+    async fn transfer_owner(&self, clt: ClientID, new_owner: ClientID) -> Result<(), BtcmDataError>;
+    /// Makes a member an admin. `BtcmDataError::NotFound` if `member` is not in the group,
+    /// `BtcmDataError::Conflict` if `member` is the owner.
+    async fn promote_admin(&self, clt: ClientID, member: ClientID) -> Result<(), BtcmDataError>;
+    /// Takes the admin role away from a member, who stays in the group; returns whether
+    /// the member was an admin.
+    async fn demote_admin(&self, clt: ClientID, member: ClientID) -> Result<bool, BtcmDataError>;
+    /// Returns the member's role, `None` if `member` is not in the group.
+    async fn get_member_role(&self, clt: ClientID, member: ClientID) -> Result<Option<GroupRole>, BtcmDataError>;
+    /// Returns the admins of the group, not including the owner.
+    async fn get_group_admins(&self, clt: ClientID) -> Result<HashSet<u64>, BtcmDataError>;
+    /// Returns the members of the group.
+    async fn get_group(&self, clt: ClientID) -> Result<HashSet<u64>, BtcmDataError>;
+    /// Returns whether the group exists.
+    async fn exists_group(&self, clt: ClientID) -> Result<bool, BtcmDataError>;
+    /// Removes the group, returns whether it existed.
+    async fn remove_group(&self, clt: ClientID) -> Result<bool, BtcmDataError>;
+    /// Returns the groups the user is a member of, kept in step with the membership
+    /// sets (unlike the user's own `conts_group:` contact set).
+    async fn groups_of_user(&self, clt: ClientID) -> Result<HashSet<u64>, BtcmDataError>;
+    /// Returns the groups both users are members of.
+    async fn common_groups(&self, a: ClientID, b: ClientID) -> Result<HashSet<u64>, BtcmDataError>;
+}
+
+/// # Device Store
+///
+/// Storage of a client's device set and of the per-device information (`Device`).
+#[async_trait]
+pub trait DeviceStore: Send + Sync {
+    /// Adds device ids to the client's device set.
+    async fn add_dev2clt(&self, clt: ClientID, devs: &HashSet<u64>) -> Result<(), BtcmDataError>;
+    /// Removes device ids from the client's device set.
+    async fn del_dev4clt(&self, clt: ClientID, devs: &HashSet<u64>) -> Result<(), BtcmDataError>;
+    /// Returns the client's device set.
+    async fn get_devclt_set(&self, clt: ClientID) -> Result<HashSet<u64>, BtcmDataError>;
+    /// Returns whether the client's device set exists.
+    async fn exists_devclt(&self, clt: ClientID) -> Result<bool, BtcmDataError>;
+    /// Removes the client's device set, returns whether it existed.
+    async fn remove_devclt_set(&self, clt: ClientID) -> Result<bool, BtcmDataError>;
+    /// Writes the device's information, replacing the stored one.
+    async fn add_dev2clt_hash(&self, clt: ClientID, dev: u32, device: &Device) -> Result<(), BtcmDataError>;
+    /// Returns the device's information, `None` if it does not exist.
+    async fn get_device(&self, clt: ClientID, dev: u32) -> Result<Option<Device>, BtcmDataError>;
+    /// Returns only the given fields of the device's information; missing fields are left out.
+    async fn get_device_fields(&self, clt: ClientID, dev: u32, fields: &[&str]) -> Result<HashMap<String, String>, BtcmDataError>;
+    /// Writes only the given fields, leaving the others untouched. `BtcmDataError::NotFound`
+    /// if the device does not exist, `BtcmDataError::TypeMismatch` if a value does not fit `Device`.
+    async fn set_device_fields(&self, clt: ClientID, dev: u32, fields: &HashMap<String, String>) -> Result<(), BtcmDataError>;
+    /// Deletes the given fields, returns how many existed.
+    async fn delete_device_fields(&self, clt: ClientID, dev: u32, fields: &[&str]) -> Result<usize, BtcmDataError>;
+    /// Returns whether the device's information hash exists.
+    async fn exists_device(&self, clt: ClientID, dev: u32) -> Result<bool, BtcmDataError>;
+    /// Removes the device's information hash, returns whether it existed.
+    async fn remove_device(&self, clt: ClientID, dev: u32) -> Result<bool, BtcmDataError>;
+}
+
+/// # Account Store
+///
+/// Operations on a whole account, spanning the other stores.
+#[async_trait]
+pub trait AccountStore: Send + Sync {
+    /// Deletes the account: soft deletes the profile with `reason` (see
+    /// `UserStore::remove_user`) and removes the user's contacts, blocklist, group
+    /// memberships, devices and friend requests, including the user's entry in the other
+    /// users' contact sets. Owned groups are handed to an admin, else to another member,
+    /// and removed if no member is left. Deleting an already deleted account again
+    /// cleans up whatever is left; `BtcmDataError::NotFound` if the user never existed.
+    /// `BtcmDataError::TypeMismatch`, before anything is deleted, if a stored device id
+    /// does not fit in `u32`.
+    async fn delete_account(&self, clt: ClientID, reason: Option<&str>) -> Result<AccountDeletionReport, BtcmDataError>;
+}